@@ -3,7 +3,7 @@ pub mod runtime_config;
 mod util;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     sync::Arc,
 };
 
@@ -145,6 +145,15 @@ impl AppState {
     pub async fn get_store(&self, label: &str) -> Option<Arc<dyn Store>> {
         self.store_manager.get(label).await.ok()
     }
+
+    /// Returns the labels of the stores used by any component.
+    pub fn used_stores(&self) -> BTreeSet<&str> {
+        self.component_allowed_stores
+            .values()
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
 }
 
 /// `SwapError` are errors that occur during compare and swap operations
@@ -1,7 +1,7 @@
 mod host;
 pub mod runtime_config;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
 use host::InstanceState;
@@ -169,6 +169,15 @@ impl AppState {
             .values()
             .any(|stores| stores.contains(label))
     }
+
+    /// Returns the labels of the databases used by any component.
+    pub fn used_databases(&self) -> BTreeSet<&str> {
+        self.allowed_databases
+            .values()
+            .flat_map(|databases| databases.iter())
+            .map(String::as_str)
+            .collect()
+    }
 }
 
 /// A creator of a connections for a particular SQLite database.
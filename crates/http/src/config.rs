@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 use spin_http_routes::HttpTriggerRouteConfig;
 
@@ -9,26 +11,82 @@ pub struct HttpTriggerConfig {
     pub component: Option<String>,
     /// Static response to send
     pub static_response: Option<StaticResponse>,
+    /// Directory of static files to serve
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub static_files: Option<StaticFilesConfig>,
+    /// Upstream server to forward requests to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
     /// HTTP route the component will be invoked for
     pub route: HttpTriggerRouteConfig,
+    /// Host the route is restricted to, either exact (`api.example.com`) or a
+    /// wildcard (`*.example.com`). If not set, the route matches any host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// HTTP methods the route is restricted to. If empty, the route matches any method.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<String>,
+    /// Request headers the route is restricted to, with the value they must have.
+    /// A value of `*` requires only that the header is present.
+    #[serde(default, skip_serializing_if = "indexmap::IndexMap::is_empty")]
+    pub headers: indexmap::IndexMap<String, String>,
     /// The HTTP executor the component requires
     #[serde(default)]
     pub executor: Option<HttpExecutorType>,
+    /// Compression of the component's responses. Overrides the
+    /// application-wide setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionConfig>,
+    /// The CORS policy for the route. Overrides the application-wide policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsConfig>,
+    /// A shared cache of the component's responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheConfig>,
+    /// Whether instances of the component may be reused to handle further
+    /// requests, one at a time. This only affects `wasi:http@0.2` components;
+    /// WASIp3 components are always reused.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub instance_reuse: bool,
+    /// The share of the route's requests this trigger handles, relative to
+    /// other triggers with the same route and conditions which set a weight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// How a client's requests keep going to the same trigger when requests
+    /// to the route are split by weight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<StickyConfig>,
+    /// Whether the component is sent a copy of each request to the trigger
+    /// with the same route and conditions, with its response discarded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shadow: bool,
+    /// A limit on the requests the trigger handles at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ConcurrencyConfig>,
 }
 
 impl HttpTriggerConfig {
     pub fn lookup_key(&self, trigger_id: &str) -> anyhow::Result<crate::routes::TriggerLookupKey> {
-        match (&self.component, &self.static_response) {
-            (None, None) => Err(anyhow::anyhow!(
-                "Triggers must specify either component or static_response - {trigger_id} has neither"
-            )),
-            (Some(_), Some(_)) => Err(anyhow::anyhow!(
-                "Triggers must specify either component or static_response - {trigger_id} has both"
+        let handler_count = [
+            self.component.is_some(),
+            self.static_response.is_some(),
+            self.static_files.is_some(),
+            self.proxy.is_some(),
+        ]
+        .into_iter()
+        .filter(|set| *set)
+        .count();
+        match (handler_count, &self.component) {
+            (0, _) => Err(anyhow::anyhow!(
+                "Triggers must specify one of component, static_response, static_files or proxy - {trigger_id} has none"
             )),
-            (Some(c), None) => Ok(crate::routes::TriggerLookupKey::Component(c.to_string())),
-            (None, Some(_)) => Ok(crate::routes::TriggerLookupKey::Trigger(
+            (1, Some(c)) => Ok(crate::routes::TriggerLookupKey::Component(c.to_string())),
+            (1, None) => Ok(crate::routes::TriggerLookupKey::Trigger(
                 trigger_id.to_string(),
             )),
+            _ => Err(anyhow::anyhow!(
+                "Triggers must specify only one of component, static_response, static_files or proxy - {trigger_id} has more than one"
+            )),
         }
     }
 }
@@ -51,6 +109,17 @@ pub enum HttpExecutorType {
     Wagi(WagiTriggerConfig),
 }
 
+/// How requests to a split route are assigned to the same trigger.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum StickyConfig {
+    /// By a cookie naming the trigger's component, which is set on responses
+    /// to requests without it.
+    Cookie(String),
+    /// By the value of a request header, such as a user ID.
+    Header(String),
+}
+
 /// Wagi specific configuration for the http executor.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
@@ -110,6 +179,230 @@ impl StaticResponse {
     }
 }
 
+/// A directory of files to be served directly by the host
+/// without instantiating a component.
+///
+/// The files are read from the host rather than packaged with the app, so
+/// they are only served for apps run from a local manifest.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticFilesConfig {
+    /// The directory to serve files from, relative to the directory
+    /// containing the application manifest.
+    pub directory: PathBuf,
+    /// The files to serve for requests for a directory, in order of preference.
+    #[serde(default = "StaticFilesConfig::default_index_files")]
+    pub index_files: Vec<String>,
+}
+
+impl StaticFilesConfig {
+    fn default_index_files() -> Vec<String> {
+        vec!["index.html".to_owned()]
+    }
+}
+
+/// An upstream HTTP server which requests are forwarded to
+/// without instantiating a component.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// The URL of the upstream server. The request path is appended to
+    /// the URL's path.
+    pub upstream: String,
+    /// Whether to remove the part of the path matched by the route before
+    /// forwarding, so that only the part matched by a trailing wildcard is sent.
+    #[serde(default)]
+    pub strip_prefix: bool,
+}
+
+/// Compression of component responses by the host, negotiated
+/// from the request's `Accept-Encoding` header.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Whether responses are compressed.
+    #[serde(default = "CompressionConfig::default_enabled")]
+    pub enabled: bool,
+    /// The algorithms which may be used, in order of preference.
+    #[serde(default = "CompressionConfig::default_algorithms")]
+    pub algorithms: Vec<CompressionAlgorithm>,
+    /// Responses smaller than this many bytes are not compressed.
+    #[serde(default = "CompressionConfig::default_min_size")]
+    pub min_size: u64,
+    /// The content types which are compressed. An entry may be a
+    /// wildcard for all subtypes, such as `text/*`.
+    #[serde(default = "CompressionConfig::default_content_types")]
+    pub content_types: Vec<String>,
+}
+
+impl CompressionConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_algorithms() -> Vec<CompressionAlgorithm> {
+        vec![
+            CompressionAlgorithm::Brotli,
+            CompressionAlgorithm::Zstd,
+            CompressionAlgorithm::Gzip,
+        ]
+    }
+
+    fn default_min_size() -> u64 {
+        1024
+    }
+
+    fn default_content_types() -> Vec<String> {
+        [
+            "text/*",
+            "application/javascript",
+            "application/json",
+            "application/wasm",
+            "application/xml",
+            "image/svg+xml",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            algorithms: Self::default_algorithms(),
+            min_size: Self::default_min_size(),
+            content_types: Self::default_content_types(),
+        }
+    }
+}
+
+/// A compression algorithm, named by its HTTP content coding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CompressionAlgorithm {
+    /// Brotli (`br`)
+    #[serde(rename = "br")]
+    Brotli,
+    /// Gzip (`gzip`)
+    #[serde(rename = "gzip")]
+    Gzip,
+    /// Zstandard (`zstd`)
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    /// The HTTP content coding for the algorithm.
+    pub fn content_coding(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// A CORS policy, applied by the host to a route's responses.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// The origins allowed to make requests, such as `https://example.com`.
+    /// An entry may be `*` to allow any origin, or may use a wildcard for
+    /// subdomains, such as `https://*.example.com`.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// The methods allowed in requests. `*` allows any method.
+    #[serde(default = "CorsConfig::default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// The headers allowed in requests. `*` allows any header.
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// The response headers which browsers expose to scripts.
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    /// Whether requests may include credentials such as cookies.
+    #[serde(default)]
+    pub allow_credentials: bool,
+    /// How long, in seconds, browsers may cache preflight responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    fn default_allowed_methods() -> Vec<String> {
+        ["GET", "HEAD", "POST"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// A shared cache of component responses, kept by the host. Responses are
+/// cached as directed by their `Cache-Control`, `Expires`, `Vary` and `ETag`
+/// headers.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Where cached responses are stored.
+    #[serde(default)]
+    pub backend: CacheBackend,
+    /// The maximum total size, in bytes, of cached response bodies. The least
+    /// recently used responses are evicted to stay within it.
+    #[serde(default = "CacheConfig::default_max_size")]
+    pub max_size: u64,
+}
+
+impl CacheConfig {
+    fn default_max_size() -> u64 {
+        64 * 1024 * 1024
+    }
+}
+
+/// A limit on the requests a trigger handles at once. Requests beyond the
+/// limit wait for others to finish, and are rejected with a 503 Service
+/// Unavailable response if they wait too long.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// The most requests handled at once.
+    pub max: usize,
+    /// How long, in milliseconds, a request may wait to be handled. By
+    /// default, requests beyond the limit are rejected immediately.
+    #[serde(default)]
+    pub queue_timeout_ms: u64,
+}
+
+/// A page served in place of the body of an error response made by the
+/// host, such as the 404 response to a request which matches no route.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum ErrorPageConfig {
+    /// A file, relative to the directory containing the app manifest. It is
+    /// read from the host rather than packaged with the app, so only apps run
+    /// from a local manifest can use it.
+    File(PathBuf),
+    /// A component, which is sent a `GET` request for the URL of the failed
+    /// request, with the status in the `spin-error-status` header. The
+    /// component must be used by an HTTP trigger, which may have a private route.
+    Component(String),
+    /// A message, served as HTML, JSON or plain text as the client accepts.
+    /// `{status}`, `{reason}` and `{path}` are replaced with the response
+    /// status code, its reason phrase and the request path.
+    Template(String),
+}
+
+/// Where cached responses are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    /// In memory. The cache is emptied when the app stops.
+    #[default]
+    Memory,
+    /// On disk, in the app's state directory. The cache persists across restarts.
+    Disk,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -123,4 +416,164 @@ mod tests {
         assert_eq!(config.entrypoint, "_start");
         assert_eq!(config.argv, "${SCRIPT_NAME} ${ARGS}");
     }
+
+    #[test]
+    fn static_files_config_defaults_index_files() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/assets/..."
+            static_files = { directory = "dist" }
+        }
+        .try_into()
+        .unwrap();
+        let static_files = config.static_files.as_ref().unwrap();
+        assert_eq!(static_files.directory, PathBuf::from("dist"));
+        assert_eq!(static_files.index_files, ["index.html"]);
+        assert!(matches!(
+            config.lookup_key("assets").unwrap(),
+            crate::routes::TriggerLookupKey::Trigger(id) if id == "assets"
+        ));
+    }
+
+    #[test]
+    fn proxy_config_is_exclusive_with_component() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/legacy/..."
+            proxy = { upstream = "http://localhost:8080", strip_prefix = true }
+        }
+        .try_into()
+        .unwrap();
+        let proxy = config.proxy.as_ref().unwrap();
+        assert_eq!(proxy.upstream, "http://localhost:8080");
+        assert!(proxy.strip_prefix);
+        assert!(matches!(
+            config.lookup_key("legacy").unwrap(),
+            crate::routes::TriggerLookupKey::Trigger(id) if id == "legacy"
+        ));
+
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/legacy/..."
+            component = "legacy"
+            proxy = { upstream = "http://localhost:8080" }
+        }
+        .try_into()
+        .unwrap();
+        assert!(config.lookup_key("legacy").is_err());
+    }
+
+    #[test]
+    fn compression_config_defaults() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/..."
+            component = "app"
+            compression = { algorithms = ["gzip"] }
+        }
+        .try_into()
+        .unwrap();
+        let compression = config.compression.unwrap();
+        assert!(compression.enabled);
+        assert_eq!(compression.algorithms, [CompressionAlgorithm::Gzip]);
+        assert_eq!(compression.min_size, 1024);
+        assert!(compression.content_types.iter().any(|t| t == "text/*"));
+    }
+
+    #[test]
+    fn cors_config_defaults() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/api/..."
+            component = "api"
+            cors = { allowed_origins = ["https://example.com"] }
+        }
+        .try_into()
+        .unwrap();
+        let cors = config.cors.unwrap();
+        assert_eq!(cors.allowed_origins, ["https://example.com"]);
+        assert_eq!(cors.allowed_methods, ["GET", "HEAD", "POST"]);
+        assert!(cors.allowed_headers.is_empty());
+        assert!(!cors.allow_credentials);
+        assert_eq!(cors.max_age, None);
+    }
+
+    #[test]
+    fn cache_config_defaults() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/..."
+            component = "api"
+            cache = {}
+        }
+        .try_into()
+        .unwrap();
+        assert!(!config.instance_reuse);
+        assert!(config.weight.is_none() && !config.shadow);
+        assert!(config.concurrency.is_none());
+        let cache = config.cache.unwrap();
+        assert_eq!(cache.backend, CacheBackend::Memory);
+        assert_eq!(cache.max_size, 64 * 1024 * 1024);
+
+        let cache: CacheConfig = toml::toml! {
+            backend = "disk"
+            max_size = 1024
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(cache.backend, CacheBackend::Disk);
+        assert_eq!(cache.max_size, 1024);
+    }
+
+    #[test]
+    fn sticky_config() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/cart/..."
+            component = "cart-v2"
+            weight = 10
+            sticky = { cookie = "cart-version" }
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.weight, Some(10));
+        assert_eq!(
+            config.sticky,
+            Some(StickyConfig::Cookie("cart-version".into()))
+        );
+
+        let sticky: StickyConfig = toml::toml! { header = "x-user-id" }.try_into().unwrap();
+        assert_eq!(sticky, StickyConfig::Header("x-user-id".into()));
+    }
+
+    #[test]
+    fn error_page_config() {
+        let pages: std::collections::HashMap<String, ErrorPageConfig> = toml::from_str(
+            r#"
+            404 = { file = "errors/404.html" }
+            5xx = { component = "errors" }
+            504 = { template = "{path} took too long" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            pages["404"],
+            ErrorPageConfig::File(PathBuf::from("errors/404.html"))
+        );
+        assert_eq!(pages["5xx"], ErrorPageConfig::Component("errors".into()));
+        assert_eq!(
+            pages["504"],
+            ErrorPageConfig::Template("{path} took too long".into())
+        );
+    }
+
+    #[test]
+    fn concurrency_config() {
+        let config: HttpTriggerConfig = toml::toml! {
+            route = "/reports/..."
+            component = "reports"
+            concurrency = { max = 4, queue_timeout_ms = 500 }
+        }
+        .try_into()
+        .unwrap();
+        let concurrency = config.concurrency.unwrap();
+        assert_eq!(concurrency.max, 4);
+        assert_eq!(concurrency.queue_timeout_ms, 500);
+
+        let concurrency: ConcurrencyConfig = toml::toml! { max = 1 }.try_into().unwrap();
+        assert_eq!(concurrency.queue_timeout_ms, 0);
+    }
 }
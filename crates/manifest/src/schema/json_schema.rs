@@ -15,6 +15,17 @@ pub struct TriggerSchema {
     /// Redis triggers
     #[schemars(default)]
     redis: Vec<RedisTriggerSchema>,
+    /// MQTT subscription triggers
+    #[schemars(default)]
+    #[serde(rename = "mqtt-subscription")]
+    mqtt_subscription: Vec<MqttTriggerSchema>,
+    /// Timer triggers
+    #[schemars(default)]
+    timer: Vec<TimerTriggerSchema>,
+    /// Command triggers
+    #[schemars(default)]
+    #[serde(rename = "cli-command")]
+    cli_command: Vec<CommandTriggerSchema>,
 }
 
 #[allow(dead_code)]
@@ -35,9 +46,82 @@ pub struct HttpTriggerSchema {
     pub dependencies: Map<String, TriggerDependencies>,
     /// `route = "/user/:name/..."`
     route: HttpRouteSchema,
+    /// `host = "api.example.com"` or `host = "*.example.com"`
+    ///
+    /// Restricts the route to requests for the given host. If omitted, the route matches any host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    /// `methods = ["GET", "POST"]`
+    ///
+    /// Restricts the route to requests using one of the given methods. `GET` implies `HEAD`.
+    /// Requests using other methods fall through to less specific routes, and receive a
+    /// 405 Method Not Allowed response if no route accepts them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    methods: Vec<String>,
+    /// `headers = { "x-api-version" = "2" }`
+    ///
+    /// Restricts the route to requests with the given header values. A value of `"*"`
+    /// requires only that the header is present.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    headers: Map<String, String>,
     /// `executor = { type = "wagi" }
     #[schemars(default, schema_with = "toml_table")]
     executor: Option<toml::Table>,
+    /// `static_files = { directory = "public", index_files = ["index.html"] }`
+    ///
+    /// Serves files from a directory, relative to the manifest, instead of invoking a component.
+    /// The files are not packaged with the app, so this only works for apps run from a local
+    /// manifest, not ones pushed to a registry.
+    #[schemars(default, schema_with = "toml_table")]
+    static_files: Option<toml::Table>,
+    /// `proxy = { upstream = "http://localhost:8080", strip_prefix = true }`
+    ///
+    /// Forwards requests to an upstream server instead of invoking a component.
+    #[schemars(default, schema_with = "toml_table")]
+    proxy: Option<toml::Table>,
+    /// `compression = { algorithms = ["br", "gzip"], min_size = 1024 }`
+    ///
+    /// Compression of the component's responses. Overrides the application-wide setting.
+    #[schemars(default, schema_with = "toml_table")]
+    compression: Option<toml::Table>,
+    /// `cors = { allowed_origins = ["https://example.com"] }`
+    ///
+    /// The CORS policy for the route. Overrides the application-wide policy.
+    #[schemars(default, schema_with = "toml_table")]
+    cors: Option<toml::Table>,
+    /// `cache = { backend = "disk", max_size = 67108864 }`
+    ///
+    /// A shared cache of the component's responses, following their `Cache-Control` headers.
+    #[schemars(default, schema_with = "toml_table")]
+    cache: Option<toml::Table>,
+    /// `instance_reuse = true`
+    ///
+    /// Whether instances of a `wasi:http@0.2` component may be reused to handle further
+    /// requests, one at a time.
+    #[serde(default)]
+    instance_reuse: bool,
+    /// `weight = 90`
+    ///
+    /// The share of the route's requests this trigger handles, relative to other triggers
+    /// with the same route and conditions which set a weight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    weight: Option<u32>,
+    /// `sticky = { cookie = "variant" }` or `sticky = { header = "x-user-id" }`
+    ///
+    /// How a client's requests keep going to the same trigger when the route is split by weight.
+    #[schemars(default, schema_with = "toml_table")]
+    sticky: Option<toml::Table>,
+    /// `shadow = true`
+    ///
+    /// Sends the component a copy of each request to the trigger with the same route and
+    /// conditions, discarding its response.
+    #[serde(default)]
+    shadow: bool,
+    /// `concurrency = { max = 10, queue_timeout_ms = 500 }`
+    ///
+    /// A limit on the requests the trigger handles at once.
+    #[schemars(default, schema_with = "toml_table")]
+    concurrency: Option<toml::Table>,
 }
 
 #[allow(dead_code)]
@@ -88,12 +172,132 @@ pub struct RedisTriggerSchema {
     #[serde(default, skip_serializing_if = "Map::is_empty")]
     pub components: Map<String, OneOrManyComponentSpecs>,
     /// `channel = "my-messages"`
-    channel: String,
+    ///
+    /// The handler is given the channel in the `SPIN_REDIS_CHANNEL` environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channel: Option<String>,
+    /// `pattern = "orders.*"`
+    ///
+    /// A glob-style pattern of channels to subscribe to. The handler is given the channel
+    /// a message was published to in the `SPIN_REDIS_CHANNEL` environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    /// `stream = "orders"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream: Option<String>,
+    /// `group = "order-processors"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// `consumer = "{{ instance_name }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    consumer: Option<String>,
+    /// `dead_letter_stream = "orders:dead"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dead_letter_stream: Option<String>,
+    /// `max_failures = 3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_failures: Option<u32>,
+    /// `claim_idle_ms = 30000`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    claim_idle_ms: Option<u64>,
     /// `address = "redis://redis.example.com:6379"`
     #[serde(default, skip_serializing_if = "Option::is_none")]
     address: Option<String>,
 }
 
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct MqttTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+    /// `topics = ["sensors/+/temperature", "alerts/#"]`
+    topics: Vec<String>,
+    /// `qos = 1`
+    ///
+    /// The QoS level to subscribe with: 0 (at most once, the default), 1 (at least once)
+    /// or 2 (exactly once).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    qos: Option<u8>,
+    /// `client_id = "ingest-{{ instance_name }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    /// `username = "{{ mqtt_username }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    /// `password = "{{ mqtt_password }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    /// `clean_session = false`
+    ///
+    /// Whether the broker discards the session when the connection closes. Defaults to true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clean_session: Option<bool>,
+    /// `address = "mqtt://mqtt.example.com:1883"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct TimerTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+    /// `cron = "0 3 * * *"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cron: Option<String>,
+    /// `interval = "15m"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interval: Option<String>,
+    /// `time_zone = "Europe/London"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time_zone: Option<String>,
+    /// `overlap = "skip"`
+    ///
+    /// What to do when a run is due while the previous one is still running:
+    /// `skip` it (the default), `queue` it, or `allow` it to run alongside.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overlap: Option<String>,
+    /// `jitter = "30s"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jitter: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CommandTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+    /// `args = ["--since", "2024-01-01"]`
+    ///
+    /// The arguments to pass to the command, unless others are given on the command line.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+}
+
 /// The SQLite databases which the component is allowed to access. Databases are identified
 /// by label e.g. "default" or "analytics". Databases other than "default" must be mapped
 /// to a backing store in the runtime config. Use "spin up --sqlite" to run database setup scripts.
@@ -130,8 +130,15 @@ pub struct AppDetails {
 /// The trigger manifest contains additional fields which depend on the trigger
 /// type. For the `http` type, these additional fields are `route` (required) and
 /// `executor` (optional). For the `redis` type, the additional fields are
-/// `channel` (required) and `address` (optional). For other types, see the trigger
-/// documentation.
+/// `channel`, `pattern` or `stream` (one is required), `group` (required with
+/// `stream`), and `address` (optional); handlers for a `channel` or `pattern`
+/// are given the channel in the `SPIN_REDIS_CHANNEL` environment variable. For
+/// the `mqtt-subscription` type, the additional fields are `topics` (required),
+/// `qos`, `client_id`, `username`, `password`, `clean_session` and `address`
+/// (optional). For the `timer` type, the additional fields are `cron` or
+/// `interval` (one is required), `time_zone`, `overlap` and `jitter`
+/// (optional). For the `cli-command` type, the additional field is `args`
+/// (optional). For other types, see the trigger documentation.
 ///
 /// Learn more: https://spinframework.dev/http-trigger, https://spinframework.dev/redis-trigger
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -961,7 +961,9 @@ async fn precompose_using_trigger(
     };
 
     let resolver_subcmd = match resolve_extras_using {
-        "http" | "redis" => vec!["trigger".into(), resolve_extras_using.into()],
+        "http" | "redis" | "mqtt-subscription" | "timer" | "cli-command" => {
+            vec!["trigger".into(), resolve_extras_using.into()]
+        }
         _ => vec![format!("trigger-{resolve_extras_using}")],
     };
 
@@ -15,7 +15,11 @@ pub const WELL_KNOWN_PREFIX: &str = "/.well-known/spin/";
 pub struct Router {
     /// Resolves paths to routing information - specifically component IDs
     /// but also recording about the original route.
-    router: std::sync::Arc<routefinder::Router<RouteHandler>>,
+    ///
+    /// Routes are grouped by the host pattern they are restricted to; routes
+    /// which accept any host are under `None`. Each path may map to several
+    /// handlers, which are distinguished by their request conditions.
+    routers: std::sync::Arc<IndexMap<Option<HostPattern>, routefinder::Router<Vec<RouteHandler>>>>,
 }
 
 /// What a route maps to
@@ -23,6 +27,10 @@ pub struct Router {
 struct RouteHandler {
     /// The handler identifier (typically component ID) that the route maps to.
     lookup_key: TriggerLookupKey,
+    /// The host pattern that requests must match, if any.
+    host: Option<HostPattern>,
+    /// Method and header conditions that requests must match.
+    conditions: RouteConditions,
     /// The route, including any application base.
     based_route: Cow<'static, str>,
     /// The route, not including any application base.
@@ -30,6 +38,11 @@ struct RouteHandler {
     /// The route, including any application base and capturing information about whether it has a trailing wildcard.
     /// (This avoids re-parsing the route string.)
     parsed_based_route: ParsedRoute,
+    /// If requests to the route are split by weight, the handlers they are
+    /// split between (starting with this one) and their weights.
+    split: Vec<(RouteHandler, u32)>,
+    /// The handler which is sent a copy of requests to the route, if any.
+    shadow: Option<Box<RouteHandler>>,
 }
 
 /// An identifier that can be returned from a RouteMatch and used to look up the trigger
@@ -51,11 +64,113 @@ impl std::fmt::Display for TriggerLookupKey {
     }
 }
 
+/// A route to be added to a [`Router`], together with any conditions other
+/// than the path that a request must meet to match it.
+#[derive(Clone, Copy, Debug)]
+pub struct RouteDefinition<'a> {
+    /// The handler that the route maps to.
+    pub lookup_key: &'a TriggerLookupKey,
+    /// The route pattern.
+    pub route: &'a HttpTriggerRouteConfig,
+    /// The host that requests must be addressed to, either exactly (`api.example.com`)
+    /// or as a wildcard (`*.example.com`). If `None`, requests to any host match.
+    pub host: Option<&'a str>,
+    /// The methods that requests must use. If empty, requests with any method match.
+    pub methods: &'a [String],
+    /// Headers that requests must have, with the value they must have. A value of
+    /// `*` matches any value. If `None`, requests match regardless of headers.
+    pub headers: Option<&'a IndexMap<String, String>>,
+    /// The share of requests the handler receives, relative to other weighted
+    /// routes with the same path and conditions. If `None`, the route replaces
+    /// any other route with the same path and conditions.
+    pub weight: Option<u32>,
+    /// Whether the handler is sent a copy of requests to another route with the
+    /// same path and conditions, rather than handling them itself.
+    pub shadow: bool,
+}
+
+impl<'a> RouteDefinition<'a> {
+    /// A route which matches requests to any host.
+    pub fn new(lookup_key: &'a TriggerLookupKey, route: &'a HttpTriggerRouteConfig) -> Self {
+        Self {
+            lookup_key,
+            route,
+            host: None,
+            methods: &[],
+            headers: None,
+            weight: None,
+            shadow: false,
+        }
+    }
+
+    /// Restricts the route to requests for the given host pattern.
+    pub fn with_host(self, host: Option<&'a str>) -> Self {
+        Self { host, ..self }
+    }
+
+    /// Restricts the route to requests using one of the given methods.
+    pub fn with_methods(self, methods: &'a [String]) -> Self {
+        Self { methods, ..self }
+    }
+
+    /// Restricts the route to requests with the given header values.
+    pub fn with_headers(self, headers: &'a IndexMap<String, String>) -> Self {
+        Self {
+            headers: Some(headers),
+            ..self
+        }
+    }
+
+    /// Splits requests between this and other weighted routes with the same path
+    /// and conditions.
+    pub fn with_weight(self, weight: Option<u32>) -> Self {
+        Self { weight, ..self }
+    }
+
+    /// Sends the handler a copy of requests to the route with the same path and
+    /// conditions, instead of routing requests to it.
+    pub fn with_shadow(self, shadow: bool) -> Self {
+        Self { shadow, ..self }
+    }
+}
+
+/// The error returned by [`Router::route_request`] when routes exist for the
+/// request path, but none of them accept the request method.
+#[derive(Debug)]
+pub struct MethodNotAllowed {
+    allowed: Vec<http::Method>,
+}
+
+impl MethodNotAllowed {
+    /// The methods accepted by routes for the path, formatted for an `Allow` header.
+    pub fn allow_header_value(&self) -> String {
+        self.allowed
+            .iter()
+            .map(http::Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl fmt::Display for MethodNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Method not allowed; routes for this path accept {}",
+            self.allow_header_value()
+        )
+    }
+}
+
+impl std::error::Error for MethodNotAllowed {}
+
 /// A detected duplicate route.
 #[derive(Debug)] // Needed to call `expect_err` on `Router::build`
 pub struct DuplicateRoute {
     /// The duplicated route pattern.
     route: String,
+    /// The host pattern of the duplicated route, if any.
+    host: Option<String>,
     /// The raw route that was duplicated.
     pub replaced_id: String,
     /// The component ID corresponding to the duplicated route.
@@ -70,6 +185,30 @@ impl Router {
     pub fn build<'a>(
         base: &str,
         trigger_routes: impl IntoIterator<Item = (&'a TriggerLookupKey, &'a HttpTriggerRouteConfig)>,
+        duplicate_routes: Option<&mut Vec<DuplicateRoute>>,
+    ) -> Result<Self> {
+        let definitions = trigger_routes
+            .into_iter()
+            .map(|(lookup_key, route)| RouteDefinition::new(lookup_key, route));
+        Self::build_definitions(base, definitions, duplicate_routes)
+    }
+
+    /// Builds a router from route definitions, which may carry conditions such
+    /// as a host pattern in addition to the route.
+    ///
+    /// `duplicate_routes` behaves as for [`Router::build`]. Routes are only
+    /// duplicates if they also have the same host pattern and request conditions.
+    ///
+    /// Where several routes have the same path but different conditions, routes
+    /// with conditions are tried before unconditional ones, and otherwise routes
+    /// are tried in the order they are defined.
+    ///
+    /// Routes with the same path and conditions are not duplicates if they all
+    /// have weights: requests are then split between them. A shadow route is
+    /// attached to the route with the same path and conditions.
+    pub fn build_definitions<'a>(
+        base: &str,
+        definitions: impl IntoIterator<Item = RouteDefinition<'a>>,
         mut duplicate_routes: Option<&mut Vec<DuplicateRoute>>,
     ) -> Result<Self> {
         // Some information we need to carry between stages of the builder.
@@ -77,18 +216,38 @@ impl Router {
             based_route: String,
             raw_route: &'a str,
             lookup_key: &'a TriggerLookupKey,
+            host: Option<HostPattern>,
+            conditions: RouteConditions,
+            weight: Option<u32>,
+            shadow: bool,
+            /// The other weighted routes requests are split with.
+            split: Vec<(&'a TriggerLookupKey, u32)>,
+            shadowed_by: Option<&'a TriggerLookupKey>,
         }
 
-        let mut routes: IndexMap<&str, RoutingEntry> = IndexMap::new();
+        type RoutingKey<'a> = (Option<HostPattern>, &'a str, RouteConditions);
+        let mut routes: IndexMap<RoutingKey, RoutingEntry> = IndexMap::new();
+        let mut shadows = Vec::new();
 
         // Filter out private endpoints and capture the routes.
-        let routes_iter = trigger_routes
+        let routes_iter = definitions
             .into_iter()
-            .filter_map(|(lookup_key, route)| {
+            .filter_map(|RouteDefinition { lookup_key, route, host, methods, headers, weight, shadow }| {
                 match route {
                     HttpTriggerRouteConfig::Route(raw_route) => {
                         let based_route = sanitize_with_base(base, raw_route);
-                        Some(Ok(RoutingEntry { based_route, raw_route, lookup_key }))
+                        let host = match host.map(HostPattern::parse).transpose() {
+                            Ok(host) => host,
+                            Err(e) => return Some(Err(anyhow!("Invalid host for component {lookup_key}: {e}"))),
+                        };
+                        let conditions = match RouteConditions::parse(methods, headers) {
+                            Ok(conditions) => conditions,
+                            Err(e) => return Some(Err(anyhow!("Invalid route conditions for component {lookup_key}: {e}"))),
+                        };
+                        if shadow && weight.is_some() {
+                            return Some(Err(anyhow!("Shadow route for component {lookup_key} cannot have a weight")));
+                        }
+                        Some(Ok(RoutingEntry { based_route, raw_route, lookup_key, host, conditions, weight, shadow, split: Vec::new(), shadowed_by: None }))
                     }
                     HttpTriggerRouteConfig::Private(endpoint) => if endpoint.private {
                         None
@@ -98,18 +257,30 @@ impl Router {
                 }
             });
 
-        // Remove duplicates.
+        // Remove duplicates, and combine weighted routes.
         for re in routes_iter {
             let re = re?;
-            if let Some(replaced) = routes.insert(re.raw_route, re) {
+            let key = (re.host.clone(), re.raw_route, re.conditions.clone());
+            if re.shadow {
+                shadows.push((key, re));
+                continue;
+            }
+            if let Some(existing) = routes.get_mut(&key)
+                && let (Some(_), Some(weight)) = (existing.weight, re.weight)
+            {
+                existing.split.push((re.lookup_key, weight));
+                continue;
+            }
+            if let Some(replaced) = routes.insert(key.clone(), re) {
                 if let Some(duplicate_routes) = &mut duplicate_routes {
                     let effective_id = routes
-                        .get(replaced.raw_route)
+                        .get(&key)
                         .unwrap() // Safe because we just inserted it
                         .lookup_key
                         .to_string();
                     duplicate_routes.push(DuplicateRoute {
                         route: replaced.based_route,
+                        host: replaced.host.map(|h| h.to_string()),
                         replaced_id: replaced.lookup_key.to_string(),
                         effective_id,
                     });
@@ -117,9 +288,27 @@ impl Router {
             }
         }
 
-        // Build a `routefinder` from the remaining routes.
+        // Attach shadows to the routes they copy requests from.
+        for (key, shadow) in shadows {
+            let Some(re) = routes.get_mut(&key) else {
+                return Err(anyhow!(
+                    "Shadow route {} for component {} has no route to copy requests from",
+                    shadow.based_route,
+                    shadow.lookup_key,
+                ));
+            };
+            if re.shadowed_by.replace(shadow.lookup_key).is_some() {
+                return Err(anyhow!(
+                    "Route {} has more than one shadow route",
+                    shadow.based_route
+                ));
+            }
+        }
+
+        // Group the remaining routes by host and path.
 
-        let mut rf = routefinder::Router::new();
+        let mut grouped: IndexMap<_, IndexMap<_, (routefinder::RouteSpec, Vec<RouteHandler>)>> =
+            IndexMap::new();
 
         for re in routes.into_values() {
             let (rfroute, parsed) = Self::parse_route(&re.based_route).map_err(|e| {
@@ -130,18 +319,59 @@ impl Router {
                 )
             })?;
 
-            let handler = RouteHandler {
-                lookup_key: re.lookup_key.clone(),
-                based_route: re.based_route.into(),
+            let handler_for = |lookup_key: &TriggerLookupKey| RouteHandler {
+                lookup_key: lookup_key.clone(),
+                host: re.host.clone(),
+                conditions: re.conditions.clone(),
+                based_route: re.based_route.clone().into(),
                 raw_route: re.raw_route.to_string().into(),
-                parsed_based_route: parsed,
+                parsed_based_route: parsed.clone(),
+                split: Vec::new(),
+                shadow: None,
             };
 
-            rf.add(rfroute, handler).map_err(|e| anyhow!("{e}"))?;
+            let mut handler = handler_for(re.lookup_key);
+            if let Some(weight) = re.weight
+                && !re.split.is_empty()
+            {
+                let split = std::iter::once((re.lookup_key, weight)).chain(re.split);
+                handler.split = split
+                    .map(|(lookup_key, weight)| (handler_for(lookup_key), weight))
+                    .collect();
+                if handler.split.iter().all(|(_, weight)| *weight == 0) {
+                    return Err(anyhow!(
+                        "The weights of the routes for {} must not all be zero",
+                        re.based_route
+                    ));
+                }
+            }
+            handler.shadow = re.shadowed_by.map(|key| Box::new(handler_for(key)));
+
+            grouped
+                .entry(re.host)
+                .or_default()
+                .entry(re.based_route)
+                .or_insert_with(|| (rfroute, Vec::new()))
+                .1
+                .push(handler);
+        }
+
+        // Build a `routefinder` for each host.
+
+        let mut routers = IndexMap::new();
+
+        for (host, paths) in grouped {
+            let mut rf = routefinder::Router::new();
+            for (rfroute, mut handlers) in paths.into_values() {
+                // Stable, so otherwise preserves the order routes were defined in.
+                handlers.sort_by_key(|handler| handler.conditions.is_empty());
+                rf.add(rfroute, handlers).map_err(|e| anyhow!("{e}"))?;
+            }
+            routers.insert(host, rf);
         }
 
         let router = Self {
-            router: std::sync::Arc::new(rf),
+            routers: std::sync::Arc::new(routers),
         };
 
         Ok(router)
@@ -165,21 +395,30 @@ impl Router {
 
     /// Returns the constructed routes.
     pub fn routes(&self) -> impl Iterator<Item = (&impl RouteInfo, &TriggerLookupKey)> {
-        self.router
-            .iter()
-            .map(|(_spec, handler)| (&handler.parsed_based_route, &handler.lookup_key))
+        self.handlers()
+            .map(|handler| (handler, &handler.lookup_key))
+    }
+
+    fn handlers(&self) -> impl Iterator<Item = &RouteHandler> {
+        self.routers
+            .values()
+            .flat_map(|rf| rf.iter().flat_map(|(_spec, handlers)| handlers))
+            // The first handler of a split is the route's own handler.
+            .flat_map(|handler| {
+                std::iter::once(handler).chain(handler.split.iter().skip(1).map(|(h, _)| h))
+            })
     }
 
     /// true if one or more routes is under the reserved `/.well-known/spin/*`
     /// prefix; otherwise false.
     pub fn contains_reserved_route(&self) -> bool {
-        self.router
-            .iter()
-            .any(|(_spec, handler)| handler.based_route.starts_with(crate::WELL_KNOWN_PREFIX))
+        self.handlers()
+            .any(|handler| handler.based_route.starts_with(crate::WELL_KNOWN_PREFIX))
     }
 
     /// This returns the component ID that should handle the given path, or an error
-    /// if no component matches.
+    /// if no component matches. Only routes which accept any host are considered,
+    /// and method and header conditions are not checked.
     ///
     /// If multiple components could potentially handle the same request based on their
     /// defined routes, components with matching exact routes take precedence followed
@@ -188,12 +427,99 @@ impl Router {
         &'router self,
         path: &'path str,
     ) -> Result<RouteMatch<'router, 'path>> {
-        let best_match = self
-            .router
-            .best_match(path)
-            .ok_or_else(|| anyhow!("Cannot match route for path {path}"))?;
+        self.route_for_host(None, path)
+    }
+
+    /// This returns the component ID that should handle the given host and path,
+    /// or an error if no component matches.
+    ///
+    /// The host is checked before the path: routes for an exact host match are
+    /// tried first, then routes for matching wildcard hosts (most specific first),
+    /// and finally routes which accept any host. Within each of these, paths are
+    /// matched as for [`Router::route`]. Method and header conditions are not checked.
+    pub fn route_for_host<'path, 'router: 'path>(
+        &'router self,
+        host: Option<&str>,
+        path: &'path str,
+    ) -> Result<RouteMatch<'router, 'path>> {
+        self.find_route(host, None, path)
+    }
 
-        let route_handler = best_match.handler();
+    /// This returns the component ID that should handle the given request, or an
+    /// error if no component matches.
+    ///
+    /// Routes are tried in the order of [`Router::route_for_host`], checking the
+    /// method and header conditions of each in turn, so a request which doesn't
+    /// meet the conditions of a route falls through to less specific routes. If no
+    /// route accepts the request, but some match the request headers and not the
+    /// method, the error is a [`MethodNotAllowed`].
+    pub fn route_request<'path, 'router: 'path>(
+        &'router self,
+        host: Option<&str>,
+        method: &http::Method,
+        headers: &http::HeaderMap,
+        path: &'path str,
+    ) -> Result<RouteMatch<'router, 'path>> {
+        self.find_route(host, Some((method, headers)), path)
+    }
+
+    fn find_route<'path, 'router: 'path>(
+        &'router self,
+        host: Option<&str>,
+        request: Option<(&http::Method, &http::HeaderMap)>,
+        path: &'path str,
+    ) -> Result<RouteMatch<'router, 'path>> {
+        let host = host.map(normalize_host);
+        let mut candidates = self
+            .routers
+            .iter()
+            .filter(|(pattern, _)| match (pattern, &host) {
+                (None, _) => true,
+                (Some(pattern), Some(host)) => pattern.matches(host),
+                (Some(_), None) => false,
+            })
+            .collect::<Vec<_>>();
+        // `None` (any host) sorts before `Some`, so reverse for most specific first.
+        candidates.sort_by_key(|(pattern, _)| {
+            std::cmp::Reverse(pattern.as_ref().map(HostPattern::specificity))
+        });
+
+        let matches = candidates
+            .into_iter()
+            .flat_map(|(_, rf)| rf.match_iter(path))
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(anyhow!("Cannot match route for path {path}"));
+        }
+
+        // If the request doesn't meet the conditions of any route for the best
+        // match, fall through to less specific matches.
+        let found = matches.iter().find_map(|path_match| {
+            let handlers = path_match.handler();
+            let route_handler = match request {
+                None => handlers.first(),
+                Some((method, headers)) => handlers
+                    .iter()
+                    .find(|handler| handler.conditions.matches(method, headers)),
+            }?;
+            Some((path_match, route_handler))
+        });
+        let Some((best_match, route_handler)) = found else {
+            let mut allowed = Vec::new();
+            if let Some((_, headers)) = request {
+                for handler in matches.iter().flat_map(|path_match| path_match.handler()) {
+                    if handler.conditions.headers_match(headers) {
+                        allowed.extend(handler.conditions.methods.iter().cloned());
+                    }
+                }
+            }
+            allowed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            allowed.dedup();
+            if allowed.is_empty() {
+                return Err(anyhow!("Cannot match route for path {path}"));
+            }
+            return Err(MethodNotAllowed { allowed }.into());
+        };
         let captures = best_match.captures();
 
         Ok(RouteMatch {
@@ -215,6 +541,138 @@ impl DuplicateRoute {
             &self.route
         }
     }
+
+    /// The host pattern of the duplicated route, if it is restricted to a host.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+}
+
+/// Conditions, other than the host and path, that a request must meet to match a route.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct RouteConditions {
+    /// If not empty, the request method must be one of these. Kept sorted.
+    methods: Vec<http::Method>,
+    /// Headers the request must have, with the value they must have (`None`
+    /// for any value). Kept sorted by name.
+    headers: Vec<(http::HeaderName, Option<String>)>,
+}
+
+impl RouteConditions {
+    fn parse(methods: &[String], headers: Option<&IndexMap<String, String>>) -> Result<Self> {
+        let mut methods = methods
+            .iter()
+            .map(|method| {
+                http::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| anyhow!("'{method}' is not a valid HTTP method"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // As in HTTP, a route which accepts GET also accepts HEAD.
+        if methods.contains(&http::Method::GET) {
+            methods.push(http::Method::HEAD);
+        }
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        methods.dedup();
+
+        let mut headers = headers
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| {
+                let name = http::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| anyhow!("'{name}' is not a valid HTTP header name"))?;
+                let value = (value != "*").then(|| value.clone());
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+        Ok(Self { methods, headers })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.methods.is_empty() && self.headers.is_empty()
+    }
+
+    fn matches(&self, method: &http::Method, headers: &http::HeaderMap) -> bool {
+        (self.methods.is_empty() || self.methods.contains(method)) && self.headers_match(headers)
+    }
+
+    fn headers_match(&self, headers: &http::HeaderMap) -> bool {
+        self.headers.iter().all(|(name, expected)| {
+            let mut values = headers.get_all(name).iter();
+            match expected {
+                None => values.next().is_some(),
+                Some(expected) => values.any(|value| value.to_str().is_ok_and(|v| v == expected)),
+            }
+        })
+    }
+}
+
+/// A pattern for the hosts a route accepts requests for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum HostPattern {
+    /// Matches only the given host, e.g. `api.example.com`.
+    Exact(String),
+    /// Matches any subdomain of the host following the `*`, e.g. `*.example.com`
+    /// matches `api.example.com` but not `example.com`.
+    Wildcard(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        let pattern = normalize_host(pattern);
+        let name = pattern.strip_prefix("*.").unwrap_or(&pattern);
+        if name.is_empty() {
+            return Err(anyhow!("host pattern '{pattern}' has no host name"));
+        }
+        if let Some(c) = name.chars().find(|c| matches!(c, '*' | ':' | '/')) {
+            return Err(anyhow!(
+                "host pattern '{pattern}' must be a host name or a `*.` wildcard followed by a host name, and may not contain '{c}'"
+            ));
+        }
+        if pattern.starts_with("*.") {
+            Ok(Self::Wildcard(pattern))
+        } else {
+            Ok(Self::Exact(pattern))
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Exact(pattern) => pattern == host,
+            Self::Wildcard(pattern) => {
+                // Keep the leading '.' so that `*.example.com` does not match `badexample.com`.
+                let suffix = &pattern[1..];
+                host.len() > suffix.len() && host.ends_with(suffix)
+            }
+        }
+    }
+
+    /// Exact hosts are more specific than any wildcard; otherwise longer
+    /// wildcards are more specific than shorter ones.
+    fn specificity(&self) -> (bool, usize) {
+        match self {
+            Self::Exact(pattern) => (true, pattern.len()),
+            Self::Wildcard(pattern) => (false, pattern.len()),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Exact(pattern) | Self::Wildcard(pattern) => pattern,
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Host names are case-insensitive and may be written with a trailing dot.
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
 }
 
 /// Information about a parsed route.
@@ -223,6 +681,10 @@ pub trait RouteInfo: fmt::Display + fmt::Debug {
     fn path(&self) -> &str;
     /// Returns true if this route has a trailing wildcard.
     fn is_wildcard(&self) -> bool;
+    /// Returns the host pattern this route is restricted to, if any.
+    fn host(&self) -> Option<&str> {
+        None
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -255,6 +717,26 @@ impl RouteInfo for ParsedRoute {
     }
 }
 
+impl RouteInfo for RouteHandler {
+    fn path(&self) -> &str {
+        self.parsed_based_route.path()
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.parsed_based_route.is_wildcard()
+    }
+
+    fn host(&self) -> Option<&str> {
+        self.host.as_ref().map(HostPattern::as_str)
+    }
+}
+
+impl fmt::Display for RouteHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.parsed_based_route.fmt(f)
+    }
+}
+
 impl fmt::Display for ParsedRoute {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -277,9 +759,13 @@ impl RouteMatch<'_, '_> {
             inner: RouteMatchKind::Synthetic {
                 route_handler: RouteHandler {
                     lookup_key: TriggerLookupKey::Component(component_id),
+                    host: None,
+                    conditions: RouteConditions::default(),
                     based_route: "/...".into(),
                     raw_route: "/...".into(),
                     parsed_based_route: ParsedRoute::TrailingWildcard(String::new()),
+                    split: Vec::new(),
+                    shadow: None,
                 },
                 trailing_wildcard: path,
             },
@@ -328,6 +814,65 @@ impl RouteMatch<'_, '_> {
     pub fn trailing_wildcard(&self) -> Cow<'_, str> {
         self.inner.trailing_wildcard()
     }
+
+    /// The handlers requests to the route are split between, with their
+    /// weights, or nothing if requests to the route are not split.
+    pub fn split(&self) -> impl ExactSizeIterator<Item = (&TriggerLookupKey, u32)> {
+        self.inner
+            .route_handler()
+            .split
+            .iter()
+            .map(|(handler, weight)| (&handler.lookup_key, *weight))
+    }
+
+    /// The handler which is sent a copy of requests to the route, if any.
+    pub fn shadow(&self) -> Option<&TriggerLookupKey> {
+        let shadow = self.inner.route_handler().shadow.as_ref()?;
+        Some(&shadow.lookup_key)
+    }
+}
+
+impl<'router, 'path> RouteMatch<'router, 'path> {
+    /// The match for the handler at the given index of [`RouteMatch::split`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of range.
+    pub fn into_split(self, index: usize) -> Self {
+        match self.inner {
+            RouteMatchKind::Real {
+                route_handler,
+                captures,
+                path,
+            } => Self {
+                inner: RouteMatchKind::Real {
+                    route_handler: &route_handler.split[index].0,
+                    captures,
+                    path,
+                },
+            },
+            RouteMatchKind::Synthetic { .. } => panic!("synthetic routes are not split"),
+        }
+    }
+
+    /// The match for the handler which is sent a copy of requests to the route,
+    /// if any.
+    pub fn into_shadow(self) -> Option<Self> {
+        match self.inner {
+            RouteMatchKind::Real {
+                route_handler,
+                captures,
+                path,
+            } => Some(Self {
+                inner: RouteMatchKind::Real {
+                    route_handler: route_handler.shadow.as_deref()?,
+                    captures,
+                    path,
+                },
+            }),
+            RouteMatchKind::Synthetic { .. } => None,
+        }
+    }
 }
 
 /// The kind of route match that was made.
@@ -835,4 +1380,561 @@ mod route_tests {
         let routes = component_router("/", [("comp", "/.well-known/spin")], None).unwrap();
         assert!(!routes.contains_reserved_route());
     }
+
+    /// Produces a router using component routes with optional hosts
+    fn host_router<'a>(
+        components: impl IntoIterator<Item = (&'a str, Option<&'a str>, &'a str)>,
+        duplicate_routes: Option<&mut Vec<DuplicateRoute>>,
+    ) -> anyhow::Result<Router> {
+        let owned_routes = components
+            .into_iter()
+            .map(|(cid, host, path)| (component_key(cid), host, HttpTriggerRouteConfig::from(path)))
+            .collect::<Vec<_>>();
+        let definitions = owned_routes
+            .iter()
+            .map(|(k, host, v)| RouteDefinition::new(k, v).with_host(*host));
+
+        Router::build_definitions("/", definitions, duplicate_routes)
+    }
+
+    #[test]
+    fn host_is_checked_before_path() -> Result<()> {
+        let r = host_router(
+            [
+                ("api", Some("api.example.com"), "/..."),
+                ("www", Some("www.example.com"), "/..."),
+                ("any", None, "/..."),
+            ],
+            None,
+        )?;
+
+        assert_eq!(
+            r.route_for_host(Some("api.example.com"), "/foo")?
+                .component_id(),
+            "api"
+        );
+        assert_eq!(
+            r.route_for_host(Some("www.example.com"), "/foo")?
+                .component_id(),
+            "www"
+        );
+        assert_eq!(
+            r.route_for_host(Some("other.example.com"), "/foo")?
+                .component_id(),
+            "any"
+        );
+        assert_eq!(r.route_for_host(None, "/foo")?.component_id(), "any");
+        assert_eq!(r.route("/foo")?.component_id(), "any");
+        Ok(())
+    }
+
+    #[test]
+    fn host_matching_ignores_case_and_trailing_dot() -> Result<()> {
+        let r = host_router([("api", Some("API.example.com"), "/...")], None)?;
+
+        assert_eq!(
+            r.route_for_host(Some("api.EXAMPLE.com."), "/foo")?
+                .component_id(),
+            "api"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exact_host_beats_wildcard_host() -> Result<()> {
+        let r = host_router(
+            [
+                ("wild", Some("*.example.com"), "/..."),
+                ("deeper-wild", Some("*.eu.example.com"), "/..."),
+                ("exact", Some("api.eu.example.com"), "/..."),
+            ],
+            None,
+        )?;
+
+        assert_eq!(
+            r.route_for_host(Some("api.eu.example.com"), "/")?
+                .component_id(),
+            "exact"
+        );
+        assert_eq!(
+            r.route_for_host(Some("www.eu.example.com"), "/")?
+                .component_id(),
+            "deeper-wild"
+        );
+        assert_eq!(
+            r.route_for_host(Some("www.example.com"), "/")?
+                .component_id(),
+            "wild"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard_host_does_not_match_parent_domain() -> Result<()> {
+        let r = host_router([("wild", Some("*.example.com"), "/...")], None)?;
+
+        assert!(r.route_for_host(Some("example.com"), "/").is_err());
+        assert!(r.route_for_host(Some("badexample.com"), "/").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn unmatched_path_on_host_falls_back_to_any_host() -> Result<()> {
+        let r = host_router(
+            [
+                ("api", Some("api.example.com"), "/api/..."),
+                ("any", None, "/..."),
+            ],
+            None,
+        )?;
+
+        assert_eq!(
+            r.route_for_host(Some("api.example.com"), "/api/x")?
+                .component_id(),
+            "api"
+        );
+        assert_eq!(
+            r.route_for_host(Some("api.example.com"), "/other")?
+                .component_id(),
+            "any"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_routes_consider_host() {
+        let mut duplicates = Vec::new();
+        let routes = host_router(
+            [
+                ("api", Some("api.example.com"), "/foo"),
+                ("www", Some("www.example.com"), "/foo"),
+                ("any", None, "/foo"),
+                ("api-again", Some("API.example.com"), "/foo"),
+            ],
+            Some(&mut duplicates),
+        )
+        .unwrap();
+
+        assert_eq!(3, routes.routes().count());
+        assert_eq!(1, duplicates.len());
+        assert_eq!("api", duplicates[0].replaced_id);
+        assert_eq!("api-again", duplicates[0].effective_id);
+        assert_eq!(Some("api.example.com"), duplicates[0].host());
+        assert_eq!("/foo", duplicates[0].route());
+    }
+
+    #[test]
+    fn routes_report_host() {
+        let routes = host_router(
+            [
+                ("any", None, "/"),
+                ("api", Some("*.example.com"), "/api/..."),
+            ],
+            None,
+        )
+        .unwrap();
+
+        let hosts = routes
+            .routes()
+            .map(|(route, _)| route.host())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![None, Some("*.example.com")], hosts);
+    }
+
+    #[test]
+    fn invalid_host_patterns_are_rejected() {
+        for host in ["", "*.", "api.*.com", "example.com:3000", "example.com/foo"] {
+            assert!(
+                host_router([("comp", Some(host), "/")], None).is_err(),
+                "host pattern {host:?} should be rejected"
+            );
+        }
+    }
+
+    fn request_headers<'a>(
+        headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> http::HeaderMap {
+        headers
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    http::HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn methods_select_between_routes_for_same_path() -> Result<()> {
+        let (reader, writer) = (component_key("reader"), component_key("writer"));
+        let route = HttpTriggerRouteConfig::from("/items/...");
+        let (read_methods, write_methods) = (
+            vec!["GET".to_owned(), "head".to_owned()],
+            vec!["POST".to_owned(), "DELETE".to_owned()],
+        );
+        let mut duplicates = Vec::new();
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&reader, &route).with_methods(&read_methods),
+                RouteDefinition::new(&writer, &route).with_methods(&write_methods),
+            ],
+            Some(&mut duplicates),
+        )?;
+        let no_headers = http::HeaderMap::new();
+
+        assert_eq!(0, duplicates.len());
+        assert_eq!(2, r.routes().count());
+        for (method, expected) in [
+            (http::Method::GET, "reader"),
+            (http::Method::HEAD, "reader"),
+            (http::Method::POST, "writer"),
+            (http::Method::DELETE, "writer"),
+        ] {
+            let m = r.route_request(None, &method, &no_headers, "/items/1")?;
+            assert_eq!(m.component_id(), expected);
+            assert_eq!(m.trailing_wildcard(), "/1");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unaccepted_method_is_not_allowed() -> Result<()> {
+        let key = component_key("reader");
+        let route = HttpTriggerRouteConfig::from("/items");
+        let methods = vec!["HEAD".to_owned(), "GET".to_owned()];
+        let r = Router::build_definitions(
+            "/",
+            [RouteDefinition::new(&key, &route).with_methods(&methods)],
+            None,
+        )?;
+
+        let err = r
+            .route_request(None, &http::Method::PUT, &http::HeaderMap::new(), "/items")
+            .err()
+            .expect("PUT should not be routed");
+        let err = err.downcast_ref::<MethodNotAllowed>().unwrap();
+        assert_eq!("GET, HEAD", err.allow_header_value());
+
+        let err = r
+            .route_request(None, &http::Method::PUT, &http::HeaderMap::new(), "/other")
+            .err()
+            .expect("/other should not be routed");
+        assert!(err.downcast_ref::<MethodNotAllowed>().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_routes_are_tried_before_unconditional() -> Result<()> {
+        let (fallback, v2, writer) = (
+            component_key("fallback"),
+            component_key("v2"),
+            component_key("writer"),
+        );
+        let route = HttpTriggerRouteConfig::from("/api/...");
+        let v2_headers = IndexMap::from([("X-Api-Version".to_owned(), "2".to_owned())]);
+        let write_methods = vec!["POST".to_owned()];
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&fallback, &route),
+                RouteDefinition::new(&v2, &route).with_headers(&v2_headers),
+                RouteDefinition::new(&writer, &route).with_methods(&write_methods),
+            ],
+            None,
+        )?;
+        let get = http::Method::GET;
+
+        let m = r.route_request(
+            None,
+            &get,
+            &request_headers([("x-api-version", "2")]),
+            "/api/x",
+        )?;
+        assert_eq!(m.component_id(), "v2");
+        let m = r.route_request(
+            None,
+            &get,
+            &request_headers([("x-api-version", "1")]),
+            "/api/x",
+        )?;
+        assert_eq!(m.component_id(), "fallback");
+        let m = r.route_request(None, &http::Method::POST, &http::HeaderMap::new(), "/api/x")?;
+        assert_eq!(m.component_id(), "writer");
+        Ok(())
+    }
+
+    #[test]
+    fn header_wildcard_requires_presence() -> Result<()> {
+        let key = component_key("authed");
+        let route = HttpTriggerRouteConfig::from("/...");
+        let headers = IndexMap::from([("authorization".to_owned(), "*".to_owned())]);
+        let r = Router::build_definitions(
+            "/",
+            [RouteDefinition::new(&key, &route).with_headers(&headers)],
+            None,
+        )?;
+        let get = http::Method::GET;
+
+        let m = r.route_request(
+            None,
+            &get,
+            &request_headers([("authorization", "Bearer x")]),
+            "/",
+        )?;
+        assert_eq!(m.component_id(), "authed");
+        let err = r
+            .route_request(None, &get, &http::HeaderMap::new(), "/")
+            .err()
+            .expect("request without the header should not be routed");
+        assert!(err.downcast_ref::<MethodNotAllowed>().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn failed_conditions_fall_through_to_less_specific_routes() -> Result<()> {
+        let (writer, catch_all) = (component_key("writer"), component_key("catch-all"));
+        let (items, all) = (
+            HttpTriggerRouteConfig::from("/items"),
+            HttpTriggerRouteConfig::from("/..."),
+        );
+        let write_methods = vec!["POST".to_owned()];
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&writer, &items).with_methods(&write_methods),
+                RouteDefinition::new(&catch_all, &all),
+            ],
+            None,
+        )?;
+
+        let m = r.route_request(None, &http::Method::POST, &http::HeaderMap::new(), "/items")?;
+        assert_eq!(m.component_id(), "writer");
+        let m = r.route_request(None, &http::Method::GET, &http::HeaderMap::new(), "/items")?;
+        assert_eq!(m.component_id(), "catch-all");
+        Ok(())
+    }
+
+    #[test]
+    fn method_not_allowed_only_if_no_route_accepts_request() -> Result<()> {
+        let (writer, reader) = (component_key("writer"), component_key("reader"));
+        let (items, all) = (
+            HttpTriggerRouteConfig::from("/items"),
+            HttpTriggerRouteConfig::from("/..."),
+        );
+        let (write_methods, read_methods) = (vec!["POST".to_owned()], vec!["GET".to_owned()]);
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&writer, &items).with_methods(&write_methods),
+                RouteDefinition::new(&reader, &all).with_methods(&read_methods),
+            ],
+            None,
+        )?;
+
+        let m = r.route_request(None, &http::Method::GET, &http::HeaderMap::new(), "/items")?;
+        assert_eq!(m.component_id(), "reader");
+        let err = r
+            .route_request(None, &http::Method::PUT, &http::HeaderMap::new(), "/items")
+            .err()
+            .expect("PUT should not be routed");
+        let err = err.downcast_ref::<MethodNotAllowed>().unwrap();
+        assert_eq!("GET, HEAD, POST", err.allow_header_value());
+        Ok(())
+    }
+
+    #[test]
+    fn get_route_accepts_head() -> Result<()> {
+        let key = component_key("reader");
+        let route = HttpTriggerRouteConfig::from("/items");
+        let methods = vec!["GET".to_owned()];
+        let r = Router::build_definitions(
+            "/",
+            [RouteDefinition::new(&key, &route).with_methods(&methods)],
+            None,
+        )?;
+
+        let m = r.route_request(None, &http::Method::HEAD, &http::HeaderMap::new(), "/items")?;
+        assert_eq!(m.component_id(), "reader");
+        let err = r
+            .route_request(None, &http::Method::POST, &http::HeaderMap::new(), "/items")
+            .err()
+            .expect("POST should not be routed");
+        let err = err.downcast_ref::<MethodNotAllowed>().unwrap();
+        assert_eq!("GET, HEAD", err.allow_header_value());
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_routes_consider_conditions() {
+        let (first, second, third) = (
+            component_key("first"),
+            component_key("second"),
+            component_key("third"),
+        );
+        let route = HttpTriggerRouteConfig::from("/foo");
+        let (get, get_upper) = (vec!["get".to_owned()], vec!["GET".to_owned()]);
+        let mut duplicates = Vec::new();
+        Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&first, &route).with_methods(&get),
+                RouteDefinition::new(&second, &route),
+                RouteDefinition::new(&third, &route).with_methods(&get_upper),
+            ],
+            Some(&mut duplicates),
+        )
+        .unwrap();
+
+        assert_eq!(1, duplicates.len());
+        assert_eq!("first", duplicates[0].replaced_id);
+        assert_eq!("third", duplicates[0].effective_id);
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        let key = component_key("comp");
+        let route = HttpTriggerRouteConfig::from("/");
+        let bad_methods = vec!["GE T".to_owned()];
+        let bad_headers = IndexMap::from([("bad header".to_owned(), "x".to_owned())]);
+
+        assert!(
+            Router::build_definitions(
+                "/",
+                [RouteDefinition::new(&key, &route).with_methods(&bad_methods)],
+                None
+            )
+            .is_err()
+        );
+        assert!(
+            Router::build_definitions(
+                "/",
+                [RouteDefinition::new(&key, &route).with_headers(&bad_headers)],
+                None
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn weighted_routes_are_split() -> Result<()> {
+        let (v1, v2, other) = (
+            component_key("v1"),
+            component_key("v2"),
+            component_key("other"),
+        );
+        let (route, other_route) = (
+            HttpTriggerRouteConfig::from("/cart/..."),
+            HttpTriggerRouteConfig::from("/other"),
+        );
+        let mut duplicates = Vec::new();
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&v1, &route).with_weight(Some(90)),
+                RouteDefinition::new(&v2, &route).with_weight(Some(10)),
+                RouteDefinition::new(&other, &other_route).with_weight(Some(5)),
+            ],
+            Some(&mut duplicates),
+        )?;
+        assert!(duplicates.is_empty());
+
+        let m = r.route("/cart/items")?;
+        assert_eq!(m.component_id(), "v1");
+        let split = m
+            .split()
+            .map(|(key, weight)| (key.component_id(), weight))
+            .collect::<Vec<_>>();
+        assert_eq!(split, [("v1", 90), ("v2", 10)]);
+
+        let m = m.into_split(1);
+        assert_eq!(m.component_id(), "v2");
+        assert_eq!(m.trailing_wildcard(), "/items");
+        assert_eq!(m.raw_route(), "/cart/...");
+
+        // A single weighted route is not split.
+        assert_eq!(r.route("/other")?.split().len(), 0);
+
+        let mut routed = r
+            .routes()
+            .map(|(_, key)| key.component_id())
+            .collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(routed, ["other", "v1", "v2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn unweighted_route_replaces_weighted_routes() {
+        let (v1, v2) = (component_key("v1"), component_key("v2"));
+        let route = HttpTriggerRouteConfig::from("/cart");
+        let mut duplicates = Vec::new();
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&v1, &route).with_weight(Some(90)),
+                RouteDefinition::new(&v2, &route),
+            ],
+            Some(&mut duplicates),
+        )
+        .unwrap();
+
+        assert_eq!(1, duplicates.len());
+        assert_eq!(r.route("/cart").unwrap().component_id(), "v2");
+    }
+
+    #[test]
+    fn zero_weights_are_rejected() {
+        let (v1, v2) = (component_key("v1"), component_key("v2"));
+        let route = HttpTriggerRouteConfig::from("/cart");
+        let err = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&v1, &route).with_weight(Some(0)),
+                RouteDefinition::new(&v2, &route).with_weight(Some(0)),
+            ],
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("must not all be zero"), "{err}");
+    }
+
+    #[test]
+    fn shadow_routes_are_attached() -> Result<()> {
+        let (live, next) = (component_key("live"), component_key("next"));
+        let route = HttpTriggerRouteConfig::from("/cart/:id");
+        let r = Router::build_definitions(
+            "/",
+            [
+                RouteDefinition::new(&next, &route).with_shadow(true),
+                RouteDefinition::new(&live, &route),
+            ],
+            None,
+        )?;
+
+        let m = r.route("/cart/42")?;
+        assert_eq!(m.component_id(), "live");
+        assert_eq!(m.shadow().map(|key| key.component_id()), Some("next"));
+
+        let shadow = m.into_shadow().unwrap();
+        assert_eq!(shadow.component_id(), "next");
+        assert_eq!(shadow.named_wildcards()["id"], "42");
+        assert!(shadow.shadow().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn shadow_route_needs_a_route_to_copy() {
+        let next = component_key("next");
+        let route = HttpTriggerRouteConfig::from("/cart");
+        assert!(
+            Router::build_definitions(
+                "/",
+                [RouteDefinition::new(&next, &route).with_shadow(true)],
+                None
+            )
+            .is_err()
+        );
+    }
 }
@@ -90,4 +90,12 @@ impl RuntimeFactorsBuilder for FactorsBuilder {
 
         Ok(())
     }
+
+    fn log_dir(runtime_config: &Self::RuntimeConfig) -> Option<PathBuf> {
+        runtime_config.log_dir()
+    }
+
+    fn state_dir(runtime_config: &Self::RuntimeConfig) -> Option<PathBuf> {
+        runtime_config.state_dir()
+    }
 }
@@ -0,0 +1,313 @@
+//! A trigger which runs a `wasi:cli/run` component once, and exits with its
+//! exit code.
+
+use std::fmt;
+
+use anyhow::{Context, bail};
+use clap::Args;
+use serde::Deserialize;
+use spin_factor_wasi::WasiFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{App, Trigger, TriggerApp};
+use tracing::{Level, instrument};
+use wasmtime_wasi::p2::bindings::CommandIndices;
+
+pub struct CommandTrigger {
+    command: Option<String>,
+    args: Vec<String>,
+}
+
+/// Command trigger CLI arguments.
+#[derive(Args, Debug)]
+pub struct CliArgs {
+    /// The command to run, by trigger ID or component ID. Required if the
+    /// application has more than one command trigger.
+    #[clap(long = "command", env = "SPIN_COMMAND")]
+    pub command: Option<String>,
+
+    /// Arguments to pass to the command, instead of those in the manifest.
+    /// Put them after `--` if any begin with a hyphen.
+    #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Command trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+    /// Arguments to pass to the command, if none are given on the command line
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// The error returned when a command exits with a non-zero exit code. The
+/// command is expected to have reported why itself.
+#[derive(Debug)]
+pub struct CommandExit {
+    pub code: i32,
+}
+
+impl fmt::Display for CommandExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "command exited with code {}", self.code)
+    }
+}
+
+impl std::error::Error for CommandExit {}
+
+impl<F: RuntimeFactors> Trigger<F> for CommandTrigger {
+    const TYPE: &'static str = "cli-command";
+
+    type CliArgs = CliArgs;
+
+    type InstanceState = ();
+
+    fn new(cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            command: cli_args.command,
+            args: cli_args.args,
+        })
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let app = trigger_app.app();
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let configs = app
+            .trigger_configs::<TriggerConfig>(trigger_type)?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let (component_id, args) = self.select(configs)?;
+
+        let pre = trigger_app.get_instance_pre(&component_id)?;
+        let indices = CommandIndices::new(pre)
+            .map_err(anyhow::Error::from)
+            .with_context(|| {
+                format!("command component {component_id} must export wasi:cli/run")
+            })?;
+
+        match execute(&trigger_app, &component_id, &args, &indices).await? {
+            0 => Ok(()),
+            code => Err(CommandExit { code }.into()),
+        }
+    }
+}
+
+impl CommandTrigger {
+    /// Chooses the command to run from the application's command triggers,
+    /// returning its component ID and the arguments to pass to it.
+    fn select(
+        self,
+        mut configs: Vec<(&str, TriggerConfig)>,
+    ) -> anyhow::Result<(String, Vec<String>)> {
+        let config = match &self.command {
+            Some(command) => {
+                let Some(index) = configs.iter().position(|(trigger_id, config)| {
+                    *trigger_id == command.as_str() || config.component == *command
+                }) else {
+                    bail!("the application has no command {command:?}");
+                };
+                configs.swap_remove(index).1
+            }
+            None if configs.len() == 1 => configs.remove(0).1,
+            None => {
+                let components = configs
+                    .iter()
+                    .map(|(_, config)| config.component.as_str())
+                    .collect::<Vec<_>>();
+                bail!(
+                    "the application has more than one command, so use --command to choose one of: {}",
+                    components.join(", ")
+                );
+            }
+        };
+
+        let args = if self.args.is_empty() {
+            config.args
+        } else {
+            self.args
+        };
+        Ok((config.component, args))
+    }
+}
+
+/// Runs a command to completion, returning its exit code.
+#[instrument(name = "spin_trigger_command.execute", skip_all, err(level = Level::INFO), fields(
+    otel.name = format!("command {component_id}"),
+))]
+async fn execute<F: RuntimeFactors>(
+    trigger_app: &TriggerApp<CommandTrigger, F>,
+    component_id: &str,
+    args: &[String],
+    indices: &CommandIndices,
+) -> anyhow::Result<i32> {
+    spin_telemetry::metrics::monotonic_counter!(
+        spin.request_count = 1,
+        trigger_type = "cli-command",
+        app_id = trigger_app.app().id(),
+        component_id = component_id
+    );
+
+    tracing::trace!("Executing command component {component_id}");
+    let mut instance_builder = trigger_app.prepare(component_id)?;
+    if let Some(wasi_builder) = instance_builder.factor_builder::<WasiFactor>() {
+        // By convention, the first argument is the name of the program.
+        wasi_builder.args(std::iter::once(component_id).chain(args.iter().map(String::as_str)));
+    }
+    let (instance, mut store) = instance_builder.instantiate(()).await?;
+
+    let command = indices.load(&mut store, &instance)?;
+    match command.wasi_cli_run().call_run(&mut store).await {
+        Ok(Ok(())) => Ok(0),
+        Ok(Err(())) => Ok(1),
+        Err(guest_err) => match guest_err
+            .root_cause()
+            .downcast_ref::<wasmtime_wasi::I32Exit>()
+        {
+            Some(exit) => Ok(exit.0),
+            None => Err(guest_err.into()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_core::{Component, async_trait};
+    use spin_factor_wasi::{DummyFilesMounter, WasiFactor};
+    use spin_factors::AppComponent;
+    use spin_factors_executor::{ComponentLoader, FactorsExecutor, TriggerDependenciesComposer};
+    use spin_factors_test::{TestEnvironment, toml};
+
+    use super::*;
+
+    /// A command whose `run` returns the given `result` discriminant.
+    fn command_component(discriminant: u32) -> String {
+        format!(
+            r#"(component
+                (core module $m (func (export "run") (result i32) i32.const {discriminant}))
+                (core instance $i (instantiate $m))
+                (func $run (result (result)) (canon lift (core func $i "run")))
+                (instance $cli-run (export "run" (func $run)))
+                (export "wasi:cli/run@0.2.0" (instance $cli-run))
+            )"#
+        )
+    }
+
+    fn trigger(command: Option<&str>, args: &[&str]) -> CommandTrigger {
+        CommandTrigger {
+            command: command.map(Into::into),
+            args: args.iter().map(|&arg| arg.into()).collect(),
+        }
+    }
+
+    fn configs() -> Vec<(&'static str, TriggerConfig)> {
+        vec![
+            (
+                "backfill",
+                TriggerConfig {
+                    component: "backfill-job".into(),
+                    args: vec!["--since".into(), "2024-01-01".into()],
+                },
+            ),
+            (
+                "migrate",
+                TriggerConfig {
+                    component: "migrations".into(),
+                    args: vec![],
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn selects_command_by_trigger_or_component_id() {
+        let (component_id, args) = trigger(Some("backfill"), &[]).select(configs()).unwrap();
+        assert_eq!(component_id, "backfill-job");
+        assert_eq!(args, ["--since", "2024-01-01"]);
+
+        let (component_id, _) = trigger(Some("migrations"), &[]).select(configs()).unwrap();
+        assert_eq!(component_id, "migrations");
+
+        let err = trigger(Some("nope"), &[]).select(configs()).unwrap_err();
+        assert!(err.to_string().contains("no command \"nope\""), "{err}");
+    }
+
+    #[test]
+    fn requires_a_choice_between_commands() {
+        let err = trigger(None, &[]).select(configs()).unwrap_err();
+        assert!(err.to_string().contains("--command"), "{err}");
+        assert!(
+            err.to_string().contains("backfill-job, migrations"),
+            "{err}"
+        );
+
+        let mut configs = configs();
+        configs.truncate(1);
+        let (component_id, _) = trigger(None, &[]).select(configs).unwrap();
+        assert_eq!(component_id, "backfill-job");
+    }
+
+    #[test]
+    fn cli_args_replace_manifest_args() {
+        let (_, args) = trigger(Some("backfill"), &["--dry-run"])
+            .select(configs())
+            .unwrap();
+        assert_eq!(args, ["--dry-run"]);
+    }
+
+    #[derive(RuntimeFactors)]
+    struct TestFactors {
+        wasi: WasiFactor,
+    }
+
+    struct WatLoader(String);
+
+    #[async_trait]
+    impl ComponentLoader<TestFactors, ()> for WatLoader {
+        async fn load_component(
+            &self,
+            engine: &spin_core::wasmtime::Engine,
+            _component: &AppComponent,
+            _trigger_dependencies_composer: &impl TriggerDependenciesComposer,
+        ) -> anyhow::Result<Component> {
+            Ok(Component::new(engine, &self.0)?)
+        }
+    }
+
+    /// Runs an app with a single command trigger for the given component.
+    async fn run_command(wat: String) -> anyhow::Result<()> {
+        let env = TestEnvironment::new(TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        })
+        .extend_manifest(toml! {
+            [[trigger.cli-command]]
+            component = "job"
+
+            [component.job]
+            source = "does-not-exist.wasm"
+        });
+        let locked = env.build_locked_app().await?;
+        let app = App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let executor = std::sync::Arc::new(FactorsExecutor::new(engine_builder, env.factors)?);
+        let trigger_app = executor
+            .load_app(app, Default::default(), &WatLoader(wat), None, ())
+            .await?;
+
+        Trigger::run(trigger(None, &[]), trigger_app).await
+    }
+
+    #[tokio::test]
+    async fn exits_with_the_command_exit_code() {
+        run_command(command_component(0)).await.unwrap();
+
+        let err = run_command(command_component(1)).await.unwrap_err();
+        let exit = err
+            .downcast_ref::<CommandExit>()
+            .expect("should be a CommandExit");
+        assert_eq!(exit.code, 1);
+    }
+}
@@ -0,0 +1,646 @@
+//! Access logging for the HTTP trigger.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{LineWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Local};
+use http::{Request, Response, header};
+use http_body_util::BodyExt;
+use hyper::body::{Frame, SizeHint};
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::{Body, instrument::MatchedRoute};
+
+/// The name of the access log file in the log directory.
+const ACCESS_LOG_FILE: &str = "http_access.log";
+/// The most lines waiting to be written. Lines beyond this are dropped, so
+/// that a slow destination can't use unbounded memory.
+const MAX_PENDING_LINES: usize = 4096;
+
+/// Where access log lines are written.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum AccessLogDestination {
+    /// Standard output.
+    Stdout,
+    /// `http_access.log` in the log directory (see --log-dir).
+    File,
+}
+
+/// The format of access log lines.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Common Log Format, followed by the route, component, duration and instance.
+    Common,
+    /// Combined Log Format (Common plus referer and user agent), followed by the
+    /// route, component, duration and instance.
+    #[default]
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Writes a line for each request handled by the server.
+pub(crate) struct AccessLog {
+    format: AccessLogFormat,
+    writer: Option<WriterThread>,
+}
+
+/// A thread which writes access log lines, so that requests don't wait on I/O.
+struct WriterThread {
+    lines: mpsc::SyncSender<String>,
+    /// Lines dropped because too many were waiting, since the writer last
+    /// reported them.
+    dropped: Arc<AtomicU64>,
+    thread: JoinHandle<()>,
+}
+
+impl WriterThread {
+    fn spawn(mut writer: Box<dyn Write + Send>, capacity: usize) -> anyhow::Result<Self> {
+        let (lines, rx) = mpsc::sync_channel::<String>(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let thread = std::thread::Builder::new()
+            .name("access-log".into())
+            .spawn({
+                let dropped = dropped.clone();
+                move || {
+                    for line in rx {
+                        if let Err(err) = writeln!(writer, "{line}") {
+                            tracing::warn!("Failed to write access log: {err}");
+                        }
+                        let dropped = dropped.swap(0, Ordering::Relaxed);
+                        if dropped > 0 {
+                            tracing::warn!(
+                                "Dropped {dropped} access log lines because the log could not keep up"
+                            );
+                        }
+                    }
+                }
+            })
+            .context("failed to start access log writer")?;
+        Ok(Self {
+            lines,
+            dropped,
+            thread,
+        })
+    }
+
+    /// Queues a line to be written, dropping it if too many are waiting.
+    fn send(&self, line: String) {
+        match self.lines.try_send(line) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                tracing::warn!("Failed to write access log: the writer has stopped");
+            }
+        }
+    }
+}
+
+impl AccessLog {
+    /// Opens the access log at the given destination.
+    pub fn open(
+        destination: AccessLogDestination,
+        format: AccessLogFormat,
+        log_dir: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let writer: Box<dyn Write + Send> = match destination {
+            AccessLogDestination::Stdout => Box::new(LineWriter::new(std::io::stdout())),
+            AccessLogDestination::File => {
+                let log_dir = log_dir.context(
+                    "--access-log=file requires a log directory, but logging to disk is disabled",
+                )?;
+                std::fs::create_dir_all(log_dir)
+                    .with_context(|| format!("failed to create log dir {}", log_dir.display()))?;
+                let path = log_dir.join(ACCESS_LOG_FILE);
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("failed to open access log {}", path.display()))?;
+                Box::new(LineWriter::new(file))
+            }
+        };
+        Ok(Self {
+            format,
+            writer: Some(WriterThread::spawn(writer, MAX_PENDING_LINES)?),
+        })
+    }
+
+    /// Starts the entry for a request. The entry is written once the response
+    /// has been sent.
+    pub fn start<B>(self: &Arc<Self>, req: &Request<B>, client_addr: SocketAddr) -> PendingEntry {
+        let header_value = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        PendingEntry {
+            log: self.clone(),
+            entry: Entry {
+                time: Local::now(),
+                start: Instant::now(),
+                client_addr,
+                method: req.method().to_string(),
+                uri: req
+                    .uri()
+                    .path_and_query()
+                    .map(|p| p.to_string())
+                    .unwrap_or_else(|| req.uri().to_string()),
+                version: format!("{:?}", req.version()),
+                referer: header_value(header::REFERER),
+                user_agent: header_value(header::USER_AGENT),
+                status: 0,
+                bytes: 0,
+                route: None,
+                component: None,
+                instance: None,
+            },
+        }
+    }
+
+    fn write(&self, entry: &Entry) {
+        let line = match self.format {
+            AccessLogFormat::Common | AccessLogFormat::Combined => entry.to_clf(self.format),
+            AccessLogFormat::Json => entry.to_json(),
+        };
+        if let Some(writer) = &self.writer {
+            writer.send(line);
+        }
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        // Close the channel, then wait for the writer to finish the lines sent.
+        if let Some(WriterThread { lines, thread, .. }) = self.writer.take() {
+            drop(lines);
+            _ = thread.join();
+        }
+    }
+}
+
+/// The entry for a request which has not yet been responded to.
+pub(crate) struct PendingEntry {
+    log: Arc<AccessLog>,
+    entry: Entry,
+}
+
+impl PendingEntry {
+    /// Attaches the entry to the response, to be written once its body has
+    /// been sent (or abandoned).
+    pub fn finish(mut self, res: Response<Body>) -> Response<Body> {
+        self.entry.status = res.status().as_u16();
+        self.entry.route = res
+            .extensions()
+            .get::<MatchedRoute>()
+            .map(|m| m.route.clone());
+        self.entry.component = res
+            .extensions()
+            .get::<RouteComponent>()
+            .map(|c| c.0.clone());
+        self.entry.instance = res.extensions().get::<InstanceReuse>().copied();
+        res.map(|body| {
+            LoggedBody {
+                inner: body,
+                pending: Some(self),
+            }
+            .boxed_unsync()
+        })
+    }
+
+    /// Writes the entry for a request which failed without a response.
+    pub fn fail(mut self) {
+        self.entry.status = 500;
+        self.log.write(&self.entry);
+    }
+}
+
+/// A response body which writes its access log entry when it is finished.
+struct LoggedBody {
+    inner: Body,
+    pending: Option<PendingEntry>,
+}
+
+impl hyper::body::Body for LoggedBody {
+    type Data = hyper::body::Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(pending)) = (frame.data_ref(), &mut self.pending) {
+                    pending.entry.bytes += data.len() as u64;
+                }
+            }
+            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => self.write_entry(),
+            Poll::Pending => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl LoggedBody {
+    fn write_entry(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.log.write(&pending.entry);
+        }
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.write_entry();
+    }
+}
+
+/// A response extension naming the component which handled the request.
+#[derive(Clone)]
+pub(crate) struct RouteComponent(pub String);
+
+impl RouteComponent {
+    pub fn with_response_extension(
+        mut resp: Response<Body>,
+        component_id: impl Into<String>,
+    ) -> Response<Body> {
+        resp.extensions_mut().insert(Self(component_id.into()));
+        resp
+    }
+}
+
+/// A response extension recording which reusable instance handled a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InstanceReuse {
+    /// Identifies the instance within the server.
+    pub instance: u64,
+    /// How many requests the instance has started, including this one.
+    pub request: u64,
+}
+
+/// Tracks which reusable instance each request is dispatched to.
+#[derive(Clone, Default)]
+pub(crate) struct ReuseTracker(Arc<ReuseTrackerInner>);
+
+#[derive(Default)]
+struct ReuseTrackerInner {
+    next_request_id: AtomicU64,
+    next_instance_id: AtomicU64,
+    /// Request ID -> the instance it started on, for dispatched requests.
+    started: Mutex<HashMap<u64, Option<InstanceReuse>>>,
+}
+
+impl ReuseTracker {
+    /// Allocates an ID for a request about to be dispatched. The request is
+    /// tracked until the returned guard is dropped.
+    pub fn dispatch(&self) -> DispatchedRequest {
+        let id = self.0.next_request_id.fetch_add(1, Ordering::Relaxed);
+        self.0.started.lock().unwrap().insert(id, None);
+        DispatchedRequest {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    /// Allocates an ID for a new instance.
+    pub fn next_instance_id(&self) -> u64 {
+        self.0.next_instance_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Records that the request has started on an instance, unless it is no
+    /// longer tracked.
+    pub fn started(&self, request_id: u64, reuse: InstanceReuse) {
+        if let Some(started) = self.0.started.lock().unwrap().get_mut(&request_id) {
+            *started = Some(reuse);
+        }
+    }
+
+    fn stop_tracking(&self, request_id: u64) -> Option<InstanceReuse> {
+        self.0.started.lock().unwrap().remove(&request_id).flatten()
+    }
+}
+
+/// A request being tracked by a [`ReuseTracker`], which stops being tracked
+/// when this is dropped, even if it never finishes.
+pub(crate) struct DispatchedRequest {
+    tracker: ReuseTracker,
+    id: u64,
+}
+
+impl DispatchedRequest {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the instance the request ran on, if it started.
+    pub fn finished(self) -> Option<InstanceReuse> {
+        self.tracker.stop_tracking(self.id)
+    }
+}
+
+impl Drop for DispatchedRequest {
+    fn drop(&mut self) {
+        self.tracker.stop_tracking(self.id);
+    }
+}
+
+struct Entry {
+    time: DateTime<Local>,
+    start: Instant,
+    client_addr: SocketAddr,
+    method: String,
+    uri: String,
+    version: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    status: u16,
+    bytes: u64,
+    route: Option<String>,
+    component: Option<String>,
+    instance: Option<InstanceReuse>,
+}
+
+impl Entry {
+    fn duration_ms(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+
+    fn to_clf(&self, format: AccessLogFormat) -> String {
+        let quoted = |value: Option<&str>| match value {
+            Some(value) => format!("\"{}\"", escape(value)),
+            None => "\"-\"".to_owned(),
+        };
+        let mut line = format!(
+            "{} - - [{}] \"{} {} {}\" {} ",
+            self.client_addr.ip(),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            escape(&self.method),
+            escape(&self.uri),
+            self.version,
+            self.status,
+        );
+        if self.bytes == 0 {
+            line.push('-');
+        } else {
+            _ = write!(line, "{}", self.bytes);
+        }
+        if format == AccessLogFormat::Combined {
+            _ = write!(
+                line,
+                " {} {}",
+                quoted(self.referer.as_deref()),
+                quoted(self.user_agent.as_deref())
+            );
+        }
+        _ = write!(
+            line,
+            " {} {} {:.3}",
+            quoted(self.route.as_deref()),
+            quoted(self.component.as_deref()),
+            self.duration_ms(),
+        );
+        match self.instance {
+            Some(InstanceReuse { instance, request }) => {
+                _ = write!(line, " {instance}:{request}");
+            }
+            None => line.push_str(" -"),
+        }
+        line
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "time": self.time.to_rfc3339(),
+            "client": self.client_addr.ip().to_string(),
+            "method": self.method,
+            "uri": self.uri,
+            "protocol": self.version,
+            "status": self.status,
+            "bytes": self.bytes,
+            "duration_ms": self.duration_ms(),
+            "referer": self.referer,
+            "user_agent": self.user_agent,
+            "route": self.route,
+            "component": self.component,
+            "instance": self.instance.map(|reuse| serde_json::json!({
+                "id": reuse.instance,
+                "request": reuse.request,
+            })),
+        })
+        .to_string()
+    }
+}
+
+/// Escapes a value for a quoted CLF field, in the style of Apache's `mod_log_config`.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_ascii_control() => _ = write!(escaped, "\\x{:02x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            time: DateTime::parse_from_rfc3339("2000-10-10T13:55:36-07:00")
+                .unwrap()
+                .with_timezone(&Local),
+            start: Instant::now(),
+            client_addr: "127.0.0.1:51234".parse().unwrap(),
+            method: "GET".into(),
+            uri: "/hello?name=\"spin\"".into(),
+            version: "HTTP/1.1".into(),
+            referer: None,
+            user_agent: Some("curl/8.0".into()),
+            status: 200,
+            bytes: 2326,
+            route: Some("/hello/...".into()),
+            component: Some("hello".into()),
+            instance: Some(InstanceReuse {
+                instance: 3,
+                request: 7,
+            }),
+        }
+    }
+
+    #[test]
+    fn formats_common_and_combined() {
+        let entry = entry();
+        let common = entry.to_clf(AccessLogFormat::Common);
+        assert!(
+            common.starts_with("127.0.0.1 - - ["),
+            "unexpected line {common}"
+        );
+        assert!(
+            common.contains(
+                "] \"GET /hello?name=\\\"spin\\\" HTTP/1.1\" 200 2326 \"/hello/...\" \"hello\" "
+            ),
+            "unexpected line {common}"
+        );
+        assert!(common.ends_with(" 3:7"), "unexpected line {common}");
+
+        let combined = entry.to_clf(AccessLogFormat::Combined);
+        assert!(
+            combined.contains(" 200 2326 \"-\" \"curl/8.0\" \"/hello/...\" \"hello\" "),
+            "unexpected line {combined}"
+        );
+    }
+
+    #[test]
+    fn formats_json() {
+        let mut entry = entry();
+        entry.instance = None;
+        let json: serde_json::Value = serde_json::from_str(&entry.to_json()).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["bytes"], 2326);
+        assert_eq!(json["route"], "/hello/...");
+        assert_eq!(json["component"], "hello");
+        assert_eq!(json["referer"], serde_json::Value::Null);
+        assert_eq!(json["instance"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn escapes_quoted_fields() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\x0a");
+    }
+
+    #[test]
+    fn tracks_instance_reuse() {
+        let tracker = ReuseTracker::default();
+        let request = tracker.dispatch();
+        let reuse = InstanceReuse {
+            instance: tracker.next_instance_id(),
+            request: 1,
+        };
+        tracker.started(request.id(), reuse);
+        assert_eq!(request.finished(), Some(reuse));
+
+        // A request which is abandoned, such as by the client disconnecting,
+        // stops being tracked even if it starts afterwards.
+        let request = tracker.dispatch();
+        let request_id = request.id();
+        drop(request);
+        tracker.started(request_id, reuse);
+        assert!(tracker.0.started.lock().unwrap().is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_once_body_is_sent() {
+        let buf = SharedBuf::default();
+        let log = Arc::new(AccessLog {
+            format: AccessLogFormat::Json,
+            writer: Some(WriterThread::spawn(Box::new(buf.clone()), 16).unwrap()),
+        });
+        let req = Request::builder().uri("/").body(()).unwrap();
+        let pending = log.start(&req, "127.0.0.1:1".parse().unwrap());
+        let res = pending.finish(RouteComponent::with_response_extension(
+            Response::new(spin_http::body::full("hello".into())),
+            "hello",
+        ));
+        assert!(buf.0.lock().unwrap().is_empty());
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+        // Wait for the writer thread to write the line.
+        drop(log);
+        let line = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(line.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["bytes"], 5);
+        assert_eq!(json["component"], "hello");
+    }
+
+    /// A writer which waits for the gate to open before each write.
+    struct Gated {
+        gate: Arc<Mutex<()>>,
+        buf: SharedBuf,
+    }
+
+    impl Write for Gated {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let _open = self.gate.lock().unwrap();
+            self.buf.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn drops_lines_when_the_writer_falls_behind() {
+        let gate = Arc::new(Mutex::new(()));
+        let buf = SharedBuf::default();
+        let closed = gate.lock().unwrap();
+        let writer = Gated {
+            gate: gate.clone(),
+            buf: buf.clone(),
+        };
+        let writer = WriterThread::spawn(Box::new(writer), 2).unwrap();
+        for line in 0..10 {
+            writer.send(line.to_string());
+        }
+        // Two lines wait in the channel, and the writer may hold a third.
+        let dropped = writer.dropped.load(Ordering::Relaxed);
+        assert!((7..=8).contains(&dropped), "{dropped}");
+
+        drop(closed);
+        let WriterThread { lines, thread, .. } = writer;
+        drop(lines);
+        thread.join().unwrap();
+        let written = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        // The lines sent once the log was full are the ones dropped.
+        let expected = (0..10 - dropped).map(|line| format!("{line}\n"));
+        assert_eq!(written, expected.collect::<String>());
+    }
+}
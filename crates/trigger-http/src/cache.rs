@@ -0,0 +1,1285 @@
+//! A shared cache of component responses, following the rules for shared
+//! caches in RFC 9111.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use anyhow::Context as _;
+use bytes::{Bytes, BytesMut};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, header};
+use http_body_util::BodyExt;
+use hyper::body::{Body as _, Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spin_http::{
+    body,
+    config::{CacheBackend, CacheConfig},
+};
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::Body;
+
+/// The statuses which may be cached, given explicit freshness information.
+const CACHEABLE_STATUSES: &[u16] = &[200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Response headers which are not stored.
+const UNSTORED_HEADERS: &[HeaderName] = &[
+    header::AGE,
+    header::CONNECTION,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::TE,
+    header::TRAILER,
+];
+
+/// Headers sent with a `304 Not Modified` response to a conditional request.
+const NOT_MODIFIED_HEADERS: &[HeaderName] = &[
+    header::AGE,
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::VARY,
+];
+
+/// The directory in the state directory which holds disk caches.
+const DISK_CACHE_DIR: &str = "http-cache";
+
+/// A cache of a trigger's responses.
+pub(crate) struct ResponseCache {
+    /// Where entries are stored, for a disk cache.
+    dir: Option<PathBuf>,
+    max_size: u64,
+    index: Mutex<Index>,
+}
+
+impl ResponseCache {
+    /// Creates the cache for a trigger. A disk cache is stored in the state
+    /// directory, and is loaded from any previous run.
+    pub fn new(config: &CacheConfig, name: &str, state_dir: Option<&Path>) -> anyhow::Result<Self> {
+        let cache = Self {
+            dir: None,
+            max_size: config.max_size,
+            index: Default::default(),
+        };
+        match config.backend {
+            CacheBackend::Memory => Ok(cache),
+            CacheBackend::Disk => {
+                let state_dir = state_dir
+                    .context("a disk cache requires a state directory, but none is available")?;
+                let dir = state_dir.join(DISK_CACHE_DIR).join(dir_name(name));
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("failed to create cache dir {}", dir.display()))?;
+                let cache = Self {
+                    dir: Some(dir),
+                    ..cache
+                };
+                cache.load()?;
+                Ok(cache)
+            }
+        }
+    }
+
+    /// Handles a request, serving it from the cache if possible and otherwise
+    /// by calling `respond`, storing the response if it may be cached.
+    ///
+    /// The request URI must be absolute.
+    pub async fn handle<Fut>(
+        self: &Arc<Self>,
+        mut req: Request<Body>,
+        respond: impl FnOnce(Request<Body>) -> Fut,
+    ) -> anyhow::Result<Response<Body>>
+    where
+        Fut: Future<Output = anyhow::Result<Response<Body>>>,
+    {
+        let key = req.uri().to_string();
+        let method = req.method().clone();
+        if method != Method::GET && method != Method::HEAD {
+            let res = respond(req).await?;
+            // A successful unsafe request may have changed the resource (RFC 9111 section 4.4).
+            if !method.is_safe() && (res.status().is_success() || res.status().is_redirection()) {
+                self.invalidate(&key).await;
+            }
+            return Ok(res);
+        }
+        if req.headers().contains_key(header::UPGRADE) {
+            return respond(req).await;
+        }
+
+        let now = SystemTime::now();
+        let request_cc = CacheControl::from_headers(req.headers());
+        let req_headers = req.headers().clone();
+        let cached = if request_cc.no_cache {
+            None
+        } else {
+            self.lookup(&key, &req_headers)
+        };
+
+        let mut revalidating = None;
+        if let Some(cached) = cached {
+            if cached.meta.is_fresh(now, &request_cc) {
+                match self.body(&cached).await {
+                    Ok(body) => return Ok(cached.meta.response(now, &method, &req_headers, body)),
+                    Err(err) => {
+                        tracing::warn!("Failed to read cached response: {err:#}");
+                        self.remove(cached.id).await;
+                    }
+                }
+            } else if !is_conditional(&req_headers) && cached.meta.add_validators(req.headers_mut())
+            {
+                revalidating = Some(cached);
+            }
+        }
+
+        let path = req.uri().path().to_owned();
+        let res = respond(req).await?;
+
+        if let Some(cached) = revalidating
+            && res.status() == StatusCode::NOT_MODIFIED
+        {
+            let meta = Arc::new(cached.meta.freshened(res.headers(), now));
+            if let Ok(body) = self.body(&cached).await {
+                self.freshen(cached.id, meta.clone()).await;
+                return Ok(meta.response(now, &method, &req_headers, body));
+            }
+            // The stored body has gone, so the 304 can't be used.
+            self.remove(cached.id).await;
+            return Ok(res);
+        }
+
+        if method != Method::GET {
+            return Ok(res);
+        }
+        let Some(meta) =
+            EntryMeta::for_response(key, path, &request_cc, &req_headers, &res, self.max_size)
+        else {
+            return Ok(res);
+        };
+        let cache = self.clone();
+        Ok(res.map(|body| {
+            CachingBody {
+                inner: body,
+                buf: BytesMut::new(),
+                pending: Some((cache, meta)),
+            }
+            .boxed_unsync()
+        }))
+    }
+
+    /// Removes all responses for requests whose path starts with the prefix,
+    /// returning how many were removed.
+    pub async fn purge(&self, prefix: &str) -> usize {
+        let removed = self
+            .index
+            .lock()
+            .unwrap()
+            .remove_where(|meta| meta.path.starts_with(prefix));
+        let count = removed.len();
+        self.remove_files(removed).await;
+        count
+    }
+
+    /// Removes all variants of the response for a URI.
+    async fn invalidate(&self, key: &str) {
+        let removed = self
+            .index
+            .lock()
+            .unwrap()
+            .remove_where(|meta| meta.key == key);
+        self.remove_files(removed).await;
+    }
+
+    /// Finds the stored response matching the request, if any.
+    fn lookup(&self, key: &str, req_headers: &HeaderMap) -> Option<Cached> {
+        let mut index = self.index.lock().unwrap();
+        let id = index
+            .variants
+            .get(key)?
+            .iter()
+            .copied()
+            .find(|id| index.entries[id].meta.matches(req_headers))?;
+        index.touch(id);
+        let entry = &index.entries[&id];
+        Some(Cached {
+            id,
+            meta: entry.meta.clone(),
+            body: entry.body.clone(),
+        })
+    }
+
+    /// Reads the body of a stored response.
+    async fn body(&self, cached: &Cached) -> anyhow::Result<Bytes> {
+        match (&cached.body, &self.dir) {
+            (Some(body), _) => Ok(body.clone()),
+            (None, Some(dir)) => Ok(tokio::fs::read(body_path(dir, cached.id)).await?.into()),
+            (None, None) => anyhow::bail!("memory cache entry has no body"),
+        }
+    }
+
+    /// Stores a response.
+    async fn insert(self: Arc<Self>, meta: EntryMeta, body: Bytes) {
+        let meta = Arc::new(meta);
+        let id = self.index.lock().unwrap().allocate_id();
+        let body = match &self.dir {
+            None => Some(body),
+            Some(dir) => {
+                let written = async {
+                    write_atomic(&body_path(dir, id), &body).await?;
+                    write_atomic(&meta_path(dir, id), &serde_json::to_vec(&*meta)?).await
+                };
+                if let Err(err) = written.await {
+                    tracing::warn!("Failed to write cached response: {err:#}");
+                    self.remove_files(vec![id]).await;
+                    return;
+                }
+                None
+            }
+        };
+        let removed = self
+            .index
+            .lock()
+            .unwrap()
+            .insert(id, meta, body, self.max_size);
+        self.remove_files(removed).await;
+    }
+
+    /// Replaces the metadata of a stored response after revalidation.
+    async fn freshen(&self, id: u64, meta: Arc<EntryMeta>) {
+        if let Some(dir) = &self.dir {
+            let written =
+                async { write_atomic(&meta_path(dir, id), &serde_json::to_vec(&*meta)?).await };
+            if let Err(err) = written.await {
+                tracing::warn!("Failed to write cached response: {err:#}");
+                return;
+            }
+        }
+        if let Some(entry) = self.index.lock().unwrap().entries.get_mut(&id) {
+            entry.meta = meta;
+        }
+    }
+
+    async fn remove(&self, id: u64) {
+        if self.index.lock().unwrap().remove(id) {
+            self.remove_files(vec![id]).await;
+        }
+    }
+
+    async fn remove_files(&self, ids: Vec<u64>) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        for id in ids {
+            _ = tokio::fs::remove_file(meta_path(dir, id)).await;
+            _ = tokio::fs::remove_file(body_path(dir, id)).await;
+        }
+    }
+
+    /// Loads the entries of a disk cache, removing any which are incomplete.
+    fn load(&self) -> anyhow::Result<()> {
+        let dir = self.dir.as_deref().context("not a disk cache")?;
+        let mut stored = Vec::new();
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            let id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            match (id, path.extension().and_then(|ext| ext.to_str())) {
+                (Some(id), Some("json")) => {
+                    let meta = std::fs::read(&path)
+                        .ok()
+                        .and_then(|meta| serde_json::from_slice::<EntryMeta>(&meta).ok());
+                    let body_len = std::fs::metadata(body_path(dir, id)).map(|m| m.len());
+                    match meta {
+                        Some(meta) if body_len.is_ok_and(|len| len == meta.size) => {
+                            stored.push((id, meta))
+                        }
+                        _ => {
+                            _ = std::fs::remove_file(&path);
+                            _ = std::fs::remove_file(body_path(dir, id));
+                        }
+                    }
+                }
+                (Some(_), Some("body")) => {}
+                _ => _ = std::fs::remove_file(&path),
+            }
+        }
+        // Entries were written in ID order, so this roughly preserves recency.
+        stored.sort_by_key(|(id, _)| *id);
+
+        let mut index = self.index.lock().unwrap();
+        let mut removed = Vec::new();
+        for (id, meta) in stored {
+            index.next_id = index.next_id.max(id + 1);
+            removed.extend(index.insert(id, Arc::new(meta), None, self.max_size));
+        }
+        // Bodies whose metadata was never written.
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            let id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if id.is_some_and(|id| !index.entries.contains_key(&id)) {
+                _ = std::fs::remove_file(&path);
+            }
+        }
+        drop(index);
+        for id in removed {
+            _ = std::fs::remove_file(meta_path(dir, id));
+            _ = std::fs::remove_file(body_path(dir, id));
+        }
+        Ok(())
+    }
+}
+
+/// Handles a request to purge cached responses:
+/// `POST /.well-known/spin/cache/purge?prefix=PATH`, authorized by a bearer token.
+pub(crate) async fn purge<'a, B>(
+    caches: impl IntoIterator<Item = &'a Arc<ResponseCache>>,
+    req: &Request<B>,
+    token: &str,
+) -> anyhow::Result<Response<Body>> {
+    if req.method() != Method::POST {
+        return Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, "POST")
+            .body(body::empty())?);
+    }
+    if !is_authorized(req.headers(), token) {
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .body(body::empty())?);
+    }
+    let prefix = req.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(name, _)| name == "prefix")
+            .map(|(_, value)| value.into_owned())
+    });
+    let Some(prefix) = prefix.filter(|prefix| prefix.starts_with('/')) else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(body::full("a `prefix` path parameter is required".into()))?);
+    };
+
+    let mut purged = 0;
+    for cache in caches {
+        purged += cache.purge(&prefix).await;
+    }
+    tracing::info!("Purged {purged} cached responses under {prefix}");
+    let body = serde_json::to_vec(&serde_json::json!({ "purged": purged }))?;
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body::full(body.into()))?)
+}
+
+/// Returns whether the request has the purge token as a bearer token.
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(provided) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare digests so the comparison doesn't reveal how much of the token matched.
+    Sha256::digest(provided.trim()) == Sha256::digest(token)
+}
+
+/// The cache index, which holds the metadata (and for a memory cache, the
+/// bodies) of stored responses.
+#[derive(Default)]
+struct Index {
+    entries: HashMap<u64, Entry>,
+    /// Cache key -> entry IDs of the stored variants of the response.
+    variants: HashMap<String, Vec<u64>>,
+    /// Last use -> entry ID, least recently used first.
+    lru: BTreeMap<u64, u64>,
+    /// The total size of stored bodies.
+    size: u64,
+    next_id: u64,
+    clock: u64,
+}
+
+impl Index {
+    fn allocate_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Marks an entry as most recently used.
+    fn touch(&mut self, id: u64) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&id) {
+            self.lru.remove(&entry.last_used);
+            entry.last_used = self.clock;
+            self.lru.insert(self.clock, id);
+        }
+    }
+
+    /// Inserts an entry, replacing any for the same variant and evicting the
+    /// least recently used entries to stay within the maximum size. Returns
+    /// the IDs of the removed entries.
+    fn insert(
+        &mut self,
+        id: u64,
+        meta: Arc<EntryMeta>,
+        body: Option<Bytes>,
+        max_size: u64,
+    ) -> Vec<u64> {
+        let mut removed =
+            self.remove_where(|stored| stored.key == meta.key && stored.vary == meta.vary);
+        self.size += meta.size;
+        self.variants.entry(meta.key.clone()).or_default().push(id);
+        self.entries.insert(
+            id,
+            Entry {
+                meta,
+                body,
+                last_used: 0,
+            },
+        );
+        self.touch(id);
+        while self.size > max_size {
+            let Some((_, &lru)) = self.lru.first_key_value() else {
+                break;
+            };
+            self.remove(lru);
+            removed.push(lru);
+        }
+        removed
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        let Some(entry) = self.entries.remove(&id) else {
+            return false;
+        };
+        self.lru.remove(&entry.last_used);
+        self.size -= entry.meta.size;
+        if let Some(ids) = self.variants.get_mut(&entry.meta.key) {
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.variants.remove(&entry.meta.key);
+            }
+        }
+        true
+    }
+
+    fn remove_where(&mut self, f: impl Fn(&EntryMeta) -> bool) -> Vec<u64> {
+        let ids: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| f(&entry.meta))
+            .map(|(id, _)| *id)
+            .collect();
+        for &id in &ids {
+            self.remove(id);
+        }
+        ids
+    }
+}
+
+struct Entry {
+    meta: Arc<EntryMeta>,
+    /// The body, for a memory cache.
+    body: Option<Bytes>,
+    last_used: u64,
+}
+
+/// A stored response found for a request.
+struct Cached {
+    id: u64,
+    meta: Arc<EntryMeta>,
+    body: Option<Bytes>,
+}
+
+/// A stored response, without its body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct EntryMeta {
+    /// The request URI.
+    key: String,
+    /// The request path, for purging.
+    path: String,
+    /// The request headers named by the response's `Vary` header, with their values.
+    vary: Vec<(String, Option<String>)>,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// When the response was received, or last revalidated.
+    received: SystemTime,
+    /// The response's age, in seconds, when it was received.
+    initial_age: u64,
+    /// How long, in seconds, the response is fresh for.
+    lifetime: u64,
+    /// Whether the response must be revalidated before each use.
+    revalidate: bool,
+    /// The size of the body.
+    size: u64,
+}
+
+impl EntryMeta {
+    /// Describes a response to store, or returns `None` if it may not be stored.
+    fn for_response(
+        key: String,
+        path: String,
+        request_cc: &CacheControl,
+        req_headers: &HeaderMap,
+        res: &Response<Body>,
+        max_size: u64,
+    ) -> Option<Self> {
+        let res_headers = res.headers();
+        let response_cc = CacheControl::from_headers(res_headers);
+        if !CACHEABLE_STATUSES.contains(&res.status().as_u16())
+            || request_cc.no_store
+            || response_cc.no_store
+            || response_cc.private
+            || res_headers.contains_key(header::SET_COOKIE)
+        {
+            return None;
+        }
+        // Responses to authorized requests are specific to the user unless
+        // explicitly marked otherwise (RFC 9111 section 3.5).
+        if req_headers.contains_key(header::AUTHORIZATION)
+            && !(response_cc.public
+                || response_cc.s_maxage.is_some()
+                || response_cc.must_revalidate)
+        {
+            return None;
+        }
+        if res
+            .body()
+            .size_hint()
+            .exact()
+            .is_some_and(|size| size > max_size)
+        {
+            return None;
+        }
+
+        let mut vary = Vec::new();
+        for name in tokens(res_headers, header::VARY) {
+            if name == "*" {
+                return None;
+            }
+            let name = name.to_ascii_lowercase();
+            let value = header_value(req_headers, &name);
+            vary.push((name, value));
+        }
+
+        let headers = res_headers
+            .iter()
+            .filter(|(name, _)| !UNSTORED_HEADERS.contains(name))
+            .map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect::<Option<Vec<_>>>()?;
+
+        let meta = Self {
+            key,
+            path,
+            vary,
+            status: res.status().as_u16(),
+            headers,
+            received: SystemTime::now(),
+            initial_age: age_header(res_headers),
+            lifetime: 0,
+            revalidate: false,
+            size: 0,
+        }
+        .with_freshness(res_headers)?;
+        Some(meta)
+    }
+
+    /// Sets the freshness of the response from its headers, or returns `None`
+    /// if it has no explicit freshness information or can never be used.
+    fn with_freshness(mut self, res_headers: &HeaderMap) -> Option<Self> {
+        let response_cc = CacheControl::from_headers(res_headers);
+        // A `no-cache` response may be stored without a lifetime, as it is
+        // always revalidated before use.
+        self.lifetime =
+            freshness_lifetime(&response_cc, res_headers).or(response_cc.no_cache.then_some(0))?;
+        self.revalidate = response_cc.no_cache;
+        let has_validator = res_headers.contains_key(header::ETAG)
+            || res_headers.contains_key(header::LAST_MODIFIED);
+        if (self.lifetime == 0 || self.revalidate) && !has_validator {
+            return None;
+        }
+        Some(self)
+    }
+
+    /// Returns whether the response may be used for a request with the given headers.
+    fn matches(&self, req_headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| header_value(req_headers, name) == *value)
+    }
+
+    /// The response's current age, in seconds.
+    fn age(&self, now: SystemTime) -> u64 {
+        let resident = now.duration_since(self.received).unwrap_or_default();
+        self.initial_age + resident.as_secs()
+    }
+
+    fn is_fresh(&self, now: SystemTime, request_cc: &CacheControl) -> bool {
+        let age = self.age(now);
+        !self.revalidate
+            && age < self.lifetime
+            && request_cc.max_age.is_none_or(|max_age| age <= max_age)
+    }
+
+    fn header(&self, name: &HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(stored, _)| stored.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Makes the request conditional on the stored response's validators,
+    /// returning whether it had any.
+    fn add_validators(&self, req_headers: &mut HeaderMap) -> bool {
+        let mut added = false;
+        for (validator, condition) in [
+            (header::ETAG, header::IF_NONE_MATCH),
+            (header::LAST_MODIFIED, header::IF_MODIFIED_SINCE),
+        ] {
+            if let Some(value) = self.header(&validator).and_then(|v| v.parse().ok()) {
+                req_headers.insert(condition, value);
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Updates the stored response with the headers of a `304 Not Modified`
+    /// response to a revalidation request (RFC 9111 section 4.3.4).
+    fn freshened(&self, not_modified: &HeaderMap, now: SystemTime) -> Self {
+        let excluded = |name: &str| {
+            name.eq_ignore_ascii_case(header::CONTENT_LENGTH.as_str())
+                || UNSTORED_HEADERS.iter().any(|h| h == name)
+        };
+        let mut headers: Vec<_> = self
+            .headers
+            .iter()
+            .filter(|(name, _)| excluded(name) || !not_modified.contains_key(name.as_str()))
+            .cloned()
+            .collect();
+        headers.extend(
+            not_modified
+                .iter()
+                .filter(|(name, _)| !excluded(name.as_str()))
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                }),
+        );
+        let meta = Self {
+            headers,
+            received: now,
+            initial_age: age_header(not_modified),
+            ..self.clone()
+        };
+        let header_map = meta.header_map();
+        meta.clone().with_freshness(&header_map).unwrap_or(Self {
+            // The response may no longer be stored, but can still be used this once.
+            lifetime: 0,
+            ..meta
+        })
+    }
+
+    fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(value).ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Builds the response to a request from the stored response.
+    fn response(
+        &self,
+        now: SystemTime,
+        method: &Method,
+        req_headers: &HeaderMap,
+        body: Bytes,
+    ) -> Response<Body> {
+        let mut headers = self.header_map();
+        headers.insert(header::AGE, HeaderValue::from(self.age(now)));
+
+        let not_modified = self
+            .header(&header::ETAG)
+            .is_some_and(|etag| if_none_match(req_headers, etag));
+        let mut res = if not_modified {
+            let mut kept = HeaderMap::new();
+            for name in NOT_MODIFIED_HEADERS {
+                for value in headers.get_all(name) {
+                    kept.append(name.clone(), value.clone());
+                }
+            }
+            headers = kept;
+            let mut res = Response::new(body::empty());
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            res
+        } else {
+            let body = if method == Method::HEAD {
+                body::empty()
+            } else {
+                body::full(body)
+            };
+            let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+            if status != StatusCode::NO_CONTENT {
+                headers.insert(header::CONTENT_LENGTH, HeaderValue::from(self.size));
+            }
+            let mut res = Response::new(body);
+            *res.status_mut() = status;
+            res
+        };
+        *res.headers_mut() = headers;
+        res
+    }
+}
+
+/// A response body which stores the response in the cache once it has been
+/// sent in full.
+struct CachingBody {
+    inner: Body,
+    buf: BytesMut,
+    pending: Option<(Arc<ResponseCache>, EntryMeta)>,
+}
+
+impl hyper::body::Body for CachingBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                let max_size = self.pending.as_ref().map(|(cache, _)| cache.max_size);
+                match (frame.data_ref(), max_size) {
+                    (Some(data), Some(max_size))
+                        if (self.buf.len() + data.len()) as u64 <= max_size =>
+                    {
+                        self.buf.extend_from_slice(data)
+                    }
+                    (_, None) => {}
+                    // Too large to store, or trailers, which are not stored.
+                    _ => self.pending = None,
+                }
+            }
+            Poll::Ready(None) => {
+                if let Some((cache, mut meta)) = self.pending.take() {
+                    let body = std::mem::take(&mut self.buf).freeze();
+                    meta.size = body.len() as u64;
+                    tokio::spawn(cache.insert(meta, body));
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.pending = None,
+            Poll::Pending => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// `Cache-Control` directives, from a request or response.
+#[derive(Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    must_revalidate: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut cc = Self::default();
+        for directive in tokens(headers, header::CACHE_CONTROL) {
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = || arg.and_then(|arg| arg.parse().ok());
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => cc.no_store = true,
+                // Qualified forms (`no-cache="set-cookie"`) are treated as unqualified.
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                // An invalid max-age means the response is stale.
+                "max-age" => cc.max_age = Some(seconds().unwrap_or(0)),
+                "s-maxage" => cc.s_maxage = Some(seconds().unwrap_or(0)),
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+/// How long, in seconds, a response is fresh for, if it says.
+fn freshness_lifetime(cc: &CacheControl, headers: &HeaderMap) -> Option<u64> {
+    if let Some(lifetime) = cc.s_maxage.or(cc.max_age) {
+        return Some(lifetime);
+    }
+    let expires = headers.get(header::EXPIRES)?;
+    // An invalid date, such as `0`, means the response has already expired.
+    let Some(expires) = expires
+        .to_str()
+        .ok()
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+    else {
+        return Some(0);
+    };
+    let date = headers
+        .get(header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .unwrap_or_else(SystemTime::now);
+    Some(expires.duration_since(date).unwrap_or_default().as_secs())
+}
+
+fn age_header(headers: &HeaderMap) -> u64 {
+    headers
+        .get(header::AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Returns whether the request is conditional, so must be answered by the component
+/// if it can't be answered from a fresh response.
+fn is_conditional(headers: &HeaderMap) -> bool {
+    [
+        header::IF_MATCH,
+        header::IF_NONE_MATCH,
+        header::IF_MODIFIED_SINCE,
+        header::IF_UNMODIFIED_SINCE,
+        header::IF_RANGE,
+    ]
+    .iter()
+    .any(|name| headers.contains_key(name))
+}
+
+/// Returns whether the request's `If-None-Match` header matches the entity tag,
+/// using weak comparison.
+fn if_none_match(req_headers: &HeaderMap, etag: &str) -> bool {
+    let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let etag = weak(etag);
+    tokens(req_headers, header::IF_NONE_MATCH).any(|tag| tag == "*" || weak(tag) == etag)
+}
+
+/// The comma-separated values of a header.
+fn tokens(headers: &HeaderMap, name: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+}
+
+/// The values of a request header, combined into one.
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// A directory name for a trigger's cache.
+fn dir_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn meta_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{id}.json"))
+}
+
+fn body_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{id}.body"))
+}
+
+/// Writes a file so that it is never seen partly written.
+async fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn cache(max_size: u64) -> Arc<ResponseCache> {
+        let config = CacheConfig {
+            backend: CacheBackend::Memory,
+            max_size,
+        };
+        Arc::new(ResponseCache::new(&config, "test", None).unwrap())
+    }
+
+    fn request(method: Method, uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+        let mut req = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(body::empty()).unwrap()
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        request(Method::GET, uri, &[])
+    }
+
+    /// A component which counts its calls and responds with the given headers.
+    struct Component {
+        calls: AtomicUsize,
+        headers: Vec<(&'static str, &'static str)>,
+        status: StatusCode,
+    }
+
+    impl Component {
+        fn new(headers: &[(&'static str, &'static str)]) -> Self {
+            Self {
+                calls: AtomicUsize::new(0),
+                headers: headers.to_vec(),
+                status: StatusCode::OK,
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+
+        async fn respond(&self, req: Request<Body>) -> anyhow::Result<Response<Body>> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut res = Response::builder().status(self.status);
+            for (name, value) in &self.headers {
+                res = res.header(*name, *value);
+            }
+            if req
+                .headers()
+                .get(header::IF_NONE_MATCH)
+                .is_some_and(|v| v == "\"v1\"")
+            {
+                return Ok(res.status(StatusCode::NOT_MODIFIED).body(body::empty())?);
+            }
+            Ok(res.body(body::full(format!("call {call}").into()))?)
+        }
+    }
+
+    async fn send(
+        cache: &Arc<ResponseCache>,
+        component: &Component,
+        req: Request<Body>,
+    ) -> (StatusCode, HeaderMap, String) {
+        let res = cache
+            .handle(req, |req| component.respond(req))
+            .await
+            .unwrap();
+        let (parts, body) = res.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        // Let the response be stored.
+        tokio::task::yield_now().await;
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parses_cache_control() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::CACHE_CONTROL, "public, max-age=60".parse().unwrap());
+        headers.append(
+            header::CACHE_CONTROL,
+            "S-MaxAge=\"120\", no-cache".parse().unwrap(),
+        );
+        let cc = CacheControl::from_headers(&headers);
+        assert!(cc.public && cc.no_cache);
+        assert!(!cc.private && !cc.no_store);
+        assert_eq!(cc.max_age, Some(60));
+        assert_eq!(cc.s_maxage, Some(120));
+        assert_eq!(freshness_lifetime(&cc, &headers), Some(120));
+    }
+
+    #[test]
+    fn computes_lifetime_from_expires() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::DATE,
+            "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap(),
+        );
+        headers.insert(
+            header::EXPIRES,
+            "Sun, 06 Nov 1994 08:59:37 GMT".parse().unwrap(),
+        );
+        let cc = CacheControl::from_headers(&headers);
+        assert_eq!(freshness_lifetime(&cc, &headers), Some(600));
+
+        headers.insert(header::EXPIRES, "0".parse().unwrap());
+        assert_eq!(freshness_lifetime(&cc, &headers), Some(0));
+
+        headers.remove(header::EXPIRES);
+        assert_eq!(freshness_lifetime(&cc, &headers), None);
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_responses_from_cache() {
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+
+        let (status, _, body) = send(&cache, &component, get("http://localhost/a")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "call 1"));
+        let (_, headers, body) = send(&cache, &component, get("http://localhost/a")).await;
+        assert_eq!(body, "call 1");
+        assert!(headers.contains_key(header::AGE));
+        let (_, _, body) = send(&cache, &component, get("http://localhost/a?b")).await;
+        assert_eq!(body, "call 2");
+
+        let (_, headers, body) = send(
+            &cache,
+            &component,
+            request(Method::HEAD, "http://localhost/a", &[]),
+        )
+        .await;
+        assert_eq!(body, "");
+        assert_eq!(headers[header::CONTENT_LENGTH], "6");
+
+        let no_cache = request(
+            Method::GET,
+            "http://localhost/a",
+            &[("cache-control", "no-cache")],
+        );
+        let (_, _, body) = send(&cache, &component, no_cache).await;
+        assert_eq!(body, "call 3");
+        assert_eq!(component.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_store_uncacheable_responses() {
+        let cases: [&[(&str, &str)]; 6] = [
+            &[],
+            &[("cache-control", "max-age=60, private")],
+            &[("cache-control", "no-store, max-age=60")],
+            &[("cache-control", "max-age=60"), ("set-cookie", "a=b")],
+            &[("cache-control", "max-age=60"), ("vary", "*")],
+            &[("cache-control", "max-age=0")],
+        ];
+        for headers in cases {
+            let cache = cache(1024);
+            let component = Component::new(headers);
+            send(&cache, &component, get("http://localhost/")).await;
+            send(&cache, &component, get("http://localhost/")).await;
+            assert_eq!(component.calls(), 2, "{headers:?} was cached");
+        }
+
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+        let authorized = || request(Method::GET, "http://localhost/", &[("authorization", "x")]);
+        send(&cache, &component, authorized()).await;
+        send(&cache, &component, authorized()).await;
+        assert_eq!(component.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn stores_variants() {
+        let cache = cache(1024);
+        let component =
+            Component::new(&[("cache-control", "max-age=60"), ("vary", "Accept-Language")]);
+        let lang = |lang| {
+            request(
+                Method::GET,
+                "http://localhost/",
+                &[("accept-language", lang)],
+            )
+        };
+
+        assert_eq!(send(&cache, &component, lang("en")).await.2, "call 1");
+        assert_eq!(send(&cache, &component, lang("fr")).await.2, "call 2");
+        assert_eq!(send(&cache, &component, lang("en")).await.2, "call 1");
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/")).await.2,
+            "call 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_etag() {
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "no-cache"), ("etag", "\"v1\"")]);
+
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/")).await.2,
+            "call 1"
+        );
+        // The component is asked whether the stored response is still valid, and says it is.
+        let (status, _, body) = send(&cache, &component, get("http://localhost/")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "call 1"));
+        assert_eq!(component.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_requests() {
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "max-age=60"), ("etag", "W/\"v2\"")]);
+        send(&cache, &component, get("http://localhost/")).await;
+
+        let conditional = request(
+            Method::GET,
+            "http://localhost/",
+            &[("if-none-match", "\"v1\", \"v2\"")],
+        );
+        let (status, headers, body) = send(&cache, &component, conditional).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert_eq!(body, "");
+        assert_eq!(headers[header::ETAG], "W/\"v2\"");
+        assert_eq!(component.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn unsafe_requests_invalidate() {
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+        send(&cache, &component, get("http://localhost/a")).await;
+        send(
+            &cache,
+            &component,
+            request(Method::POST, "http://localhost/a", &[]),
+        )
+        .await;
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/a")).await.2,
+            "call 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        // Room for two six-byte bodies.
+        let cache = cache(12);
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+        send(&cache, &component, get("http://localhost/a")).await;
+        send(&cache, &component, get("http://localhost/b")).await;
+        send(&cache, &component, get("http://localhost/a")).await;
+        send(&cache, &component, get("http://localhost/c")).await;
+        assert_eq!(component.calls(), 3);
+
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/a")).await.2,
+            "call 1"
+        );
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/b")).await.2,
+            "call 4"
+        );
+    }
+
+    #[tokio::test]
+    async fn purges_by_prefix() {
+        let cache = cache(1024);
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+        for uri in [
+            "http://localhost/api/a",
+            "http://localhost/api/b",
+            "http://localhost/other",
+        ] {
+            send(&cache, &component, get(uri)).await;
+        }
+
+        let purge_request =
+            |auth: &str, uri: &str| request(Method::POST, uri, &[("authorization", auth)]);
+        let uri = "/.well-known/spin/cache/purge?prefix=%2Fapi%2F";
+        let res = purge([&cache], &purge_request("Bearer wrong", uri), "secret")
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = purge([&cache], &purge_request("Bearer secret", uri), "secret")
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"purged":2}"#);
+
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/api/a"))
+                .await
+                .2,
+            "call 4"
+        );
+        assert_eq!(
+            send(&cache, &component, get("http://localhost/other"))
+                .await
+                .2,
+            "call 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn disk_cache_persists() {
+        let state_dir =
+            std::env::temp_dir().join(format!("spin-http-cache-{}", std::process::id()));
+        let config = CacheConfig {
+            backend: CacheBackend::Disk,
+            max_size: 1024,
+        };
+        let component = Component::new(&[("cache-control", "max-age=60")]);
+
+        let cache =
+            Arc::new(ResponseCache::new(&config, "my-component", Some(&state_dir)).unwrap());
+        send(&cache, &component, get("http://localhost/")).await;
+        // Wait for the response to be written.
+        for _ in 0..100 {
+            if !cache.index.lock().unwrap().entries.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let cache =
+            Arc::new(ResponseCache::new(&config, "my-component", Some(&state_dir)).unwrap());
+        let (_, _, body) = send(&cache, &component, get("http://localhost/")).await;
+        assert_eq!(body, "call 1");
+        assert_eq!(component.calls(), 1);
+
+        std::fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
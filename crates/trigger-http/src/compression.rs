@@ -0,0 +1,355 @@
+//! Compression of component responses.
+
+use async_compression::{
+    Level,
+    tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder},
+};
+use futures::{StreamExt, TryStreamExt};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, header};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Body as _, Frame};
+use spin_http::config::{CompressionAlgorithm, CompressionConfig};
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::Body;
+
+/// The Brotli quality used. The default (11) is too slow for responses
+/// which are compressed on every request.
+const BROTLI_QUALITY: i32 = 4;
+
+/// The compression settings for a component.
+pub(crate) struct Compression {
+    algorithms: Vec<CompressionAlgorithm>,
+    min_size: u64,
+    content_types: Vec<String>,
+}
+
+impl Compression {
+    /// Returns the compression settings, if compression is enabled.
+    pub fn new(config: &CompressionConfig) -> Option<Self> {
+        if !config.enabled || config.algorithms.is_empty() {
+            return None;
+        }
+        Some(Self {
+            algorithms: config.algorithms.clone(),
+            min_size: config.min_size,
+            content_types: config
+                .content_types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .collect(),
+        })
+    }
+
+    /// Chooses the algorithm to compress the response to the request with,
+    /// based on its `Accept-Encoding` header.
+    pub fn negotiate<B>(&self, req: &Request<B>) -> Option<CompressionAlgorithm> {
+        if req.method() == Method::HEAD {
+            return None;
+        }
+        let accepted = accepted_encodings(req.headers());
+        let quality = |coding: &str| {
+            let find = |name: &str| accepted.iter().find(|(c, _)| c == name).map(|(_, q)| *q);
+            find(coding).or_else(|| find("*")).unwrap_or(0.0)
+        };
+        // Prefer the client's highest-quality coding, then our own order.
+        let mut best: Option<(CompressionAlgorithm, f32)> = None;
+        for algorithm in &self.algorithms {
+            let q = quality(algorithm.content_coding());
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((*algorithm, q));
+            }
+        }
+        best.map(|(algorithm, _)| algorithm)
+    }
+
+    /// Compresses the response with the negotiated algorithm, if the
+    /// response is eligible for compression.
+    pub fn apply(
+        &self,
+        mut res: Response<Body>,
+        algorithm: Option<CompressionAlgorithm>,
+    ) -> Response<Body> {
+        if !self.is_compressible(&res) {
+            return res;
+        }
+        // The response depends on `Accept-Encoding` whether or not this
+        // particular client gets it compressed.
+        add_vary_accept_encoding(res.headers_mut());
+        let Some(algorithm) = algorithm else {
+            return res;
+        };
+
+        let headers = res.headers_mut();
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(algorithm.content_coding()),
+        );
+        headers.remove(header::CONTENT_LENGTH);
+        if let Some(etag) = headers.get(header::ETAG)
+            && !etag.as_bytes().starts_with(b"W/")
+        {
+            // The compressed representation is not byte-for-byte identical.
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                headers.insert(header::ETAG, weak);
+            }
+        }
+
+        res.map(|body| compress_body(body, algorithm))
+    }
+
+    fn is_compressible(&self, res: &Response<Body>) -> bool {
+        let status = res.status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::PARTIAL_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            return false;
+        }
+        let headers = res.headers();
+        if headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        if no_transform {
+            return false;
+        }
+        let Some(content_type) = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return false;
+        };
+        if !self.is_compressible_type(content_type) {
+            return false;
+        }
+        let size = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .or_else(|| res.body().size_hint().exact());
+        // Bodies of unknown size are streamed, so are assumed to be large enough.
+        size.is_none_or(|size| size >= self.min_size)
+    }
+
+    fn is_compressible_type(&self, content_type: &str) -> bool {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        // Compression buffers output, which would hold back server-sent events.
+        if essence == "text/event-stream" {
+            return false;
+        }
+        self.content_types
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(top_level) => essence
+                    .split_once('/')
+                    .is_some_and(|(essence_top_level, _)| essence_top_level == top_level),
+                None => *pattern == essence,
+            })
+    }
+}
+
+/// Parses an `Accept-Encoding` header into (lowercased coding, quality) pairs.
+fn accepted_encodings(headers: &HeaderMap) -> Vec<(String, f32)> {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let coding = params.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((coding, quality))
+        })
+        .collect()
+}
+
+fn add_vary_accept_encoding(headers: &mut HeaderMap) {
+    let already_varies = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|name| {
+            let name = name.trim();
+            name == "*" || name.eq_ignore_ascii_case("accept-encoding")
+        });
+    if !already_varies {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+fn compress_body(body: Body, algorithm: CompressionAlgorithm) -> Body {
+    let reader = StreamReader::new(
+        body.into_data_stream()
+            .map_err(|err| std::io::Error::other(format!("{err:?}"))),
+    );
+    let encoder: Box<dyn AsyncRead + Send + Unpin> = match algorithm {
+        CompressionAlgorithm::Brotli => Box::new(BrotliEncoder::with_quality(
+            reader,
+            Level::Precise(BROTLI_QUALITY),
+        )),
+        CompressionAlgorithm::Gzip => Box::new(GzipEncoder::new(reader)),
+        CompressionAlgorithm::Zstd => Box::new(ZstdEncoder::new(reader)),
+    };
+    let frames = ReaderStream::new(encoder).map(|chunk| match chunk {
+        Ok(data) => Ok(Frame::data(data)),
+        Err(err) => {
+            tracing::warn!("Error compressing response body: {err:?}");
+            Err(ErrorCode::InternalError(Some(format!(
+                "error compressing response body: {err}"
+            ))))
+        }
+    });
+    StreamBody::new(frames).boxed_unsync()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipDecoder;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn compression() -> Compression {
+        Compression::new(&CompressionConfig::default()).unwrap()
+    }
+
+    fn request(accept_encoding: &str) -> Request<()> {
+        Request::builder()
+            .header(header::ACCEPT_ENCODING, accept_encoding)
+            .body(())
+            .unwrap()
+    }
+
+    fn response(content_type: &str, body: &str) -> Response<Body> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(spin_http::body::full(body.to_owned().into()))
+            .unwrap()
+    }
+
+    #[test]
+    fn negotiates_preferred_algorithm() {
+        let compression = compression();
+        let negotiate = |accept_encoding| compression.negotiate(&request(accept_encoding));
+        assert_eq!(
+            negotiate("gzip, deflate, br, zstd"),
+            Some(CompressionAlgorithm::Brotli)
+        );
+        assert_eq!(negotiate("gzip"), Some(CompressionAlgorithm::Gzip));
+        assert_eq!(
+            negotiate("br;q=0.5, gzip;q=0.8"),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(negotiate("*"), Some(CompressionAlgorithm::Brotli));
+        assert_eq!(
+            negotiate("br;q=0, *;q=0.1"),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(compression.negotiate(&Request::new(())), None);
+
+        let head = Request::builder()
+            .method(Method::HEAD)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+        assert_eq!(compression.negotiate(&head), None);
+    }
+
+    #[test]
+    fn matches_content_types() {
+        let compression = compression();
+        assert!(compression.is_compressible_type("text/html; charset=utf-8"));
+        assert!(compression.is_compressible_type("Application/JSON"));
+        assert!(compression.is_compressible_type("image/svg+xml"));
+        assert!(!compression.is_compressible_type("image/png"));
+        assert!(!compression.is_compressible_type("text/event-stream"));
+    }
+
+    #[test]
+    fn skips_ineligible_responses() {
+        let compression = compression();
+        let large = "a".repeat(2048);
+
+        let small = compression.apply(
+            response("text/plain", "tiny"),
+            Some(CompressionAlgorithm::Gzip),
+        );
+        assert!(!small.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!small.headers().contains_key(header::VARY));
+
+        let mut encoded = response("text/plain", &large);
+        encoded
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+        let encoded = compression.apply(encoded, Some(CompressionAlgorithm::Gzip));
+        assert_eq!(encoded.headers()[header::CONTENT_ENCODING], "br");
+
+        let image = compression.apply(
+            response("image/png", &large),
+            Some(CompressionAlgorithm::Gzip),
+        );
+        assert!(!image.headers().contains_key(header::CONTENT_ENCODING));
+
+        let uncompressed = compression.apply(response("text/plain", &large), None);
+        assert!(
+            !uncompressed
+                .headers()
+                .contains_key(header::CONTENT_ENCODING)
+        );
+        assert_eq!(uncompressed.headers()[header::VARY], "accept-encoding");
+    }
+
+    #[tokio::test]
+    async fn compresses_response_body() {
+        let large = "hello world ".repeat(200);
+        let mut res = response("text/plain", &large);
+        res.headers_mut()
+            .insert(header::CONTENT_LENGTH, large.len().into());
+        res.headers_mut()
+            .insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+
+        let res = compression().apply(res, Some(CompressionAlgorithm::Gzip));
+
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+
+        let compressed = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(compressed.len() < large.len());
+        let mut decompressed = String::new();
+        GzipDecoder::new(&compressed[..])
+            .read_to_string(&mut decompressed)
+            .await
+            .unwrap();
+        assert_eq!(decompressed, large);
+    }
+}
@@ -0,0 +1,185 @@
+//! Limits on the requests each HTTP trigger handles at once.
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::{Response, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::{Frame, SizeHint};
+use spin_connection_semaphore::{ConnectionPermit, ConnectionSemaphore, LimitedSemaphore};
+use spin_http::{body, config::ConcurrencyConfig};
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::Body;
+
+/// How long, in seconds, rejected clients are asked to wait before retrying.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// The requests a trigger is handling, and those waiting to be handled.
+pub(crate) struct ConcurrencyLimit {
+    semaphore: ConnectionSemaphore,
+    queue_timeout: Duration,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(config: &ConcurrencyConfig, app_id: Arc<str>) -> anyhow::Result<Self> {
+        anyhow::ensure!(config.max > 0, "concurrency max must be at least 1");
+        let queue_timeout = Duration::from_millis(config.queue_timeout_ms);
+        Ok(Self {
+            semaphore: ConnectionSemaphore::new(
+                None,
+                Some(LimitedSemaphore::new(config.max)),
+                "inbound_http",
+                app_id,
+                Some(queue_timeout),
+            ),
+            queue_timeout,
+        })
+    }
+
+    /// Waits, up to the queue timeout, for the trigger to handle fewer
+    /// requests than its limit. Returns `None` if it is still saturated.
+    pub async fn acquire(&self) -> Option<ConnectionPermit> {
+        if self.queue_timeout.is_zero() {
+            return self.semaphore.try_acquire();
+        }
+        self.semaphore.acquire().await.ok()
+    }
+
+    /// Returns a permit only if the trigger can handle another request now.
+    pub fn try_acquire(&self) -> Option<ConnectionPermit> {
+        self.semaphore.try_acquire()
+    }
+}
+
+/// A request's permit, which may be held by more than its response, such as
+/// by the WebSocket connection the request was upgraded to. It is released
+/// once all its holders are done.
+#[derive(Clone)]
+pub(crate) struct SharedPermit {
+    _permit: Arc<ConnectionPermit>,
+}
+
+impl SharedPermit {
+    pub fn new(permit: ConnectionPermit) -> Self {
+        Self {
+            _permit: Arc::new(permit),
+        }
+    }
+}
+
+/// Holds the permit until the response body has been sent (or abandoned).
+pub(crate) fn hold_permit(res: Response<Body>, permit: SharedPermit) -> Response<Body> {
+    res.map(|body| {
+        PermittedBody {
+            inner: body,
+            _permit: permit,
+        }
+        .boxed_unsync()
+    })
+}
+
+/// Creates an HTTP 503 response, for a trigger which is handling as many
+/// requests as it may.
+pub(crate) fn service_unavailable() -> anyhow::Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(http::header::RETRY_AFTER, RETRY_AFTER_SECS)
+        .body(body::empty())?)
+}
+
+/// A response body which holds a trigger's concurrency permit.
+struct PermittedBody {
+    inner: Body,
+    _permit: SharedPermit,
+}
+
+impl hyper::body::Body for PermittedBody {
+    type Data = hyper::body::Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn concurrency_limit(max: usize, queue_timeout_ms: u64) -> ConcurrencyLimit {
+        let config = ConcurrencyConfig {
+            max,
+            queue_timeout_ms,
+        };
+        ConcurrencyLimit::new(&config, "test-app".into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn permits_are_held_by_response_bodies() {
+        let limit = concurrency_limit(1, 0);
+        let permit = limit.acquire().await.unwrap();
+        assert!(limit.acquire().await.is_none());
+
+        let res = hold_permit(Response::new(body::empty()), SharedPermit::new(permit));
+        assert!(limit.try_acquire().is_none());
+        _ = res.into_body().collect().await;
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn shared_permits_are_held_until_every_holder_is_done() {
+        let limit = concurrency_limit(1, 0);
+        let permit = SharedPermit::new(limit.acquire().await.unwrap());
+        let connection = permit.clone();
+
+        let res = hold_permit(Response::new(body::empty()), permit);
+        _ = res.into_body().collect().await;
+        assert!(limit.try_acquire().is_none());
+        drop(connection);
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn requests_wait_in_the_queue() {
+        let limit = concurrency_limit(1, 1000);
+        let permit = limit.acquire().await.unwrap();
+        let (waited, ()) = tokio::join!(limit.acquire(), async { drop(permit) });
+        assert!(waited.is_some());
+
+        let limit = concurrency_limit(1, 10);
+        let _permit = limit.acquire().await.unwrap();
+        assert!(limit.acquire().await.is_none());
+    }
+
+    #[test]
+    fn zero_max_is_rejected() {
+        let config = ConcurrencyConfig {
+            max: 0,
+            queue_timeout_ms: 0,
+        };
+        assert!(ConcurrencyLimit::new(&config, "test-app".into()).is_err());
+    }
+
+    #[test]
+    fn rejections_ask_clients_to_retry() {
+        let res = service_unavailable().unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[http::header::RETRY_AFTER], "1");
+    }
+}
@@ -0,0 +1,397 @@
+//! Host-side CORS handling for HTTP trigger routes.
+
+use anyhow::Context;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, header};
+use spin_http::{body, config::CorsConfig};
+
+use crate::Body;
+
+/// A CORS policy for a route.
+pub(crate) struct Cors {
+    allowed_origins: Allowed<OriginPattern>,
+    allowed_methods: Allowed<Method>,
+    allowed_headers: Allowed<HeaderName>,
+    exposed_headers: Option<HeaderValue>,
+    allow_credentials: bool,
+    max_age: Option<HeaderValue>,
+}
+
+/// Either everything (`*`) or the listed values.
+enum Allowed<T> {
+    Any,
+    List(Vec<T>),
+}
+
+impl<T> Allowed<T> {
+    fn parse(values: &[String], parse: impl Fn(&str) -> anyhow::Result<T>) -> anyhow::Result<Self> {
+        if values.iter().any(|v| v == "*") {
+            return Ok(Self::Any);
+        }
+        Ok(Self::List(
+            values
+                .iter()
+                .map(|v| parse(v))
+                .collect::<anyhow::Result<_>>()?,
+        ))
+    }
+}
+
+enum OriginPattern {
+    Exact(String),
+    /// `scheme://*.domain`, matching any subdomain of the domain.
+    Subdomains {
+        scheme: String,
+        domain: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> anyhow::Result<Self> {
+        let pattern = pattern.trim_end_matches('/').to_ascii_lowercase();
+        let (scheme, host) = pattern
+            .split_once("://")
+            .with_context(|| format!("invalid CORS origin '{pattern}': expected scheme://host"))?;
+        match host.strip_prefix("*.") {
+            Some(domain) => Ok(Self::Subdomains {
+                scheme: scheme.to_owned(),
+                domain: domain.to_owned(),
+            }),
+            None => Ok(Self::Exact(pattern)),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(exact) => *exact == origin,
+            Self::Subdomains { scheme, domain } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(domain.as_str()))
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+        }
+    }
+}
+
+impl Cors {
+    pub fn new(config: &CorsConfig) -> anyhow::Result<Self> {
+        let allowed_origins = Allowed::parse(&config.allowed_origins, OriginPattern::parse)?;
+        // Browsers reject credentialed responses which allow any origin, and
+        // reflecting every origin instead would defeat the policy.
+        anyhow::ensure!(
+            !(config.allow_credentials && matches!(allowed_origins, Allowed::Any)),
+            "CORS allow_credentials cannot be used when any origin ('*') is allowed"
+        );
+        let allowed_methods = Allowed::parse(&config.allowed_methods, |m| {
+            Method::from_bytes(m.as_bytes())
+                .with_context(|| format!("invalid CORS allowed method '{m}'"))
+        })?;
+        let allowed_headers = Allowed::parse(&config.allowed_headers, |h| {
+            HeaderName::from_bytes(h.as_bytes())
+                .with_context(|| format!("invalid CORS allowed header '{h}'"))
+        })?;
+        let exposed_headers = if config.exposed_headers.is_empty() {
+            None
+        } else {
+            let value = config.exposed_headers.join(", ");
+            Some(
+                HeaderValue::from_str(&value)
+                    .with_context(|| format!("invalid CORS exposed headers '{value}'"))?,
+            )
+        };
+        Ok(Self {
+            allowed_origins,
+            allowed_methods,
+            allowed_headers,
+            exposed_headers,
+            allow_credentials: config.allow_credentials,
+            max_age: config.max_age.map(HeaderValue::from),
+        })
+    }
+
+    /// Returns the method of the request a preflight request is asking about,
+    /// if the request is a CORS preflight request.
+    pub fn preflight_method<B>(req: &Request<B>) -> Option<Method> {
+        if req.method() != Method::OPTIONS || !req.headers().contains_key(header::ORIGIN) {
+            return None;
+        }
+        let method = req.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD)?;
+        Method::from_bytes(method.as_bytes()).ok()
+    }
+
+    /// Answers a preflight request for the given method.
+    pub fn preflight(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+    ) -> anyhow::Result<Response<Body>> {
+        let mut res = Response::builder().status(StatusCode::NO_CONTENT);
+        let res_headers = res.headers_mut().context("invalid preflight response")?;
+        for vary in [
+            header::ORIGIN,
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+        ] {
+            res_headers.append(header::VARY, HeaderValue::from(vary));
+        }
+
+        let Some(allow_origin) = self.allow_origin(headers) else {
+            return Ok(res.status(StatusCode::FORBIDDEN).body(body::empty())?);
+        };
+        let allow_methods = match &self.allowed_methods {
+            Allowed::Any => HeaderValue::from_str(method.as_str())?,
+            Allowed::List(methods) if methods.contains(method) => {
+                HeaderValue::from_str(&join(methods.iter().map(Method::as_str)))?
+            }
+            Allowed::List(_) => {
+                return Ok(res.status(StatusCode::FORBIDDEN).body(body::empty())?);
+            }
+        };
+        let requested_headers = headers
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        let allow_headers = match &self.allowed_headers {
+            Allowed::Any => join(requested_headers.iter().map(String::as_str)),
+            Allowed::List(allowed) => {
+                if !requested_headers
+                    .iter()
+                    .all(|name| allowed.iter().any(|h| h.as_str() == name))
+                {
+                    return Ok(res.status(StatusCode::FORBIDDEN).body(body::empty())?);
+                }
+                join(allowed.iter().map(HeaderName::as_str))
+            }
+        };
+
+        res_headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        res_headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, allow_methods);
+        if !allow_headers.is_empty() {
+            res_headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_str(&allow_headers)?,
+            );
+        }
+        if self.allow_credentials {
+            res_headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(max_age) = &self.max_age {
+            res_headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+        }
+        Ok(res.body(body::empty())?)
+    }
+
+    /// Adds the CORS headers to the response to a request with the given headers.
+    pub fn apply(&self, req_headers: &HeaderMap, mut res: Response<Body>) -> Response<Body> {
+        let allow_origin = self.allow_origin(req_headers);
+        let headers = res.headers_mut();
+        if matches!(self.allowed_origins, Allowed::List(_)) {
+            headers.append(header::VARY, HeaderValue::from(header::ORIGIN));
+        }
+        let Some(allow_origin) = allow_origin else {
+            return res;
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(exposed_headers) = &self.exposed_headers {
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                exposed_headers.clone(),
+            );
+        }
+        res
+    }
+
+    /// The `Access-Control-Allow-Origin` value for the request, if its origin is allowed.
+    fn allow_origin(&self, req_headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = req_headers.get(header::ORIGIN)?;
+        match &self.allowed_origins {
+            Allowed::Any => Some(HeaderValue::from_static("*")),
+            Allowed::List(patterns) => {
+                let normalized = origin.to_str().ok()?.to_ascii_lowercase();
+                patterns
+                    .iter()
+                    .any(|p| p.matches(&normalized))
+                    .then(|| origin.clone())
+            }
+        }
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cors(config: serde_json::Value) -> Cors {
+        Cors::new(&serde_json::from_value(config).unwrap()).unwrap()
+    }
+
+    fn preflight_request(origin: &str, method: &str, headers: &str) -> Request<()> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+            .body(())
+            .unwrap()
+    }
+
+    fn preflight(cors: &Cors, req: &Request<()>) -> Response<Body> {
+        let method = Cors::preflight_method(req).expect("should be a preflight request");
+        cors.preflight(req.headers(), &method).unwrap()
+    }
+
+    #[test]
+    fn origin_patterns() {
+        let exact = OriginPattern::parse("https://Example.com/").unwrap();
+        assert!(exact.matches("https://example.com"));
+        assert!(!exact.matches("http://example.com"));
+
+        let subdomains = OriginPattern::parse("https://*.example.com").unwrap();
+        assert!(subdomains.matches("https://api.example.com"));
+        assert!(subdomains.matches("https://a.b.example.com"));
+        assert!(!subdomains.matches("https://example.com"));
+        assert!(!subdomains.matches("https://badexample.com"));
+        assert!(!subdomains.matches("http://api.example.com"));
+
+        assert!(OriginPattern::parse("example.com").is_err());
+    }
+
+    #[test]
+    fn credentials_require_explicit_origins() {
+        let config = serde_json::json!({
+            "allowed_origins": ["*"],
+            "allow_credentials": true,
+        });
+        assert!(Cors::new(&serde_json::from_value(config).unwrap()).is_err());
+    }
+
+    #[test]
+    fn preflight_is_answered() {
+        let cors = cors(serde_json::json!({
+            "allowed_origins": ["https://example.com"],
+            "allowed_methods": ["GET", "PUT"],
+            "allowed_headers": ["Content-Type", "X-Api-Key"],
+            "allow_credentials": true,
+            "max_age": 600,
+        }));
+
+        let res = preflight(
+            &cors,
+            &preflight_request("https://example.com", "PUT", "x-api-key, content-type"),
+        );
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type, x-api-key"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        for req in [
+            preflight_request("https://evil.example", "PUT", "x-api-key"),
+            preflight_request("https://example.com", "DELETE", "x-api-key"),
+            preflight_request("https://example.com", "PUT", "x-other"),
+        ] {
+            let res = preflight(&cors, &req);
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+            assert!(
+                !res.headers()
+                    .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            );
+        }
+    }
+
+    #[test]
+    fn wildcards_reflect_request() {
+        let cors = cors(serde_json::json!({
+            "allowed_origins": ["*"],
+            "allowed_methods": ["*"],
+            "allowed_headers": ["*"],
+        }));
+        let res = preflight(
+            &cors,
+            &preflight_request("https://anywhere.example", "PATCH", "X-Custom"),
+        );
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "PATCH");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "x-custom");
+    }
+
+    #[test]
+    fn non_preflight_requests_are_detected() {
+        let options = Request::builder()
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "https://example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(Cors::preflight_method(&options), None);
+        let get = Request::builder()
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(())
+            .unwrap();
+        assert_eq!(Cors::preflight_method(&get), None);
+    }
+
+    #[test]
+    fn headers_are_added_to_responses() {
+        let cors = cors(serde_json::json!({
+            "allowed_origins": ["https://example.com"],
+            "exposed_headers": ["X-Request-Id"],
+        }));
+        let response = || Response::new(body::empty());
+
+        let mut req_headers = HeaderMap::new();
+        req_headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://example.com"),
+        );
+        let res = cors.apply(&req_headers, response());
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "X-Request-Id"
+        );
+        assert_eq!(headers[header::VARY], "origin");
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+        req_headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://evil.example"),
+        );
+        let res = cors.apply(&req_headers, response());
+        assert!(
+            !res.headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+        assert_eq!(res.headers()[header::VARY], "origin");
+    }
+}
@@ -0,0 +1,354 @@
+//! Pages served in place of the bodies of error responses made by the host.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, ensure};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, header};
+use spin_app::App;
+use spin_http::{body, config::ErrorPageConfig};
+
+use crate::{Body, static_files};
+
+/// The header which tells an error page component the status of the response
+/// it is making the page for.
+pub(crate) const ERROR_STATUS_HEADER: HeaderName = HeaderName::from_static("spin-error-status");
+
+/// Marks a response made by the host for an error, whose body may be replaced
+/// by the app's error page.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HostError {
+    /// What went wrong, such as the trap of a failed component.
+    details: Option<String>,
+}
+
+impl HostError {
+    pub fn with_response_extension(
+        mut resp: Response<Body>,
+        details: Option<String>,
+    ) -> Response<Body> {
+        resp.extensions_mut().insert(HostError { details });
+        resp
+    }
+}
+
+/// The app's error pages, by status code or class (`4xx` or `5xx`).
+pub(crate) struct ErrorPages {
+    pages: HashMap<String, ErrorPage>,
+    show_details: bool,
+}
+
+pub(crate) enum ErrorPage {
+    File {
+        content: Bytes,
+        content_type: &'static str,
+    },
+    Component(String),
+    Template(String),
+}
+
+impl ErrorPages {
+    /// Prepares the app's error pages, reading files into memory. Error
+    /// details are only included in templates if `show_details` is set.
+    pub fn new(
+        app: &App,
+        configs: &HashMap<String, ErrorPageConfig>,
+        show_details: bool,
+    ) -> anyhow::Result<Self> {
+        let pages = configs
+            .iter()
+            .map(|(key, config)| {
+                let is_error_status = |status: StatusCode| {
+                    status.is_client_error() || status.is_server_error()
+                };
+                ensure!(
+                    key == "4xx"
+                        || key == "5xx"
+                        || key.parse::<StatusCode>().is_ok_and(is_error_status),
+                    "invalid error page status '{key}': expected a 4xx or 5xx status code, or a class such as '5xx'"
+                );
+                let page = match config {
+                    ErrorPageConfig::File(path) => {
+                        let path = static_files::manifest_relative_path(app, path)
+                            .with_context(|| {
+                                format!(
+                                    "error page files can only be served by apps run from a local manifest ('{key}' page)"
+                                )
+                            })?;
+                        let content = std::fs::read(&path).with_context(|| {
+                            format!("failed to read error page '{}'", path.display())
+                        })?;
+                        ErrorPage::File {
+                            content: content.into(),
+                            content_type: static_files::content_type(&path),
+                        }
+                    }
+                    ErrorPageConfig::Component(component_id) => {
+                        ErrorPage::Component(component_id.clone())
+                    }
+                    ErrorPageConfig::Template(template) => ErrorPage::Template(template.clone()),
+                };
+                Ok((key.clone(), page))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            pages,
+            show_details,
+        })
+    }
+
+    /// The page for a status, or else for its class.
+    pub fn page(&self, status: StatusCode) -> Option<&ErrorPage> {
+        self.pages
+            .get(status.as_str())
+            .or_else(|| self.pages.get(&format!("{}xx", status.as_u16() / 100)))
+    }
+
+    /// The components which make error pages.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.pages.values().filter_map(|page| match page {
+            ErrorPage::Component(component_id) => Some(component_id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The details of the error to show in its page, if they are shown.
+    pub fn details<'a>(&self, error: &'a HostError) -> Option<&'a str> {
+        if self.show_details {
+            error.details.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
+/// Replaces the body of an error response, keeping its status and headers.
+pub(crate) fn replace_body(
+    mut resp: Response<Body>,
+    content_type: &'static str,
+    content: Bytes,
+) -> Response<Body> {
+    let headers = resp.headers_mut();
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    resp.map(|_| body::full(content))
+}
+
+/// Renders a template as HTML, JSON or plain text, as the client accepts,
+/// returning the content type and content.
+pub(crate) fn render_template(
+    template: &str,
+    status: StatusCode,
+    path: &str,
+    details: Option<&str>,
+    req_headers: &HeaderMap,
+) -> (&'static str, Bytes) {
+    let reason = status.canonical_reason().unwrap_or_default();
+    let message = template
+        .replace("{status}", status.as_str())
+        .replace("{reason}", reason)
+        .replace("{path}", path);
+    match negotiate(req_headers) {
+        Format::Html => {
+            let mut html = format!(
+                "<!DOCTYPE html>\n<html>\n<head><title>{status} {reason}</title></head>\n<body>\n<h1>{status} {reason}</h1>\n<p>{}</p>\n",
+                escape_html(&message),
+                status = status.as_str(),
+            );
+            if let Some(details) = details {
+                html.push_str(&format!("<pre>{}</pre>\n", escape_html(details)));
+            }
+            html.push_str("</body>\n</html>\n");
+            ("text/html; charset=utf-8", html.into())
+        }
+        Format::Json => {
+            let mut json = serde_json::json!({
+                "status": status.as_u16(),
+                "error": reason,
+                "message": message,
+            });
+            if let Some(details) = details {
+                json["details"] = details.into();
+            }
+            ("application/json", json.to_string().into())
+        }
+        Format::Text => {
+            let mut text = format!("{message}\n");
+            if let Some(details) = details {
+                text.push_str(&format!("\n{details}\n"));
+            }
+            ("text/plain; charset=utf-8", text.into())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Html,
+    Json,
+    Text,
+}
+
+/// Chooses the format the client's `Accept` header prefers, in our order of
+/// preference if it accepts several equally.
+fn negotiate(headers: &HeaderMap) -> Format {
+    let accepted = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let media_type = params.next()?.trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_type, quality))
+        })
+        .collect::<Vec<_>>();
+    let quality = |media_type: &str| {
+        let (main_type, _) = media_type.split_once('/').unwrap_or_default();
+        let find = |name: &str| accepted.iter().find(|(t, _)| t == name).map(|(_, q)| *q);
+        find(media_type)
+            .or_else(|| find(&format!("{main_type}/*")))
+            .or_else(|| find("*/*"))
+            .unwrap_or(0.0)
+    };
+    let mut best = (Format::Text, 0.0);
+    for (format, media_type) in [
+        (Format::Html, "text/html"),
+        (Format::Json, "application/json"),
+        (Format::Text, "text/plain"),
+    ] {
+        let q = quality(media_type);
+        if q > best.1 {
+            best = (format, q);
+        }
+    }
+    best.0
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn app_from(origin: &str) -> App {
+        let locked = serde_json::json!({
+            "spin_lock_version": 1,
+            "metadata": { "origin": origin },
+            "triggers": [],
+            "components": [],
+        });
+        let locked = spin_app::locked::LockedApp::from_json(locked.to_string().as_bytes()).unwrap();
+        App::new("test-app", locked)
+    }
+
+    #[test]
+    fn error_page_files_must_be_local_to_the_manifest() {
+        let page = |path: &str| {
+            HashMap::from([("404".into(), ErrorPageConfig::File(PathBuf::from(path)))])
+        };
+
+        let app = app_from("file:///srv/app/spin.toml");
+        assert!(ErrorPages::new(&app, &page("/etc/passwd"), false).is_err());
+
+        let app = app_from("oci://registry.example.com/app:v1");
+        let err = ErrorPages::new(&app, &page("errors/404.html"), false)
+            .err()
+            .expect("registry apps can't have error page files");
+        assert!(format!("{err:#}").contains("local manifest"), "{err:#}");
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn negotiates_format() {
+        assert_eq!(
+            negotiate(&accept("text/html,application/xhtml+xml,*/*;q=0.8")),
+            Format::Html
+        );
+        assert_eq!(negotiate(&accept("application/json")), Format::Json);
+        assert_eq!(
+            negotiate(&accept("text/html;q=0.5, application/json")),
+            Format::Json
+        );
+        assert_eq!(negotiate(&accept("*/*")), Format::Html);
+        assert_eq!(negotiate(&HeaderMap::new()), Format::Text);
+    }
+
+    #[test]
+    fn renders_templates() {
+        let template = "Nothing at {path}";
+        let (content_type, html) = render_template(
+            template,
+            StatusCode::NOT_FOUND,
+            "/<script>",
+            None,
+            &accept("text/html"),
+        );
+        assert_eq!(content_type, "text/html; charset=utf-8");
+        let html = String::from_utf8(html.to_vec()).unwrap();
+        assert!(html.contains("<h1>404 Not Found</h1>"));
+        assert!(html.contains("<p>Nothing at /&lt;script&gt;</p>"));
+
+        let (content_type, json) = render_template(
+            template,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "/",
+            Some("wasm trap: unreachable"),
+            &accept("application/json"),
+        );
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["status"], 500);
+        assert_eq!(json["error"], "Internal Server Error");
+        assert_eq!(json["message"], "Nothing at /");
+        assert_eq!(json["details"], "wasm trap: unreachable");
+    }
+
+    #[test]
+    fn pages_fall_back_to_status_class() {
+        let pages = ErrorPages {
+            pages: HashMap::from([
+                ("404".to_owned(), ErrorPage::Template("missing".into())),
+                ("5xx".to_owned(), ErrorPage::Component("errors".into())),
+            ]),
+            show_details: false,
+        };
+        assert!(matches!(
+            pages.page(StatusCode::NOT_FOUND),
+            Some(ErrorPage::Template(_))
+        ));
+        assert!(matches!(
+            pages.page(StatusCode::GATEWAY_TIMEOUT),
+            Some(ErrorPage::Component(_))
+        ));
+        assert!(pages.page(StatusCode::METHOD_NOT_ALLOWED).is_none());
+        assert_eq!(pages.components().collect::<Vec<_>>(), ["errors"]);
+
+        let error = HostError {
+            details: Some("wasm trap".into()),
+        };
+        assert_eq!(pages.details(&error), None);
+        let pages = ErrorPages {
+            show_details: true,
+            ..pages
+        };
+        assert_eq!(pages.details(&error), Some("wasm trap"));
+    }
+}
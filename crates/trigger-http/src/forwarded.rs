@@ -0,0 +1,162 @@
+//! Finding the client address of requests which come through trusted proxies.
+
+use std::net::{IpAddr, SocketAddr};
+
+use http::{HeaderMap, HeaderName, header};
+use ip_network::IpNetwork;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The networks of proxies whose `Forwarded` and `X-Forwarded-For` headers
+/// are honoured.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNetwork>) -> Self {
+        Self { networks }
+    }
+
+    /// Returns the address of the client which sent the request.
+    ///
+    /// While the request came from a trusted proxy, this walks back through
+    /// the hops the proxies recorded, stopping at the first untrusted one.
+    pub fn client_addr(&self, peer_addr: SocketAddr, headers: &HeaderMap) -> SocketAddr {
+        let mut client_addr = peer_addr;
+        if self.networks.is_empty() {
+            return client_addr;
+        }
+        for hop in forwarded_for(headers).into_iter().rev() {
+            if !self.is_trusted(client_addr.ip()) {
+                break;
+            }
+            match hop {
+                Some(addr) => client_addr = addr,
+                // An obfuscated or malformed hop can't be followed.
+                None => break,
+            }
+        }
+        client_addr
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// Parses a trusted proxy network, which may be a single address.
+pub(crate) fn parse_network(s: &str) -> Result<IpNetwork, String> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(IpNetwork::from(ip));
+    }
+    IpNetwork::from_str_truncate(s)
+        .map_err(|_| format!("expected an IP address or a network in CIDR notation; got {s:?}"))
+}
+
+/// The addresses recorded by the proxies a request came through, from the
+/// client onwards. The `Forwarded` header takes precedence over
+/// `X-Forwarded-For`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<SocketAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+    };
+    if headers.contains_key(header::FORWARDED) {
+        values(header::FORWARDED)
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node.trim_matches('"')))
+            })
+            .collect()
+    } else {
+        values(X_FORWARDED_FOR).map(parse_node).collect()
+    }
+}
+
+/// Parses an address recorded by a proxy, which may not include a port.
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = node.parse() {
+        return Some(addr);
+    }
+    let ip = node
+        .strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'));
+    ip.unwrap_or(node)
+        .parse()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted(networks: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(networks.iter().map(|n| parse_network(n).unwrap()).collect())
+    }
+
+    fn header_map(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn headers_are_ignored_from_untrusted_peers() {
+        let peer = "203.0.113.9:5000".parse().unwrap();
+        let headers = header_map(&[("x-forwarded-for", "198.51.100.1")]);
+        assert_eq!(trusted(&[]).client_addr(peer, &headers), peer);
+        assert_eq!(trusted(&["10.0.0.0/8"]).client_addr(peer, &headers), peer);
+    }
+
+    #[test]
+    fn trusted_hops_are_skipped() {
+        let proxies = trusted(&["10.0.0.0/8", "192.0.2.7"]);
+        let peer = "10.1.2.3:5000".parse().unwrap();
+
+        // The client can't spoof the hops before the first untrusted one.
+        let headers = header_map(&[("x-forwarded-for", "1.1.1.1, 198.51.100.1, 192.0.2.7")]);
+        assert_eq!(
+            proxies.client_addr(peer, &headers),
+            "198.51.100.1:0".parse().unwrap()
+        );
+
+        let headers = header_map(&[("x-forwarded-for", "10.0.0.1, 10.0.0.2")]);
+        assert_eq!(
+            proxies.client_addr(peer, &headers),
+            "10.0.0.1:0".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn forwarded_takes_precedence() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        let peer = "10.1.2.3:5000".parse().unwrap();
+        let headers = header_map(&[
+            ("x-forwarded-for", "198.51.100.1"),
+            (
+                "forwarded",
+                r#"for=192.0.2.60;proto=http, For="[2001:db8:cafe::17]:4711""#,
+            ),
+        ]);
+        assert_eq!(
+            proxies.client_addr(peer, &headers),
+            "[2001:db8:cafe::17]:4711".parse().unwrap()
+        );
+
+        // Obfuscated identifiers stop the walk.
+        let headers = header_map(&[("forwarded", "for=192.0.2.60, for=_hidden")]);
+        assert_eq!(proxies.client_addr(peer, &headers), peer);
+    }
+}
@@ -10,7 +10,7 @@ use hyper::Request;
 use spin_factor_outbound_networking::config::allowed_hosts::is_service_chaining_host;
 use spin_http::routes::RouteMatch;
 
-use crate::Body;
+use crate::{Body, ClientCertificate};
 
 // We need to make the following pieces of information available to both executors.
 // While the values we set are identical, the way they are passed to the
@@ -24,6 +24,10 @@ pub const COMPONENT_ROUTE: [&str; 2] = ["SPIN_COMPONENT_ROUTE", "X_COMPONENT_ROU
 pub const RAW_COMPONENT_ROUTE: [&str; 2] = ["SPIN_RAW_COMPONENT_ROUTE", "X_RAW_COMPONENT_ROUTE"];
 pub const BASE_PATH: [&str; 2] = ["SPIN_BASE_PATH", "X_BASE_PATH"];
 pub const CLIENT_ADDR: [&str; 2] = ["SPIN_CLIENT_ADDR", "X_CLIENT_ADDR"];
+pub const CLIENT_CERT_SUBJECT: [&str; 2] = ["SPIN_CLIENT_CERT_SUBJECT", "X_CLIENT_CERT_SUBJECT"];
+pub const CLIENT_CERT_SANS: [&str; 2] = ["SPIN_CLIENT_CERT_SANS", "X_CLIENT_CERT_SANS"];
+pub const CLIENT_CERT_FINGERPRINT: [&str; 2] =
+    ["SPIN_CLIENT_CERT_FINGERPRINT", "X_CLIENT_CERT_FINGERPRINT"];
 
 // Header key/value pairs that use copy on write to avoid allocation
 pub type HeaderPair<'a> = ([Cow<'static, str>; 2], Cow<'a, str>);
@@ -34,6 +38,7 @@ pub fn compute_default_headers<'a>(
     host: &str,
     route_match: &'a RouteMatch,
     client_addr: SocketAddr,
+    client_cert: Option<&'a ClientCertificate>,
 ) -> anyhow::Result<Vec<HeaderPair<'a>>> {
     fn owned(strs: &[&'static str; 2]) -> [Cow<'static, str>; 2] {
         [strs[0].into(), strs[1].into()]
@@ -71,6 +76,18 @@ pub fn compute_default_headers<'a>(
     ));
     res.push((owned_client_addr, client_addr.to_string().into()));
 
+    if let Some(client_cert) = client_cert {
+        res.push((
+            owned(&CLIENT_CERT_SUBJECT),
+            client_cert.subject.as_str().into(),
+        ));
+        res.push((owned(&CLIENT_CERT_SANS), client_cert.sans.as_str().into()));
+        res.push((
+            owned(&CLIENT_CERT_FINGERPRINT),
+            client_cert.fingerprint.as_str().into(),
+        ));
+    }
+
     for (wild_name, wild_value) in route_match.named_wildcards() {
         let wild_header = format!("SPIN_PATH_MATCH_{}", wild_name.to_ascii_uppercase()).into();
         let wild_wagi_header = format!("X_PATH_MATCH_{}", wild_name.to_ascii_uppercase()).into();
@@ -101,6 +118,10 @@ pub fn prepare_request_headers(
         .iter()
         .map(|(name, value)| (name.to_string(), std::str::from_utf8(value.as_bytes())))
     {
+        // Client certificate headers may only be set by the host.
+        if is_client_cert_header(&name) {
+            continue;
+        }
         let value = value?.to_string();
         res.push((name, value));
     }
@@ -116,7 +137,10 @@ pub fn prepare_request_headers(
     // Set the environment information (path info, base path, etc) as headers.
     // In the future, we might want to have this information in a context
     // object as opposed to headers.
-    for (keys, val) in compute_default_headers(req.uri(), host, route_match, client_addr)? {
+    let client_cert = req.extensions().get::<ClientCertificate>();
+    for (keys, val) in
+        compute_default_headers(req.uri(), host, route_match, client_addr, client_cert)?
+    {
         res.push((prepare_header_key(&keys[0]), val.into_owned()));
     }
 
@@ -143,6 +167,16 @@ fn prepare_header_key(key: &str) -> String {
     key.replace('_', "-").to_ascii_lowercase()
 }
 
+fn is_client_cert_header(name: &str) -> bool {
+    [
+        &CLIENT_CERT_SUBJECT,
+        &CLIENT_CERT_SANS,
+        &CLIENT_CERT_FINGERPRINT,
+    ]
+    .iter()
+    .any(|keys| name.eq_ignore_ascii_case(&prepare_header_key(keys[0])))
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
@@ -196,7 +230,8 @@ mod tests {
         )?;
         let route_match = router.route("/foo/bar")?;
 
-        let default_headers = compute_default_headers(req.uri(), host, &route_match, client_addr)?;
+        let default_headers =
+            compute_default_headers(req.uri(), host, &route_match, client_addr, None)?;
 
         assert_eq!(
             search(&FULL_URL, &default_headers).unwrap(),
@@ -259,7 +294,8 @@ mod tests {
         )?;
         let route_match = router.route("/foo/42/bar")?;
 
-        let default_headers = compute_default_headers(req.uri(), host, &route_match, client_addr)?;
+        let default_headers =
+            compute_default_headers(req.uri(), host, &route_match, client_addr, None)?;
 
         assert_eq!(
             search(&FULL_URL, &default_headers).unwrap(),
@@ -302,6 +338,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_client_cert_headers() -> Result<()> {
+        let client_addr: SocketAddr = "127.0.0.1:8777".parse().unwrap();
+        let client_cert = ClientCertificate {
+            subject: "CN=caller".into(),
+            sans: "DNS:caller.example.com".into(),
+            fingerprint: "abcd".into(),
+        };
+
+        let mut req = http::Request::builder()
+            .uri("https://fermyon.dev/foo")
+            .header("spin-client-cert-subject", "CN=impostor")
+            .body(Default::default())?;
+
+        let router = Router::build(
+            "/",
+            [(
+                &spin_http::routes::TriggerLookupKey::Component("DUMMY".into()),
+                &"/foo".into(),
+            )],
+            None,
+        )?;
+        let route_match = router.route("/foo")?;
+
+        // Without a verified certificate, client-supplied values are dropped.
+        let headers = prepare_request_headers(&req, &route_match, client_addr)?;
+        assert!(!headers.iter().any(|(k, _)| k == "spin-client-cert-subject"));
+
+        req.extensions_mut().insert(client_cert);
+        let headers = prepare_request_headers(&req, &route_match, client_addr)?;
+        let find = |key: &str| {
+            headers
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(find("spin-client-cert-subject"), ["CN=caller"]);
+        assert_eq!(find("spin-client-cert-sans"), ["DNS:caller.example.com"]);
+        assert_eq!(find("spin-client-cert-fingerprint"), ["abcd"]);
+
+        Ok(())
+    }
+
     #[test]
     fn forbidden_headers_are_removed() {
         let mut req = Request::get("http://test.spin.internal")
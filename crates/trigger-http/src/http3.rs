@@ -0,0 +1,347 @@
+//! HTTP/3 (QUIC) listener for the HTTP trigger.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Context;
+use bytes::{Buf, Bytes};
+use h3::server::RequestStream;
+use http::{HeaderValue, Request, Response, uri::Scheme};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use rustls_pki_types::CertificateDer;
+use spin_factors::RuntimeFactors;
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::{Body, ClientCertificate, HttpServer};
+
+/// How long (in seconds) clients may cache the `Alt-Svc` advertisement.
+const ALT_SVC_MAX_AGE_SECS: u64 = 86400;
+
+/// Binds a QUIC endpoint on the given UDP address.
+pub(crate) fn bind_endpoint(
+    addr: SocketAddr,
+    server_config: quinn::ServerConfig,
+) -> anyhow::Result<quinn::Endpoint> {
+    quinn::Endpoint::server(server_config, addr)
+        .with_context(|| format!("Unable to listen for HTTP/3 on udp://{addr}"))
+}
+
+/// The `Alt-Svc` header value advertising HTTP/3 on the given port.
+pub(crate) fn alt_svc_header_value(port: u16) -> HeaderValue {
+    HeaderValue::from_str(&format!("h3=\":{port}\"; ma={ALT_SVC_MAX_AGE_SECS}"))
+        .expect("Alt-Svc value should always be a valid header value")
+}
+
+/// Serve HTTP/3 requests arriving on the given QUIC endpoint.
+pub(crate) async fn serve<F: RuntimeFactors>(
+    server: Arc<HttpServer<F>>,
+    endpoint: quinn::Endpoint,
+) -> anyhow::Result<()> {
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            () = server.shutdown_requested() => return Ok(()),
+        };
+        let Some(incoming) = incoming else {
+            return Ok(());
+        };
+        let conn_server = server.clone();
+        server.spawn_tracked(async move {
+            if let Err(err) = serve_connection(conn_server, incoming).await {
+                tracing::warn!("Error serving HTTP/3 connection: {err:?}");
+            }
+        });
+    }
+}
+
+async fn serve_connection<F: RuntimeFactors>(
+    server: Arc<HttpServer<F>>,
+    incoming: quinn::Incoming,
+) -> anyhow::Result<()> {
+    let conn = incoming.await.context("QUIC handshake failed")?;
+    let client_addr = conn.remote_address();
+    let client_cert = conn
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .and_then(|certs| ClientCertificate::from_peer_certificates(Some(certs.as_slice())));
+    let mut h3_conn = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn))
+        .await
+        .context("failed to establish HTTP/3 connection")?;
+
+    let mut shutting_down = false;
+    loop {
+        let accepted = tokio::select! {
+            accepted = h3_conn.accept() => accepted,
+            () = server.shutdown_requested(), if !shutting_down => {
+                // Refuse new requests, then keep serving those already accepted.
+                shutting_down = true;
+                h3_conn.shutdown(0).await?;
+                continue;
+            }
+        };
+        match accepted {
+            Ok(Some(resolver)) => {
+                let req_server = server.clone();
+                let client_cert = client_cert.clone();
+                server.spawn_tracked(async move {
+                    let result = async {
+                        let (mut req, stream) = resolver.resolve_request().await?;
+                        if let Some(client_cert) = client_cert {
+                            req.extensions_mut().insert(client_cert);
+                        }
+                        handle_request(req_server, req, stream, client_addr).await
+                    };
+                    if let Err(err) = result.await {
+                        tracing::warn!("Error serving HTTP/3 request: {err:?}");
+                    }
+                });
+            }
+            Ok(None) => return Ok(()),
+            Err(err) if err.is_h3_no_error() => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+async fn handle_request<F: RuntimeFactors>(
+    server: Arc<HttpServer<F>>,
+    req: Request<()>,
+    stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    client_addr: SocketAddr,
+) -> anyhow::Result<()> {
+    let (mut send, recv) = stream.split();
+
+    let req = req.map(|()| request_body(recv));
+    let resp = server
+        .instrumented_handle(Scheme::HTTPS, client_addr, req)
+        .await?;
+
+    send_response(&mut send, resp).await
+}
+
+/// Sends a response, followed by its body and trailers, on a request stream.
+async fn send_response<S: h3::quic::SendStream<Bytes>>(
+    send: &mut RequestStream<S, Bytes>,
+    resp: Response<Body>,
+) -> anyhow::Result<()> {
+    let (parts, mut body) = resp.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+/// Adapts the receiving half of an HTTP/3 request stream into a request body.
+fn request_body(recv: RequestStream<h3_quinn::RecvStream, Bytes>) -> Body {
+    let frames = futures::stream::unfold(Some(recv), |recv| async move {
+        let mut recv = recv?;
+        match recv.recv_data().await {
+            Ok(Some(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                Some((Ok(Frame::data(data)), Some(recv)))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("Error reading HTTP/3 request body: {err:?}");
+                Some((Err(ErrorCode::HttpProtocolError), None))
+            }
+        }
+    });
+    StreamBody::new(frames).boxed_unsync()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use rustls_pki_types::pem::PemObject;
+    use spin_factor_wasi::{DummyFilesMounter, WasiFactor};
+    use spin_factors_executor::FactorsExecutor;
+
+    use super::*;
+    use crate::{InstanceReuseConfig, ListenAddress, OutputFormat, TlsConfig};
+
+    const TESTDATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+
+    #[test]
+    fn alt_svc_advertises_port() {
+        assert_eq!(alt_svc_header_value(3000), "h3=\":3000\"; ma=86400");
+    }
+
+    /// A self-signed certificate for `localhost`.
+    fn tls_config() -> TlsConfig {
+        TlsConfig {
+            cert_path: Path::new(TESTDATA_DIR).join("localhost-cert.pem"),
+            key_path: Path::new(TESTDATA_DIR).join("localhost-private-key.pem"),
+            sni_certs: vec![],
+            http3: true,
+            client_ca_path: None,
+            client_auth: Default::default(),
+        }
+    }
+
+    /// A client TLS configuration which trusts only the test certificate.
+    fn client_tls_config() -> rustls::ClientConfig {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(tls_config().cert_path).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        rustls::ClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS13])
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    }
+
+    /// Sends a GET request over HTTP/3, returning the response with its body
+    /// and trailers.
+    async fn h3_get(
+        addr: SocketAddr,
+        path: &str,
+    ) -> (Response<()>, Bytes, Option<http::HeaderMap>) {
+        let mut tls = client_tls_config();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+        let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        let conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+        let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(conn))
+            .await
+            .unwrap();
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+        let req = Request::get(format!("https://localhost:{}{path}", addr.port()))
+            .body(())
+            .unwrap();
+        let mut stream = send_request.send_request(req).await.unwrap();
+        stream.finish().await.unwrap();
+
+        let res = stream.recv_response().await.unwrap();
+        let mut body = Vec::new();
+        while let Some(mut data) = stream.recv_data().await.unwrap() {
+            body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+        }
+        let trailers = stream.recv_trailers().await.unwrap();
+        (res, body.into(), trailers)
+    }
+
+    #[derive(spin_factors::RuntimeFactors)]
+    struct TestFactors {
+        wasi: WasiFactor,
+    }
+
+    /// Starts a server, with HTTP/3, for an app which responds to `/hello`.
+    /// Returns its address once it is listening.
+    async fn start_server() -> SocketAddr {
+        let locked = serde_json::json!({
+            "spin_lock_version": 1,
+            "metadata": { "triggers": { "http": {} } },
+            "triggers": [{
+                "id": "hello",
+                "trigger_type": "http",
+                "trigger_config": {
+                    "route": "/hello",
+                    "static_response": { "body": "hello" },
+                },
+            }],
+            "components": [],
+        });
+        let locked = spin_app::locked::LockedApp::from_json(locked.to_string().as_bytes()).unwrap();
+        let app = spin_app::App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default()).unwrap();
+        let factors = TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        };
+        let executor = Arc::new(FactorsExecutor::new(engine_builder, factors).unwrap());
+        let loader = spin_trigger::loader::ComponentLoader::new();
+        let trigger_app = executor
+            .load_app(app, Default::default(), &loader, Some("http"), ())
+            .await
+            .unwrap();
+
+        let server = HttpServer::new(
+            ListenAddress::Tcp("127.0.0.1:0".parse().unwrap()),
+            Some(tls_config()),
+            false,
+            trigger_app,
+            None,
+            InstanceReuseConfig::default(),
+            OutputFormat::default(),
+        )
+        .unwrap();
+        let server = Arc::new(server);
+        tokio::spawn(server.clone().serve());
+        loop {
+            match server.get_local_addr() {
+                Some(addr) if addr.port() != 0 => return addr,
+                _ => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_requests_and_advertises_http3_over_tcp() {
+        let addr = start_server().await;
+
+        let (res, body, _) = h3_get(addr, "/hello").await;
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(body, "hello");
+
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(client_tls_config())
+            .resolve("localhost", addr)
+            .build()
+            .unwrap();
+        let res = client
+            .get(format!("https://localhost:{}/hello", addr.port()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        assert_eq!(
+            res.headers()[http::header::ALT_SVC],
+            alt_svc_header_value(addr.port())
+        );
+        assert_eq!(res.text().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn sends_response_body_and_trailers() {
+        let resolver = tls_config().cert_resolver().unwrap();
+        let server_config = tls_config().quic_server_config(resolver).unwrap();
+        let endpoint = bind_endpoint("127.0.0.1:0".parse().unwrap(), server_config).unwrap();
+        let addr = endpoint.local_addr().unwrap();
+        tokio::spawn(async move {
+            let conn = endpoint.accept().await.unwrap().await.unwrap();
+            let mut h3_conn =
+                h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn))
+                    .await
+                    .unwrap();
+            let resolver = h3_conn.accept().await.unwrap().unwrap();
+            let (_, mut stream) = resolver.resolve_request().await.unwrap();
+
+            let mut trailers = http::HeaderMap::new();
+            trailers.insert("checksum", HeaderValue::from_static("abc123"));
+            let frames = [Frame::data(Bytes::from("hello")), Frame::trailers(trailers)];
+            let body = StreamBody::new(futures::stream::iter(frames.map(Ok))).boxed_unsync();
+            send_response(&mut stream, Response::new(body))
+                .await
+                .unwrap();
+            // Keep the connection open until the client is done with it.
+            _ = h3_conn.accept().await;
+        });
+
+        let (res, body, trailers) = h3_get(addr, "/").await;
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(body, "hello");
+        assert_eq!(trailers.unwrap()["checksum"], "abc123");
+    }
+}
@@ -1,21 +1,38 @@
 //! Implementation for the Spin HTTP engine.
 
+mod access_log;
+mod cache;
+mod compression;
+mod concurrency;
+mod cors;
+mod error_pages;
+mod forwarded;
 mod headers;
+mod http3;
 mod instrument;
+mod listener;
 mod middleware;
 mod outbound_http;
+mod pool;
+mod proxy;
+mod proxy_protocol;
 mod server;
 mod spin;
+mod split;
+mod static_files;
+mod status;
 mod tls;
 mod wagi;
 mod wasi;
 mod wasip3;
+mod websocket;
 
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::Display,
     net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
     time::Duration,
@@ -23,6 +40,7 @@ use std::{
 
 use anyhow::{Context, bail};
 use clap::Args;
+use ip_network::IpNetwork;
 use rand::{
     distr::uniform::{SampleRange, SampleUniform},
     rand_core::Rng,
@@ -30,12 +48,14 @@ use rand::{
 use serde::Deserialize;
 use spin_app::App;
 use spin_factors::RuntimeFactors;
-use spin_trigger::Trigger;
+use spin_http::config::{CompressionConfig, CorsConfig, ErrorPageConfig};
+use spin_trigger::{GracefulShutdown, Trigger};
 use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
 
+pub use access_log::{AccessLogDestination, AccessLogFormat};
 pub use server::HttpServer;
 
-pub use tls::TlsConfig;
+pub use tls::{ClientAuth, ClientCertificate, SniCertificate, TlsConfig};
 
 pub(crate) use wasmtime_wasi_http::p2::body::HyperIncomingBody as Body;
 
@@ -43,6 +63,7 @@ const DEFAULT_WASIP3_MAX_INSTANCE_REUSE_COUNT: usize = 128;
 const DEFAULT_WASIP3_MAX_INSTANCE_CONCURRENT_REUSE_COUNT: usize = 16;
 const DEFAULT_REQUEST_TIMEOUT: Option<Range<Duration>> = None;
 const DEFAULT_IDLE_INSTANCE_TIMEOUT: Range<Duration> = Range::Value(Duration::from_secs(1));
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// The format in which to print startup route information.
 #[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
@@ -63,11 +84,14 @@ pub(crate) type TriggerInstanceBuilder<'a, F> =
 
 #[derive(Args)]
 pub struct CliArgs {
-    /// IP address and port to listen on
-    #[clap(long = "listen", env = "SPIN_HTTP_LISTEN_ADDR", default_value = "127.0.0.1:3000", value_parser = parse_listen_addr)]
-    pub address: SocketAddr,
-
-    /// The path to the certificate to use for https, if this is not set, normal http will be used. The cert should be in PEM format
+    /// IP address and port to listen on. This may also be `unix:PATH` to listen on a
+    /// Unix domain socket, or `systemd` to use the socket passed by the init system
+    /// (socket activation using `LISTEN_FDS`).
+    #[clap(long = "listen", env = "SPIN_HTTP_LISTEN_ADDR", default_value = "127.0.0.1:3000", value_parser = parse_listen_address)]
+    pub address: ListenAddress,
+
+    /// The path to the certificate to use for https, if this is not set, normal http will be used. The cert should be in PEM format.
+    /// Certificate and key files are watched and reloaded when they change.
     #[clap(long, env = "SPIN_TLS_CERT", requires = "tls_key")]
     pub tls_cert: Option<PathBuf>,
 
@@ -75,6 +99,37 @@ pub struct CliArgs {
     #[clap(long, env = "SPIN_TLS_KEY", requires = "tls_cert")]
     pub tls_key: Option<PathBuf>,
 
+    /// An additional certificate to serve to clients requesting the given hostname (SNI), in
+    /// the form `HOSTNAME=CERT,KEY`. The hostname may be a wildcard such as `*.example.com`.
+    /// May be given multiple times. Clients requesting other hostnames get --tls-cert.
+    #[clap(
+        long = "tls-sni-cert",
+        value_parser = parse_sni_cert,
+        requires = "tls_cert"
+    )]
+    pub tls_sni_certs: Vec<SniCertificate>,
+
+    /// The path to a bundle of CA certificates used to verify client certificates (mutual TLS).
+    /// The bundle should be in PEM format. Details of verified client certificates are passed
+    /// to components in the `spin-client-cert-*` headers.
+    #[clap(long, env = "SPIN_TLS_CLIENT_CA", requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Whether clients must present a certificate when --tls-client-ca is set.
+    #[clap(
+        value_enum,
+        long,
+        env = "SPIN_TLS_CLIENT_AUTH",
+        default_value_t = ClientAuth::default(),
+        requires = "tls_client_ca"
+    )]
+    pub tls_client_auth: ClientAuth,
+
+    /// Also serve HTTP/3 over QUIC on the same port (UDP), and advertise it to
+    /// HTTPS clients using the `Alt-Svc` header. Requires --tls-cert and --tls-key.
+    #[clap(long, env = "SPIN_HTTP3", requires = "tls_cert")]
+    pub http3: bool,
+
     /// Sets the maximum buffer size (in bytes) for the HTTP connection. The minimum value allowed is 8192.
     #[clap(long, env = "SPIN_HTTP1_MAX_BUF_SIZE")]
     pub http1_max_buf_size: Option<usize>,
@@ -88,9 +143,9 @@ pub struct CliArgs {
     /// Maximum number of requests to send to a single component instance before
     /// dropping it.
     ///
-    /// This defaults to 1 for WASIp2 components and 128 for WASIp3 components.
-    /// As of this writing, setting it to more than 1 will have no effect for
-    /// WASIp2 components, but that may change in the future.
+    /// This defaults to 128. WASIp2 components only reuse instances if their
+    /// trigger sets `instance_reuse = true`, and then handle one request at a
+    /// time on each instance.
     ///
     /// This may be specified either as an integer value or as a range,
     /// e.g. 1..8.  If it's a range, a number will be selected from that range
@@ -113,7 +168,9 @@ pub struct CliArgs {
 
     /// Request timeout to enforce.
     ///
-    /// As of this writing, this only affects WASIp3 components.
+    /// Components which are still handling a request at the timeout are
+    /// stopped, and the client gets a 504 Gateway Timeout response if the
+    /// response has not started.
     ///
     /// A number with no suffix or with an `s` suffix is interpreted as seconds;
     /// other accepted suffixes include `ms` (milliseconds), `us` or `μs`
@@ -137,6 +194,67 @@ pub struct CliArgs {
     /// at random for each new instance.
     #[clap(long, default_value = "1s", value_parser = parse_duration_range)]
     pub idle_instance_timeout: Range<Duration>,
+
+    /// How long to wait for in-flight requests to finish when shutting down.
+    ///
+    /// On SIGTERM or Ctrl-C the server stops accepting connections and lets
+    /// in-flight requests and reused component instances finish for up to
+    /// this long before exiting. Accepts the same suffixes as --request-timeout.
+    #[clap(long, env = "SPIN_HTTP_SHUTDOWN_TIMEOUT", default_value = "30s", value_parser = parse_duration)]
+    pub shutdown_timeout: Duration,
+
+    /// Write an access log line for each request, to stdout or to a file in the log directory.
+    ///
+    /// Each line records the route, component, status, response bytes, duration
+    /// in milliseconds and, for reused instances, the instance that handled
+    /// the request and how many requests it had started, as `instance:request`.
+    #[clap(value_enum, long, env = "SPIN_HTTP_ACCESS_LOG")]
+    pub access_log: Option<AccessLogDestination>,
+
+    /// The format of access log lines.
+    #[clap(value_enum, long, env = "SPIN_HTTP_ACCESS_LOG_FORMAT", default_value_t = AccessLogFormat::default(), requires = "access_log")]
+    pub access_log_format: AccessLogFormat,
+
+    /// Enable purging cached responses, authorized by this bearer token.
+    ///
+    /// `POST /.well-known/spin/cache/purge?prefix=PATH` with an
+    /// `Authorization: Bearer TOKEN` header removes the cached responses of
+    /// all triggers for requests whose path starts with PATH.
+    #[clap(long, env = "SPIN_HTTP_CACHE_PURGE_TOKEN", hide_env_values = true)]
+    pub cache_purge_token: Option<String>,
+
+    /// Serve `health`, `ready` and `routes` endpoints under `/.well-known/spin/`.
+    ///
+    /// `health` and `ready` report whether the key-value stores and SQLite
+    /// databases the components use can be opened. `ready`
+    /// responds with 503 Service Unavailable if not, or if the server is
+    /// shutting down. `routes` returns the routing table as JSON.
+    #[clap(long, env = "SPIN_HTTP_STATUS_ENDPOINTS")]
+    pub status_endpoints: bool,
+
+    /// Expect each connection to start with a PROXY protocol (version 1 or 2)
+    /// header, and take the client address from it rather than from the TCP peer.
+    ///
+    /// Only enable this behind a load balancer which sends the header, as
+    /// connections without it are closed.
+    #[clap(long, env = "SPIN_HTTP_PROXY_PROTOCOL")]
+    pub proxy_protocol: bool,
+
+    /// An IP address or CIDR network of a proxy whose `Forwarded` and
+    /// `X-Forwarded-For` headers are trusted. May be given multiple times.
+    ///
+    /// The client address passed to components in `spin-client-addr` is the
+    /// last address in those headers before the first untrusted hop.
+    #[clap(long = "trusted-proxy", value_parser = forwarded::parse_network)]
+    pub trusted_proxies: Vec<IpNetwork>,
+
+    /// Include error details, such as the traps of failed components, in the
+    /// app's error pages.
+    ///
+    /// This helps in development, but lets clients see the internals of failing
+    /// components, so leave it unset in production. Details are always logged.
+    #[clap(long, env = "SPIN_HTTP_SHOW_ERROR_DETAILS")]
+    pub show_error_details: bool,
 }
 
 impl CliArgs {
@@ -145,6 +263,10 @@ impl CliArgs {
             (Some(cert_path), Some(key_path)) => Some(TlsConfig {
                 cert_path,
                 key_path,
+                sni_certs: self.tls_sni_certs,
+                http3: self.http3,
+                client_ca_path: self.tls_client_ca,
+                client_auth: self.tls_client_auth,
             }),
             (None, None) => None,
             _ => unreachable!(),
@@ -234,6 +356,10 @@ fn parse_duration_range(s: &str) -> Result<Range<Duration>, String> {
     parse_range::<ParsedDuration>(s).map(|v| v.map(|v| v.0))
 }
 
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    s.parse::<ParsedDuration>().map(|v| v.0)
+}
+
 #[derive(Clone, Copy)]
 pub struct InstanceReuseConfig {
     max_instance_reuse_count: Range<usize>,
@@ -261,12 +387,22 @@ pub struct HttpTrigger {
     ///
     /// Note that this might not be the actual socket address that ends up being bound to.
     /// If the port is set to 0, the actual address will be determined by the OS.
-    listen_addr: SocketAddr,
+    listen_addr: ListenAddress,
     tls_config: Option<TlsConfig>,
     find_free_port: bool,
     http1_max_buf_size: Option<usize>,
     reuse_config: InstanceReuseConfig,
     output_format: OutputFormat,
+    shutdown: GracefulShutdown,
+    shutdown_timeout: Duration,
+    access_log: Option<(AccessLogDestination, AccessLogFormat)>,
+    log_dir: Option<PathBuf>,
+    cache_purge_token: Option<String>,
+    state_dir: Option<PathBuf>,
+    status_endpoints: bool,
+    proxy_protocol: bool,
+    trusted_proxies: Vec<IpNetwork>,
+    show_error_details: bool,
 }
 
 impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
@@ -276,9 +412,19 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
     type InstanceState = ();
 
     fn new(cli_args: Self::CliArgs, app: &spin_app::App) -> anyhow::Result<Self> {
+        let address = cli_args.address.clone();
         let find_free_port = cli_args.find_free_port;
         let http1_max_buf_size = cli_args.http1_max_buf_size;
         let output_format = cli_args.format;
+        let shutdown_timeout = cli_args.shutdown_timeout;
+        let access_log = cli_args
+            .access_log
+            .map(|destination| (destination, cli_args.access_log_format));
+        let cache_purge_token = cli_args.cache_purge_token.clone();
+        let status_endpoints = cli_args.status_endpoints;
+        let proxy_protocol = cli_args.proxy_protocol;
+        let trusted_proxies = cli_args.trusted_proxies.clone();
+        let show_error_details = cli_args.show_error_details;
         let reuse_config = InstanceReuseConfig {
             max_instance_reuse_count: cli_args
                 .max_instance_reuse_count
@@ -294,13 +440,34 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
 
         Self::new(
             app,
-            cli_args.address,
+            address,
             cli_args.into_tls_config(),
             find_free_port,
             http1_max_buf_size,
             reuse_config,
             output_format,
         )
+        .map(|trigger| {
+            let trigger = trigger
+                .with_shutdown_timeout(shutdown_timeout)
+                .with_cache_purge_token(cache_purge_token)
+                .with_status_endpoints(status_endpoints)
+                .with_proxy_protocol(proxy_protocol)
+                .with_trusted_proxies(trusted_proxies)
+                .with_error_details_shown(show_error_details);
+            match access_log {
+                Some((destination, format)) => trigger.with_access_log(destination, format),
+                None => trigger,
+            }
+        })
+    }
+
+    fn set_log_dir(&mut self, log_dir: Option<&Path>) {
+        self.log_dir = log_dir.map(ToOwned::to_owned);
+    }
+
+    fn set_state_dir(&mut self, state_dir: Option<&Path>) {
+        self.state_dir = state_dir.map(ToOwned::to_owned);
     }
 
     async fn run(self, trigger_app: TriggerApp<F>) -> anyhow::Result<()> {
@@ -315,6 +482,10 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
         middleware::HttpMiddlewareComposer
     }
 
+    fn graceful_shutdown(&self) -> Option<GracefulShutdown> {
+        Some(self.shutdown.clone())
+    }
+
     fn supported_host_requirements() -> Vec<&'static str> {
         vec![spin_app::locked::SERVICE_CHAINING_KEY]
     }
@@ -328,7 +499,7 @@ impl HttpTrigger {
     /// Create a new `HttpTrigger`.
     pub fn new(
         app: &spin_app::App,
-        listen_addr: SocketAddr,
+        listen_addr: ListenAddress,
         tls_config: Option<TlsConfig>,
         find_free_port: bool,
         http1_max_buf_size: Option<usize>,
@@ -344,9 +515,70 @@ impl HttpTrigger {
             http1_max_buf_size,
             reuse_config,
             output_format,
+            shutdown: GracefulShutdown::new(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            access_log: None,
+            log_dir: None,
+            cache_purge_token: None,
+            state_dir: None,
+            status_endpoints: false,
+            proxy_protocol: false,
+            trusted_proxies: Vec::new(),
+            show_error_details: false,
         })
     }
 
+    /// Sets how long to wait for in-flight requests when shutting down.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Writes an access log line for each request.
+    ///
+    /// File logs are written to the log directory, which is set by the trigger
+    /// executor before the server is started.
+    pub fn with_access_log(
+        mut self,
+        destination: AccessLogDestination,
+        format: AccessLogFormat,
+    ) -> Self {
+        self.access_log = Some((destination, format));
+        self
+    }
+
+    /// Enables requests to purge cached responses, authorized by the token.
+    pub fn with_cache_purge_token(mut self, token: Option<String>) -> Self {
+        self.cache_purge_token = token;
+        self
+    }
+
+    /// Enables the `health`, `ready` and `routes` endpoints under the well-known prefix.
+    pub fn with_status_endpoints(mut self, enabled: bool) -> Self {
+        self.status_endpoints = enabled;
+        self
+    }
+
+    /// Expects each connection to start with a PROXY protocol header giving
+    /// the client address.
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Honours the `Forwarded` and `X-Forwarded-For` headers of requests from
+    /// proxies in these networks when finding the client address.
+    pub fn with_trusted_proxies(mut self, networks: Vec<IpNetwork>) -> Self {
+        self.trusted_proxies = networks;
+        self
+    }
+
+    /// Includes error details in the app's error pages.
+    pub fn with_error_details_shown(mut self, shown: bool) -> Self {
+        self.show_error_details = shown;
+        self
+    }
+
     /// Turn this [`HttpTrigger`] into an [`HttpServer`].
     pub fn into_server<F: RuntimeFactors>(
         self,
@@ -359,26 +591,50 @@ impl HttpTrigger {
             http1_max_buf_size,
             reuse_config,
             output_format,
+            shutdown,
+            shutdown_timeout,
+            access_log,
+            log_dir,
+            cache_purge_token,
+            state_dir,
+            status_endpoints,
+            proxy_protocol,
+            trusted_proxies,
+            show_error_details,
         } = self;
-        let server = Arc::new(HttpServer::new(
-            listen_addr,
-            tls_config,
-            find_free_port,
-            trigger_app,
-            http1_max_buf_size,
-            reuse_config,
-            output_format,
-        )?);
+        let access_log = access_log
+            .map(|(destination, format)| {
+                access_log::AccessLog::open(destination, format, log_dir.as_deref())
+            })
+            .transpose()?;
+        let server = Arc::new(
+            HttpServer::new(
+                listen_addr,
+                tls_config,
+                find_free_port,
+                trigger_app,
+                http1_max_buf_size,
+                reuse_config,
+                output_format,
+            )?
+            .with_graceful_shutdown(shutdown, shutdown_timeout)
+            .with_access_log(access_log)
+            .with_status_endpoints(status_endpoints)
+            .with_client_addresses(
+                proxy_protocol,
+                forwarded::TrustedProxies::new(trusted_proxies),
+            )
+            .with_response_caches(state_dir.as_deref(), cache_purge_token)?
+            .with_error_pages(show_error_details)?,
+        );
         Ok(server)
     }
 
     fn validate_app(app: &App) -> anyhow::Result<()> {
-        #[derive(Deserialize)]
-        #[serde(deny_unknown_fields)]
-        struct TriggerMetadata {
-            base: Option<String>,
-        }
-        if let Some(TriggerMetadata { base: Some(base) }) = app.get_trigger_metadata("http")? {
+        if let Some(HttpTriggerMetadata {
+            base: Some(base), ..
+        }) = app.get_trigger_metadata("http")?
+        {
             if base == "/" {
                 tracing::warn!(
                     "This application has the deprecated trigger 'base' set to the default value '/'. This may be an error in the future!"
@@ -393,6 +649,79 @@ impl HttpTrigger {
     }
 }
 
+/// Application-wide HTTP trigger settings, from `[application.trigger.http]`.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpTriggerMetadata {
+    base: Option<String>,
+    /// Compression of component responses, unless overridden by a trigger.
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+    /// The CORS policy for all routes, unless overridden by a trigger.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Pages served in place of the bodies of error responses made by the
+    /// host, by status code (such as `404`) or class (`4xx` or `5xx`).
+    #[serde(default)]
+    pub error_pages: HashMap<String, ErrorPageConfig>,
+}
+
+/// Where the HTTP trigger listens for connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddress {
+    /// A TCP address.
+    Tcp(SocketAddr),
+    /// A Unix domain socket at the given path (`unix:PATH`).
+    Unix(PathBuf),
+    /// The socket passed by the init system using socket activation (`systemd`).
+    Inherited,
+}
+
+impl ListenAddress {
+    /// The TCP address, if this is a TCP address.
+    pub fn tcp(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            Self::Unix(_) | Self::Inherited => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for ListenAddress {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+impl FromStr for ListenAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_listen_address(s)
+    }
+}
+
+impl Display for ListenAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Inherited => f.write_str("systemd"),
+        }
+    }
+}
+
+fn parse_listen_address(addr: &str) -> anyhow::Result<ListenAddress> {
+    if let Some(path) = addr.strip_prefix("unix:") {
+        anyhow::ensure!(!path.is_empty(), "expected a socket path after 'unix:'");
+        return Ok(ListenAddress::Unix(path.into()));
+    }
+    if addr == "systemd" {
+        return Ok(ListenAddress::Inherited);
+    }
+    parse_listen_addr(addr).map(ListenAddress::Tcp)
+}
+
 fn parse_listen_addr(addr: &str) -> anyhow::Result<SocketAddr> {
     let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
     // Prefer 127.0.0.1 over e.g. [::1] because CHANGE IS HARD
@@ -406,6 +735,21 @@ fn parse_listen_addr(addr: &str) -> anyhow::Result<SocketAddr> {
     addrs.into_iter().next().context("couldn't resolve address")
 }
 
+fn parse_sni_cert(value: &str) -> anyhow::Result<SniCertificate> {
+    let (hostname, paths) = value
+        .split_once('=')
+        .context("expected HOSTNAME=CERT,KEY")?;
+    let (cert_path, key_path) = paths
+        .split_once(',')
+        .context("expected HOSTNAME=CERT,KEY")?;
+    anyhow::ensure!(!hostname.is_empty(), "hostname must not be empty");
+    Ok(SniCertificate {
+        hostname: hostname.to_owned(),
+        cert_path: cert_path.into(),
+        key_path: key_path.into(),
+    })
+}
+
 #[derive(Debug, PartialEq)]
 enum NotFoundRouteKind {
     Normal(String),
@@ -445,4 +789,33 @@ mod tests {
         assert_eq!(addr.ip(), Ipv4Addr::LOCALHOST);
         assert_eq!(addr.port(), 12345);
     }
+
+    #[test]
+    fn parse_listen_address_kinds() {
+        assert_eq!(
+            parse_listen_address("127.0.0.1:3000").unwrap(),
+            ListenAddress::Tcp("127.0.0.1:3000".parse().unwrap())
+        );
+        assert_eq!(
+            parse_listen_address("unix:/run/app.sock").unwrap(),
+            ListenAddress::Unix(PathBuf::from("/run/app.sock"))
+        );
+        assert_eq!(
+            parse_listen_address("systemd").unwrap(),
+            ListenAddress::Inherited
+        );
+        parse_listen_address("unix:").unwrap_err();
+    }
+
+    #[test]
+    fn parse_sni_cert_splits_hostname_and_paths() {
+        let sni = parse_sni_cert("*.example.com=certs/wild.pem,certs/wild.key").unwrap();
+        assert_eq!(sni.hostname, "*.example.com");
+        assert_eq!(sni.cert_path, PathBuf::from("certs/wild.pem"));
+        assert_eq!(sni.key_path, PathBuf::from("certs/wild.key"));
+
+        parse_sni_cert("example.com=cert.pem").unwrap_err();
+        parse_sni_cert("cert.pem,key.pem").unwrap_err();
+        parse_sni_cert("=cert.pem,key.pem").unwrap_err();
+    }
 }
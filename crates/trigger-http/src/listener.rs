@@ -0,0 +1,252 @@
+//! Listeners the HTTP trigger accepts connections on.
+
+use std::{io, net::SocketAddr, path::Path};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+
+/// The client address reported for connections over Unix domain sockets, which
+/// always come from the local machine.
+#[cfg(unix)]
+const UNIX_CLIENT_ADDR: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(
+    std::net::Ipv4Addr::LOCALHOST,
+    0,
+));
+
+/// An accepted connection.
+pub(crate) trait Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Connection for T {}
+
+/// A bound listener.
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: tokio::net::UnixListener,
+        path: std::path::PathBuf,
+        /// Whether we created the socket file, and so should remove it.
+        owned: bool,
+    },
+}
+
+impl Listener {
+    /// Binds a Unix domain socket at the given path, replacing any stale socket file.
+    #[cfg(unix)]
+    pub fn unix(path: &Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        remove_stale_socket(path)?;
+        let listener = tokio::net::UnixListener::bind(path)
+            .with_context(|| format!("Unable to listen on unix:{}", path.display()))?;
+        Ok(Self::Unix {
+            listener,
+            path: path.to_owned(),
+            owned: true,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn unix(_path: &Path) -> anyhow::Result<Self> {
+        anyhow::bail!("Unix domain sockets are not supported on this platform")
+    }
+
+    /// Takes the listening socket passed by the init system using the systemd
+    /// socket activation protocol (`LISTEN_FDS`).
+    #[cfg(unix)]
+    pub fn inherited() -> anyhow::Result<Self> {
+        use std::os::fd::{FromRawFd, IntoRawFd};
+
+        let fd = inherited_fd(
+            std::env::var("LISTEN_FDS").ok().as_deref(),
+            std::env::var("LISTEN_PID").ok().as_deref(),
+        )?;
+        // SAFETY: the socket activation protocol passes ownership of the
+        // listening sockets to us, and this is only called once per process.
+        let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        if tcp.local_addr().is_ok() {
+            tcp.set_nonblocking(true)?;
+            return Ok(Self::Tcp(TcpListener::from_std(tcp)?));
+        }
+        // SAFETY: as above; this is the same socket, which is not a TCP socket.
+        let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+        unix.set_nonblocking(true)?;
+        let path = unix
+            .local_addr()?
+            .as_pathname()
+            .map(Path::to_owned)
+            .unwrap_or_default();
+        Ok(Self::Unix {
+            listener: tokio::net::UnixListener::from_std(unix)?,
+            path,
+            owned: false,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn inherited() -> anyhow::Result<Self> {
+        anyhow::bail!("Socket activation is not supported on this platform")
+    }
+
+    /// Accepts a connection, returning it with the client's address.
+    pub async fn accept(&self) -> io::Result<(Box<dyn Connection>, SocketAddr)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, client_addr) = listener.accept().await?;
+                Ok((Box::new(stream), client_addr))
+            }
+            #[cfg(unix)]
+            Self::Unix { listener, .. } => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Box::new(stream), UNIX_CLIENT_ADDR))
+            }
+        }
+    }
+
+    /// The TCP address the listener is bound to, if it is a TCP listener.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Unix { .. } => None,
+        }
+    }
+
+    /// The URL at which the listener is served, without a trailing slash.
+    pub fn base_url(&self, scheme: &str) -> String {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => format!("{scheme}://{addr:?}"),
+                Err(_) => format!("{scheme}://<unknown>"),
+            },
+            #[cfg(unix)]
+            Self::Unix { path, .. } => unix_base_url(scheme, path),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Self::Unix {
+            path, owned: true, ..
+        } = self
+        {
+            _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Formats a Unix socket URL in the `http+unix://%2Fpath%2Fto.sock` convention.
+#[cfg(unix)]
+fn unix_base_url(scheme: &str, path: &Path) -> String {
+    use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+    const SOCKET_PATH: &AsciiSet = &NON_ALPHANUMERIC.remove(b'.').remove(b'-').remove(b'_');
+    let path = path.to_string_lossy();
+    format!(
+        "{scheme}+unix://{}",
+        utf8_percent_encode(&path, SOCKET_PATH)
+    )
+}
+
+/// Removes a socket file left behind by a previous process, if no process is
+/// still listening on it.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    anyhow::ensure!(
+        metadata.file_type().is_socket(),
+        "Unable to listen on unix:{}: the file exists and is not a socket",
+        path.display()
+    );
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("unix:{} is already in use", path.display());
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Returns the inherited listening socket from the `LISTEN_FDS` and `LISTEN_PID`
+/// environment variables.
+#[cfg(unix)]
+fn inherited_fd(
+    listen_fds: Option<&str>,
+    listen_pid: Option<&str>,
+) -> anyhow::Result<std::os::fd::RawFd> {
+    use anyhow::Context;
+
+    /// The first file descriptor passed by socket activation.
+    const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+    let count: u32 = listen_fds
+        .context("No sockets were passed by the init system (LISTEN_FDS is not set)")?
+        .parse()
+        .context("LISTEN_FDS is not a number")?;
+    anyhow::ensure!(count > 0, "No sockets were passed by the init system");
+    if let Some(pid) = listen_pid {
+        let pid: u32 = pid.parse().context("LISTEN_PID is not a number")?;
+        // `spin up` runs triggers as child processes, so the init system
+        // will have passed the sockets to our parent.
+        anyhow::ensure!(
+            pid == std::process::id() || pid == std::os::unix::process::parent_id(),
+            "The sockets passed by the init system (LISTEN_PID={pid}) are for another process"
+        );
+    }
+    if count > 1 {
+        tracing::warn!(
+            "{count} sockets were passed by the init system; only the first will be used"
+        );
+    }
+    Ok(LISTEN_FDS_START)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_base_url_encodes_path() {
+        assert_eq!(
+            unix_base_url("http", Path::new("/run/my-app/app_1.sock")),
+            "http+unix://%2Frun%2Fmy-app%2Fapp_1.sock"
+        );
+    }
+
+    #[test]
+    fn inherited_fd_checks_environment() {
+        let pid = std::process::id().to_string();
+        assert_eq!(inherited_fd(Some("1"), Some(&pid)).unwrap(), 3);
+        assert_eq!(inherited_fd(Some("2"), None).unwrap(), 3);
+        inherited_fd(None, None).unwrap_err();
+        inherited_fd(Some("0"), None).unwrap_err();
+        inherited_fd(Some("one"), None).unwrap_err();
+        inherited_fd(Some("1"), Some("0")).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn unix_listener_replaces_stale_socket() {
+        let path = std::env::temp_dir().join(format!("spin-http-{}.sock", std::process::id()));
+        // A socket file with nobody listening on it.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = Listener::unix(&path).unwrap();
+        assert!(listener.local_addr().is_none());
+        assert!(Listener::unix(&path).is_err());
+
+        let client = tokio::net::UnixStream::connect(&path);
+        let (accepted, client) = tokio::join!(listener.accept(), client);
+        client.unwrap();
+        assert_eq!(accepted.unwrap().1, UNIX_CLIENT_ADDR);
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,155 @@
+//! Sequential reuse of WASIp2 component instances.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::RngExt;
+
+use crate::{
+    InstanceReuseConfig, Range,
+    access_log::{InstanceReuse, ReuseTracker},
+};
+
+/// The most idle instances kept for reuse per component.
+const MAX_IDLE_INSTANCES: usize = 16;
+
+/// Idle instances of a component which may each handle further requests,
+/// one at a time.
+pub(crate) struct InstancePool<T> {
+    /// Idle instances, most recently used last.
+    idle: Mutex<Vec<Idle<T>>>,
+    max_instance_reuse_count: Range<usize>,
+    idle_instance_timeout: Range<Duration>,
+    reuse: ReuseTracker,
+}
+
+struct Idle<T> {
+    instance: T,
+    lease: Lease,
+    expires: Instant,
+}
+
+/// The reuse limits of an instance, held by the request using it.
+pub(crate) struct Lease {
+    /// Identifies the instance in access logs.
+    instance_id: u64,
+    requests_started: usize,
+    max_requests: usize,
+    idle_timeout: Duration,
+}
+
+impl<T> InstancePool<T> {
+    pub fn new(reuse_config: &InstanceReuseConfig, reuse: ReuseTracker) -> Self {
+        Self {
+            idle: Mutex::default(),
+            max_instance_reuse_count: reuse_config.max_instance_reuse_count,
+            idle_instance_timeout: reuse_config.idle_instance_timeout,
+            reuse,
+        }
+    }
+
+    /// Takes the most recently used idle instance, dropping any which have
+    /// been idle for too long.
+    pub fn take(&self) -> Option<(T, Lease)> {
+        let now = Instant::now();
+        let mut idle = self.idle.lock().unwrap();
+        idle.retain(|idle| idle.expires > now);
+        idle.pop().map(|idle| (idle.instance, idle.lease))
+    }
+
+    /// Creates the lease of a new instance.
+    pub fn lease(&self) -> Lease {
+        Lease {
+            instance_id: self.reuse.next_instance_id(),
+            requests_started: 0,
+            max_requests: rand::rng().random_range(self.max_instance_reuse_count),
+            idle_timeout: rand::rng().random_range(self.idle_instance_timeout),
+        }
+    }
+
+    /// Returns an instance which has finished handling a request, unless it
+    /// has handled as many as it may.
+    pub fn put(&self, instance: T, lease: Lease) {
+        if lease.requests_started >= lease.max_requests {
+            return;
+        }
+        let expires = Instant::now() + lease.idle_timeout;
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() >= MAX_IDLE_INSTANCES {
+            idle.remove(0);
+        }
+        idle.push(Idle {
+            instance,
+            lease,
+            expires,
+        });
+    }
+}
+
+impl Lease {
+    /// Records that the instance is starting another request.
+    pub fn start_request(&mut self) -> InstanceReuse {
+        self.requests_started += 1;
+        InstanceReuse {
+            instance: self.instance_id,
+            request: self.requests_started as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(max_instance_reuse_count: usize, idle_instance_timeout: Duration) -> InstancePool<u32> {
+        InstancePool {
+            idle: Mutex::default(),
+            max_instance_reuse_count: Range::Value(max_instance_reuse_count),
+            idle_instance_timeout: Range::Value(idle_instance_timeout),
+            reuse: ReuseTracker::default(),
+        }
+    }
+
+    #[test]
+    fn reuses_instances_up_to_the_limit() {
+        let pool = pool(2, Duration::from_secs(60));
+        assert!(pool.take().is_none());
+
+        let mut lease = pool.lease();
+        let first = lease.start_request();
+        pool.put(7, lease);
+
+        let (instance, mut lease) = pool.take().unwrap();
+        assert_eq!(instance, 7);
+        let second = lease.start_request();
+        assert_eq!(second.instance, first.instance);
+        assert_eq!((first.request, second.request), (1, 2));
+
+        // The instance has handled as many requests as it may.
+        pool.put(instance, lease);
+        assert!(pool.take().is_none());
+    }
+
+    #[test]
+    fn drops_expired_instances() {
+        let pool = pool(8, Duration::ZERO);
+        let mut lease = pool.lease();
+        lease.start_request();
+        pool.put(7, lease);
+        assert!(pool.take().is_none());
+    }
+
+    #[test]
+    fn keeps_a_bounded_number_of_idle_instances() {
+        let pool = pool(8, Duration::from_secs(60));
+        for instance in 0..MAX_IDLE_INSTANCES as u32 + 2 {
+            let mut lease = pool.lease();
+            lease.start_request();
+            pool.put(instance, lease);
+        }
+        assert_eq!(pool.idle.lock().unwrap().len(), MAX_IDLE_INSTANCES);
+        assert_eq!(pool.take().unwrap().0, MAX_IDLE_INSTANCES as u32 + 1);
+    }
+}
@@ -0,0 +1,247 @@
+//! Forwarding requests to an upstream HTTP server for `proxy` triggers.
+
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use futures::TryStreamExt;
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, header};
+use http_body_util::BodyExt;
+use spin_http::{body, config::ProxyConfig};
+use url::Url;
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::{Body, error_pages::HostError};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Headers which apply to a single connection and must not be forwarded.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// An upstream server that a `proxy` trigger forwards requests to.
+pub(crate) struct ReverseProxy {
+    upstream: Url,
+    strip_prefix: bool,
+    client: reqwest::Client,
+}
+
+impl ReverseProxy {
+    pub fn new(config: &ProxyConfig, client: reqwest::Client) -> anyhow::Result<Self> {
+        let upstream = Url::parse(&config.upstream)
+            .with_context(|| format!("invalid upstream URL '{}'", config.upstream))?;
+        anyhow::ensure!(
+            matches!(upstream.scheme(), "http" | "https"),
+            "upstream URL '{upstream}' must use http or https"
+        );
+        anyhow::ensure!(
+            upstream.query().is_none() && upstream.fragment().is_none(),
+            "upstream URL '{upstream}' must not have a query or fragment"
+        );
+        Ok(Self {
+            upstream,
+            strip_prefix: config.strip_prefix,
+            client,
+        })
+    }
+
+    /// Creates the client shared by all proxy triggers.
+    pub fn client() -> anyhow::Result<reqwest::Client> {
+        // Pass responses through as they are: don't follow redirects or decode bodies.
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .no_gzip()
+            .no_brotli()
+            .no_deflate()
+            .no_zstd()
+            .no_proxy()
+            .build()
+            .context("failed to create HTTP client for proxy triggers")
+    }
+
+    /// Forwards the request upstream and streams back the response.
+    ///
+    /// `trailing_path` is the part of the path matched by the route's trailing
+    /// wildcard, which is forwarded in place of the full path if prefix
+    /// stripping is enabled.
+    pub async fn forward(
+        &self,
+        req: Request<Body>,
+        trailing_path: &str,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        let (parts, body) = req.into_parts();
+        let url = self.upstream_url(&parts.uri, trailing_path);
+        let headers = forwarded_headers(&parts.uri, parts.headers, client_addr)?;
+
+        let body = reqwest::Body::wrap_stream(
+            body.into_data_stream()
+                .map_err(|err| anyhow::anyhow!("error reading request body: {err:?}")),
+        );
+        let result = self
+            .client
+            .request(parts.method, url.clone())
+            .headers(headers)
+            .body(body)
+            .send()
+            .await;
+        let upstream_resp = match result {
+            Ok(resp) => resp,
+            Err(err) => {
+                tracing::warn!("Error forwarding request to {url}: {err:?}");
+                return Ok(HostError::with_response_extension(
+                    Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(body::empty())?,
+                    Some(err.to_string()),
+                ));
+            }
+        };
+
+        let mut resp = Response::builder().status(upstream_resp.status());
+        if let Some(headers) = resp.headers_mut() {
+            *headers = upstream_resp.headers().clone();
+            remove_hop_by_hop_headers(headers);
+        }
+        let body = reqwest::Body::from(upstream_resp).map_err(|err| {
+            tracing::warn!("Error reading upstream response: {err:?}");
+            ErrorCode::HttpProtocolError
+        });
+        Ok(resp.body(body.boxed_unsync())?)
+    }
+
+    fn upstream_url(&self, uri: &Uri, trailing_path: &str) -> Url {
+        let path = if self.strip_prefix {
+            trailing_path
+        } else {
+            uri.path()
+        };
+        let mut url = self.upstream.clone();
+        let base_path = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&format!("{base_path}{path}"));
+        url.set_query(uri.query());
+        url
+    }
+}
+
+/// Prepares the incoming request headers for forwarding upstream.
+fn forwarded_headers(
+    uri: &Uri,
+    mut headers: HeaderMap,
+    client_addr: SocketAddr,
+) -> anyhow::Result<HeaderMap> {
+    remove_hop_by_hop_headers(&mut headers);
+
+    // The client sets the `Host` header for the upstream server.
+    let host = headers.remove(header::HOST);
+    let host = match uri.authority() {
+        Some(authority) => Some(HeaderValue::from_str(authority.as_str())?),
+        None => host,
+    };
+    if let Some(host) = host {
+        headers.insert(X_FORWARDED_HOST, host);
+    }
+    if let Some(scheme) = uri.scheme_str() {
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_str(scheme)?);
+    }
+
+    let client_ip = client_addr.ip().to_string();
+    let forwarded_for = match headers.get(&X_FORWARDED_FOR).map(|v| v.to_str()) {
+        Some(Ok(existing)) => format!("{existing}, {client_ip}"),
+        _ => client_ip,
+    };
+    headers.insert(X_FORWARDED_FOR, HeaderValue::from_str(&forwarded_for)?);
+
+    Ok(headers)
+}
+
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    // The `Connection` header may list further connection-specific headers.
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+    for name in &listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(*name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(upstream: &str, strip_prefix: bool) -> ReverseProxy {
+        let config = ProxyConfig {
+            upstream: upstream.into(),
+            strip_prefix,
+        };
+        ReverseProxy::new(&config, reqwest::Client::new()).unwrap()
+    }
+
+    #[test]
+    fn upstream_url_appends_path_and_query() {
+        let uri: Uri = "http://spin.local/legacy/users?id=1".parse().unwrap();
+
+        let url = proxy("http://localhost:8080", false).upstream_url(&uri, "/users");
+        assert_eq!(url.as_str(), "http://localhost:8080/legacy/users?id=1");
+
+        let url = proxy("http://localhost:8080/api/", true).upstream_url(&uri, "/users");
+        assert_eq!(url.as_str(), "http://localhost:8080/api/users?id=1");
+
+        let uri: Uri = "http://spin.local/legacy".parse().unwrap();
+        let url = proxy("http://localhost:8080", true).upstream_url(&uri, "");
+        assert_eq!(url.as_str(), "http://localhost:8080/");
+    }
+
+    #[test]
+    fn invalid_upstreams_are_rejected() {
+        for upstream in ["not a url", "ftp://example.com", "http://example.com/?a=b"] {
+            let config = ProxyConfig {
+                upstream: upstream.into(),
+                strip_prefix: false,
+            };
+            assert!(
+                ReverseProxy::new(&config, reqwest::Client::new()).is_err(),
+                "{upstream} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn forwarded_headers_are_set() {
+        let uri: Uri = "https://spin.local:3000/foo".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("spin.local:3000"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("x-secret"));
+        headers.insert("x-secret", HeaderValue::from_static("hop"));
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.1"));
+        headers.insert("x-custom", HeaderValue::from_static("kept"));
+
+        let headers =
+            forwarded_headers(&uri, headers, "192.168.1.2:4567".parse().unwrap()).unwrap();
+
+        assert!(!headers.contains_key(header::HOST));
+        assert!(!headers.contains_key(header::CONNECTION));
+        assert!(!headers.contains_key("x-secret"));
+        assert_eq!(headers["x-custom"], "kept");
+        assert_eq!(headers[X_FORWARDED_HOST], "spin.local:3000");
+        assert_eq!(headers[X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[X_FORWARDED_FOR], "10.0.0.1, 192.168.1.2");
+    }
+}
@@ -0,0 +1,185 @@
+//! The PROXY protocol, which load balancers use to pass on the address of the
+//! client a connection comes from.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{Context, bail, ensure};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8; 6] = b"PROXY ";
+/// The longest possible version 1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads the PROXY protocol header from the start of a connection, returning
+/// the client address it gives. Returns `None` if the header does not give
+/// an address, such as for the load balancer's own health checks.
+///
+/// This reads no further than the end of the header.
+pub(crate) async fn read_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> anyhow::Result<Option<SocketAddr>> {
+    let mut start = [0; 6];
+    stream.read_exact(&mut start).await?;
+    if &start == V1_PREFIX {
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            ensure!(line.len() < V1_MAX_LEN, "PROXY protocol header is too long");
+            line.push(stream.read_u8().await?);
+        }
+        parse_v1(&line)
+    } else if start == V2_SIGNATURE[..6] {
+        let mut header = [0; 16];
+        header[..6].copy_from_slice(&start);
+        stream.read_exact(&mut header[6..]).await?;
+        let mut addresses = vec![0; u16::from_be_bytes([header[14], header[15]]).into()];
+        stream.read_exact(&mut addresses).await?;
+        parse_v2(&header, &addresses)
+    } else {
+        bail!("connection did not start with a PROXY protocol header")
+    }
+}
+
+/// Parses a version 1 (text) header, e.g.
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`.
+fn parse_v1(line: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .context("PROXY protocol header is not valid text")?
+        .trim_end_matches("\r\n");
+    let fields = line.split(' ').collect::<Vec<_>>();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        [
+            "PROXY",
+            family @ ("TCP4" | "TCP6"),
+            source,
+            _,
+            source_port,
+            _,
+        ] => {
+            let ip: IpAddr = source
+                .parse()
+                .with_context(|| format!("invalid PROXY protocol source address {source:?}"))?;
+            ensure!(
+                ip.is_ipv4() == (family == "TCP4"),
+                "PROXY protocol source address {ip} is not {family}"
+            );
+            let port = source_port
+                .parse()
+                .with_context(|| format!("invalid PROXY protocol source port {source_port:?}"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => bail!("invalid PROXY protocol header {line:?}"),
+    }
+}
+
+/// Parses a version 2 (binary) header, given its fixed 16 bytes and the
+/// address block which follows them.
+fn parse_v2(header: &[u8; 16], addresses: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+    ensure!(
+        header[..12] == V2_SIGNATURE[..],
+        "invalid PROXY protocol signature"
+    );
+    ensure!(
+        header[12] >> 4 == 2,
+        "unsupported PROXY protocol version {}",
+        header[12] >> 4
+    );
+    match header[12] & 0xf {
+        // LOCAL: the connection was made by the proxy itself.
+        0 => return Ok(None),
+        // PROXY
+        1 => {}
+        command => bail!("unsupported PROXY protocol command {command}"),
+    }
+    let addr = match header[13] >> 4 {
+        // AF_INET: source and destination addresses, then ports.
+        1 => {
+            ensure!(
+                addresses.len() >= 12,
+                "PROXY protocol addresses are truncated"
+            );
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addresses[..4])?);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            SocketAddr::new(ip.into(), port)
+        }
+        // AF_INET6
+        2 => {
+            ensure!(
+                addresses.len() >= 36,
+                "PROXY protocol addresses are truncated"
+            );
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[..16])?);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            SocketAddr::new(ip.into(), port)
+        }
+        // AF_UNSPEC or AF_UNIX, which have no client IP address.
+        _ => return Ok(None),
+    };
+    Ok(Some(addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_v1_headers() {
+        assert_eq!(
+            parse_v1(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").unwrap(),
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+        assert_eq!(parse_v1(b"PROXY UNKNOWN\r\n").unwrap(), None);
+
+        assert!(parse_v1(b"PROXY TCP4 2001:db8::1 2001:db8::2 56324 443\r\n").is_err());
+        assert!(parse_v1(b"PROXY TCP4 192.0.2.1\r\n").is_err());
+    }
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> ([u8; 16], Vec<u8>) {
+        let mut header = [0; 16];
+        header[..12].copy_from_slice(V2_SIGNATURE);
+        header[12] = 0x20 | command;
+        header[13] = (family << 4) | 1;
+        header[14..].copy_from_slice(&(addresses.len() as u16).to_be_bytes());
+        (header, addresses.to_vec())
+    }
+
+    #[test]
+    fn parses_v2_headers() {
+        let addresses = [192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb];
+        let (header, addresses) = v2_header(1, 1, &addresses);
+        assert_eq!(
+            parse_v2(&header, &addresses).unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+
+        let (header, addresses) = v2_header(0, 0, &[]);
+        assert_eq!(parse_v2(&header, &addresses).unwrap(), None);
+
+        let (header, addresses) = v2_header(1, 2, &[0; 12]);
+        assert!(parse_v2(&header, &addresses).is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_only_the_header() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1";
+        let addr = read_header(&mut stream).await.unwrap();
+        assert_eq!(addr, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1");
+
+        let (header, addresses) = v2_header(0, 0, &[1, 2, 3]);
+        let bytes = [&header[..], &addresses[..], &b"GET"[..]].concat();
+        let mut stream = &bytes[..];
+        assert_eq!(read_header(&mut stream).await.unwrap(), None);
+        assert_eq!(stream, b"GET");
+
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\n";
+        assert!(read_header(&mut stream).await.is_err());
+    }
+}
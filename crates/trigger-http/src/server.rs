@@ -4,8 +4,12 @@ use std::{
     io::{ErrorKind, IsTerminal},
     marker::PhantomData,
     net::SocketAddr,
+    path::Path,
     pin::Pin,
-    sync::{Arc, OnceLock},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
     task::{Context, Poll},
     time::{Duration, Instant},
 };
@@ -34,14 +38,18 @@ use spin_http::{
     app_info::AppInfo,
     body,
     config::{HttpExecutorType, HttpTriggerConfig},
-    routes::{RouteInfo, RouteMatch, Router},
+    routes::{MethodNotAllowed, RouteDefinition, RouteInfo, RouteMatch, Router, TriggerLookupKey},
     trigger::HandlerType,
 };
+use spin_trigger::GracefulShutdown;
+use spin_world::exports::spin::websocket::inbound_websocket;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpListener,
-    task,
+    sync::oneshot,
 };
+use tokio_rustls::TlsAcceptor;
+use tokio_util::task::{TaskTracker, task_tracker::TaskTrackerToken};
 use tracing::Instrument;
 use wasmtime::{Store, StoreContextMut, ToWasmtimeResult, component::GuestTaskId};
 use wasmtime_wasi::p2::bindings::CommandIndices;
@@ -53,23 +61,45 @@ use wasmtime_wasi_http::p2::body::HyperOutgoingBody;
 use wasmtime_wasi_http::p3::bindings::Service;
 
 use crate::{
-    Body, InstanceReuseConfig, NotFoundRouteKind, OutputFormat, TlsConfig, TriggerApp,
-    TriggerInstanceBuilder,
+    Body, ClientCertificate, HttpTriggerMetadata, InstanceReuseConfig, ListenAddress,
+    NotFoundRouteKind, OutputFormat, Range, TlsConfig, TriggerApp, TriggerInstanceBuilder,
+    access_log::{AccessLog, InstanceReuse, ReuseTracker, RouteComponent},
+    cache::{self, ResponseCache},
+    compression::Compression,
+    concurrency::{self, ConcurrencyLimit, SharedPermit},
+    cors::Cors,
+    error_pages::{self, ERROR_STATUS_HEADER, ErrorPage, ErrorPages, HostError},
+    forwarded::TrustedProxies,
     headers::strip_forbidden_headers,
+    http3,
     instrument::{MatchedRoute, finalize_http_span, http_span, instrument_error},
+    listener::{Connection, Listener},
     outbound_http::OutboundHttpInterceptor,
+    pool::InstancePool,
+    proxy::ReverseProxy,
+    proxy_protocol,
     spin::SpinHttpExecutor,
+    split,
+    static_files::StaticFiles,
+    status::StatusEndpoints,
     wagi::WagiHttpExecutor,
-    wasi::WasiHttpExecutor,
+    wasi::{ReusableInstance, WasiHttpExecutor},
     wasip3::Wasip3HttpExecutor,
+    websocket,
 };
 
 pub const MAX_RETRIES: u16 = 10;
 
+/// How often TLS certificate and key files are checked for changes.
+const CERT_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a new connection may take to send its PROXY protocol header.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// An HTTP server which runs Spin apps.
 pub struct HttpServer<F: RuntimeFactors> {
     /// The address the server was configured to listen on (the `--listen` value).
-    listen_addr: SocketAddr,
+    listen_addr: ListenAddress,
     /// The address the server is actually bound to, captured once after binding.
     ///
     /// This can differ from `listen_addr` when the OS assigns the port — e.g.
@@ -88,16 +118,55 @@ pub struct HttpServer<F: RuntimeFactors> {
     router: Router,
     /// The app being triggered.
     trigger_app: Arc<TriggerApp<F>>,
-    // Component ID -> component trigger config
+    // Trigger lookup key -> trigger config. Triggers for the same component
+    // share a key, so their per-route settings must match.
     component_trigger_configs: HashMap<spin_http::routes::TriggerLookupKey, HttpTriggerConfig>,
     // Component ID -> handler type
     component_handler_types: HashMap<String, HandlerType<HttpHandlerState<F>>>,
+    // Component ID -> WebSocket handler
+    websocket_handlers: HashMap<String, inbound_websocket::GuestIndices>,
+    // Trigger lookup key -> static files directory
+    static_files: HashMap<spin_http::routes::TriggerLookupKey, StaticFiles>,
+    // Trigger lookup key -> upstream server
+    proxies: HashMap<spin_http::routes::TriggerLookupKey, ReverseProxy>,
+    // Trigger lookup key -> response compression
+    compression: HashMap<spin_http::routes::TriggerLookupKey, Compression>,
+    // Trigger lookup key -> CORS policy
+    cors: HashMap<spin_http::routes::TriggerLookupKey, Cors>,
+    // Trigger lookup key -> concurrency limit
+    limits: HashMap<spin_http::routes::TriggerLookupKey, ConcurrencyLimit>,
+    // Trigger lookup key -> response cache
+    caches: HashMap<spin_http::routes::TriggerLookupKey, Arc<ResponseCache>>,
+    /// The token which authorizes cache purge requests, if they are enabled.
+    cache_purge_token: Option<String>,
+    /// The `health`, `ready` and `routes` endpoints, if they are enabled.
+    status_endpoints: Option<StatusEndpoints>,
+    /// Signals the server to stop accepting connections and drain.
+    shutdown: GracefulShutdown,
+    /// How long to wait for in-flight work when shutting down.
+    shutdown_timeout: Duration,
+    /// Tracks connections and reusable instances which must finish before shutdown.
+    tasks: TaskTracker,
+    /// Where to log requests, if anywhere.
+    access_log: Option<Arc<AccessLog>>,
+    /// Records which reusable instance handles each request.
+    reuse: ReuseTracker,
+    /// The range each request's timeout is chosen from, if requests time out.
+    request_timeout: Option<Range<Duration>>,
+    // Component ID -> idle WASIp2 instances
+    pools: HashMap<String, Arc<InstancePool<ReusableInstance<F>>>>,
+    /// Whether connections start with a PROXY protocol header.
+    proxy_protocol: bool,
+    /// The proxies whose forwarding headers give the client address.
+    trusted_proxies: TrustedProxies,
+    /// The app's error pages, if it has any.
+    error_pages: Option<ErrorPages>,
 }
 
 impl<F: RuntimeFactors> HttpServer<F> {
     /// Create a new [`HttpServer`].
     pub fn new(
-        listen_addr: SocketAddr,
+        listen_addr: ListenAddress,
         tls_config: Option<TlsConfig>,
         find_free_port: bool,
         trigger_app: TriggerApp<F>,
@@ -114,19 +183,25 @@ impl<F: RuntimeFactors> HttpServer<F> {
             .collect::<Result<Vec<_>, _>>()?;
 
         // Build router
-        let component_routes = component_trigger_configs
-            .iter()
-            .map(|(key, config)| (key, &config.route));
+        let component_routes = component_trigger_configs.iter().map(|(key, config)| {
+            RouteDefinition::new(key, &config.route)
+                .with_host(config.host.as_deref())
+                .with_methods(&config.methods)
+                .with_headers(&config.headers)
+                .with_weight(config.weight)
+                .with_shadow(config.shadow)
+        });
         let mut duplicate_routes = Vec::new();
-        let router = Router::build("/", component_routes, Some(&mut duplicate_routes))?;
+        let router = Router::build_definitions("/", component_routes, Some(&mut duplicate_routes))?;
         if !duplicate_routes.is_empty() {
             tracing::error!(
                 "The following component routes are duplicates and will never be used:"
             );
             for dup in &duplicate_routes {
                 tracing::error!(
-                    "  {}: {} (duplicate of {})",
+                    "  {}: {}{} (duplicate of {})",
                     dup.replaced_id,
+                    dup.host().unwrap_or_default(),
                     dup.route(),
                     dup.effective_id,
                 );
@@ -143,12 +218,22 @@ impl<F: RuntimeFactors> HttpServer<F> {
             router.routes().collect::<Vec<_>>()
         );
 
+        // Shadow requests are only sent to components.
+        if let Some((key, _)) = component_trigger_configs
+            .iter()
+            .find(|(_, config)| config.shadow && config.component.is_none())
+        {
+            bail!("Trigger '{key}' sets shadow, but only component triggers can be shadows");
+        }
+
         // Now that router is built we can merge duplicate routes by component
-        let component_trigger_configs = HashMap::from_iter(component_trigger_configs);
+        let component_trigger_configs = merge_component_trigger_configs(component_trigger_configs)?;
 
         let trigger_app = Arc::new(trigger_app);
+        let tasks = TaskTracker::new();
+        let reuse = ReuseTracker::default();
 
-        let component_handler_types = component_trigger_configs
+        let component_handler_types: HashMap<_, _> = component_trigger_configs
             .iter()
             .filter_map(|(key, trigger_config)| match key {
                 spin_http::routes::TriggerLookupKey::Component(component) => Some(
@@ -157,12 +242,123 @@ impl<F: RuntimeFactors> HttpServer<F> {
                         component,
                         &trigger_config.executor,
                         reuse_config,
+                        &tasks,
+                        &reuse,
                     )
                     .map(|ht| (component.clone(), ht)),
                 ),
                 spin_http::routes::TriggerLookupKey::Trigger(_) => None,
             })
             .collect::<anyhow::Result<_>>()?;
+
+        // WASIp2 components are only reused if they declare it safe.
+        let mut pools = HashMap::new();
+        for (key, trigger_config) in &component_trigger_configs {
+            let spin_http::routes::TriggerLookupKey::Component(component) = key else {
+                continue;
+            };
+            if !trigger_config.instance_reuse {
+                continue;
+            }
+            match &component_handler_types[component] {
+                HandlerType::Wasi0_2(_)
+                | HandlerType::Wasi2023_11_10(_)
+                | HandlerType::Wasi2023_10_18(_) => {
+                    let pool = InstancePool::new(&reuse_config, reuse.clone());
+                    pools.insert(component.clone(), Arc::new(pool));
+                }
+                HandlerType::Wasi0_3(_) => {}
+                _ => tracing::warn!(
+                    "Component '{component}' sets instance_reuse, but only wasi:http components can reuse instances"
+                ),
+            }
+        }
+
+        // WASIp3 components may also accept WebSocket connections.
+        let websocket_handlers = component_handler_types
+            .iter()
+            .filter(|(_, handler_type)| matches!(handler_type, HandlerType::Wasi0_3(_)))
+            .map(|(component_id, _)| {
+                let pre = trigger_app.get_instance_pre(component_id)?;
+                anyhow::Ok(
+                    inbound_websocket::GuestIndices::new(pre)
+                        .ok()
+                        .map(|indices| (component_id.clone(), indices)),
+                )
+            })
+            .filter_map(Result::transpose)
+            .collect::<anyhow::Result<_>>()?;
+
+        let static_files = component_trigger_configs
+            .iter()
+            .filter_map(|(key, trigger_config)| {
+                let config = trigger_config.static_files.as_ref()?;
+                let static_files = StaticFiles::new(
+                    trigger_app.app(),
+                    &config.directory,
+                    config.index_files.clone(),
+                )
+                .with_context(|| format!("invalid static_files for trigger '{key}'"));
+                Some(static_files.map(|static_files| (key.clone(), static_files)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut proxies = HashMap::new();
+        let mut proxy_client = None;
+        for (key, trigger_config) in &component_trigger_configs {
+            let Some(config) = &trigger_config.proxy else {
+                continue;
+            };
+            // All proxy triggers share one client (and its connection pool).
+            let client = match &proxy_client {
+                Some(client) => client,
+                None => proxy_client.insert(ReverseProxy::client()?),
+            };
+            let proxy = ReverseProxy::new(config, client.clone())
+                .with_context(|| format!("invalid proxy for trigger '{key}'"))?;
+            proxies.insert(key.clone(), proxy);
+        }
+
+        let app_name: Arc<str> = trigger_app
+            .app()
+            .get_metadata(APP_NAME_KEY)?
+            .unwrap_or_else(|| "<unnamed>".into())
+            .into();
+        let limits = component_trigger_configs
+            .iter()
+            .filter_map(|(key, trigger_config)| {
+                let config = trigger_config.concurrency.as_ref()?;
+                let limit = ConcurrencyLimit::new(config, app_name.clone())
+                    .with_context(|| format!("invalid concurrency for trigger '{key}'"));
+                Some(limit.map(|limit| (key.clone(), limit)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let metadata = trigger_app
+            .app()
+            .get_trigger_metadata::<HttpTriggerMetadata>("http")?
+            .unwrap_or_default();
+        let compression = component_trigger_configs
+            .iter()
+            .filter(|(_, trigger_config)| trigger_config.component.is_some())
+            .filter_map(|(key, trigger_config)| {
+                let config = trigger_config
+                    .compression
+                    .as_ref()
+                    .or(metadata.compression.as_ref())?;
+                Some((key.clone(), Compression::new(config)?))
+            })
+            .collect();
+        let cors = component_trigger_configs
+            .iter()
+            .filter_map(|(key, trigger_config)| {
+                let config = trigger_config.cors.as_ref().or(metadata.cors.as_ref())?;
+                let cors =
+                    Cors::new(config).with_context(|| format!("invalid cors for trigger '{key}'"));
+                Some(cors.map(|cors| (key.clone(), cors)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
         Ok(Self {
             listen_addr,
             local_addr: OnceLock::new(),
@@ -173,15 +369,123 @@ impl<F: RuntimeFactors> HttpServer<F> {
             http1_max_buf_size,
             component_trigger_configs,
             component_handler_types,
+            websocket_handlers,
+            static_files,
+            proxies,
+            compression,
+            cors,
+            limits,
             output_format,
+            shutdown: GracefulShutdown::new(),
+            shutdown_timeout: Duration::ZERO,
+            tasks,
+            access_log: None,
+            reuse,
+            request_timeout: reuse_config.request_timeout,
+            pools,
+            caches: HashMap::new(),
+            cache_purge_token: None,
+            status_endpoints: None,
+            proxy_protocol: false,
+            trusted_proxies: TrustedProxies::default(),
+            error_pages: None,
         })
     }
 
+    /// Sets the signal which starts a graceful shutdown, and how long to
+    /// wait for in-flight work to finish once it is requested.
+    pub fn with_graceful_shutdown(
+        mut self,
+        shutdown: GracefulShutdown,
+        shutdown_timeout: Duration,
+    ) -> Self {
+        self.shutdown = shutdown;
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// Sets where to log requests, if anywhere.
+    pub(crate) fn with_access_log(mut self, access_log: Option<AccessLog>) -> Self {
+        self.access_log = access_log.map(Arc::new);
+        self
+    }
+
+    /// Creates the response caches of triggers which have them, with disk
+    /// caches in the state directory, and enables purge requests authorized
+    /// by the given token.
+    pub(crate) fn with_response_caches(
+        mut self,
+        state_dir: Option<&Path>,
+        purge_token: Option<String>,
+    ) -> anyhow::Result<Self> {
+        self.caches = self
+            .component_trigger_configs
+            .iter()
+            .filter(|(_, trigger_config)| trigger_config.component.is_some())
+            .filter_map(|(key, trigger_config)| {
+                let config = trigger_config.cache.as_ref()?;
+                let cache = ResponseCache::new(config, &key.to_string(), state_dir)
+                    .with_context(|| format!("failed to create response cache for '{key}'"));
+                Some(cache.map(|cache| (key.clone(), Arc::new(cache))))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        self.cache_purge_token = purge_token;
+        Ok(self)
+    }
+
+    /// Enables the `health`, `ready` and `routes` endpoints under the
+    /// well-known prefix.
+    pub(crate) fn with_status_endpoints(mut self, enabled: bool) -> Self {
+        self.status_endpoints = enabled.then(StatusEndpoints::default);
+        self
+    }
+
+    /// Sets whether connections start with a PROXY protocol header, and
+    /// which proxies' forwarding headers are trusted, when finding the
+    /// client address of requests.
+    pub(crate) fn with_client_addresses(
+        mut self,
+        proxy_protocol: bool,
+        trusted_proxies: TrustedProxies,
+    ) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Prepares the app's error pages, including error details in them if
+    /// `show_details` is set.
+    pub(crate) fn with_error_pages(mut self, show_details: bool) -> anyhow::Result<Self> {
+        let metadata = self
+            .trigger_app
+            .app()
+            .get_trigger_metadata::<HttpTriggerMetadata>("http")?
+            .unwrap_or_default();
+        if metadata.error_pages.is_empty() {
+            return Ok(self);
+        }
+        let error_pages =
+            ErrorPages::new(self.trigger_app.app(), &metadata.error_pages, show_details)?;
+        // Only components used by triggers are prepared for instantiation.
+        if let Some(component_id) = error_pages
+            .components()
+            .find(|component_id| !self.component_handler_types.contains_key(*component_id))
+        {
+            bail!(
+                "Error page component '{component_id}' must be used by an HTTP trigger, which may have `route = {{ private = true }}`"
+            );
+        }
+        self.error_pages = Some(error_pages);
+        Ok(self)
+    }
+
     fn handler_type_for_component(
         trigger_app: &Arc<TriggerApp<F>>,
         component_id: &str,
         executor: &Option<HttpExecutorType>,
         reuse_config: InstanceReuseConfig,
+        tasks: &TaskTracker,
+        reuse: &ReuseTracker,
     ) -> anyhow::Result<HandlerType<HttpHandlerState<F>>> {
         let pre = trigger_app.get_instance_pre(component_id)?;
         let handler_type = match executor {
@@ -191,6 +495,8 @@ impl<F: RuntimeFactors> HttpServer<F> {
                     trigger_app: trigger_app.clone(),
                     component_id: component_id.into(),
                     reuse_config,
+                    tasks: tasks.clone(),
+                    reuse: reuse.clone(),
                 },
             )?,
             Some(HttpExecutorType::Wagi(wagi_config)) => {
@@ -208,33 +514,88 @@ impl<F: RuntimeFactors> HttpServer<F> {
         Ok(handler_type)
     }
 
-    /// Serve incoming requests over the provided [`TcpListener`].
+    /// Bind the listen address and serve incoming requests.
     pub async fn serve(self: Arc<Self>) -> anyhow::Result<()> {
-        let listener: TcpListener = if self.find_free_port {
-            self.search_for_free_port().await?
-        } else {
-            TcpListener::bind(self.listen_addr).await.map_err(|err| {
+        let listener = match &self.listen_addr {
+            ListenAddress::Tcp(addr) if self.find_free_port => {
+                Listener::Tcp(self.search_for_free_port(*addr).await?)
+            }
+            ListenAddress::Tcp(addr) => Listener::Tcp(TcpListener::bind(addr).await.map_err(|err| {
                 if err.kind() == ErrorKind::AddrInUse {
-                    anyhow::anyhow!("{} is already in use. To have Spin search for a free port, use the --find-free-port option.", self.listen_addr)
+                    anyhow::anyhow!("{addr} is already in use. To have Spin search for a free port, use the --find-free-port option.")
                 } else {
-                    anyhow::anyhow!("Unable to listen on {}: {err:?}", self.listen_addr)
+                    anyhow::anyhow!("Unable to listen on {addr}: {err:?}")
                 }
-            })?
+            })?),
+            ListenAddress::Unix(_) | ListenAddress::Inherited if self.find_free_port => {
+                bail!("--find-free-port can only be used when listening on a TCP address")
+            }
+            ListenAddress::Unix(path) => Listener::unix(path)?,
+            ListenAddress::Inherited => Listener::inherited()?,
         };
 
-        let _ = self.local_addr.set(listener.local_addr()?);
+        if let Some(local_addr) = listener.local_addr() {
+            let _ = self.local_addr.set(local_addr);
+        }
 
         if let Some(tls_config) = self.tls_config.clone() {
-            self.serve_https(listener, tls_config).await?;
+            let resolver = tls_config.cert_resolver()?;
+            tokio::spawn(resolver.clone().reload_on_change(CERT_RELOAD_INTERVAL));
+            let acceptor = tls_config.server_config(resolver.clone())?;
+            if tls_config.http3 {
+                let Some(local_addr) = self.get_local_addr() else {
+                    bail!("HTTP/3 can only be used when listening on a TCP address");
+                };
+                let quic_config = tls_config.quic_server_config(resolver)?;
+                let endpoint = http3::bind_endpoint(local_addr, quic_config)?;
+                tokio::try_join!(
+                    self.clone().serve_https(listener, acceptor),
+                    http3::serve(self.clone(), endpoint),
+                )?;
+            } else {
+                self.clone().serve_https(listener, acceptor).await?;
+            }
         } else {
-            self.serve_http(listener).await?;
+            self.clone().serve_http(listener).await?;
         }
+        self.drain().await;
         Ok(())
     }
 
-    async fn search_for_free_port(&self) -> anyhow::Result<TcpListener> {
+    /// Waits for in-flight connections and instances to finish, up to the shutdown timeout.
+    async fn drain(&self) {
+        self.tasks.close();
+        tracing::info!(
+            "Shutting down: waiting for {} in-flight connections",
+            self.tasks.len()
+        );
+        if tokio::time::timeout(self.shutdown_timeout, self.tasks.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "Shutdown timeout elapsed with {} connections still open",
+                self.tasks.len()
+            );
+        }
+    }
+
+    /// Waits until a graceful shutdown is requested.
+    pub(crate) async fn shutdown_requested(&self) {
+        self.shutdown.requested().await
+    }
+
+    /// Spawns a task which must finish before the server shuts down.
+    pub(crate) fn spawn_tracked<T>(&self, task: T)
+    where
+        T: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    async fn search_for_free_port(&self, listen_addr: SocketAddr) -> anyhow::Result<TcpListener> {
         let mut found_listener = None;
-        let mut addr = self.listen_addr;
+        let mut addr = listen_addr;
 
         for _ in 1..=MAX_RETRIES {
             if addr.port() == u16::MAX {
@@ -258,34 +619,86 @@ impl<F: RuntimeFactors> HttpServer<F> {
 
         found_listener.ok_or_else(|| anyhow::anyhow!(
             "Couldn't find a free port in the range {}-{}. Consider retrying with a different base port.",
-            self.listen_addr.port(),
-            self.listen_addr.port() + MAX_RETRIES
+            listen_addr.port(),
+            listen_addr.port() + MAX_RETRIES
         ))
     }
 
-    async fn serve_http(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+    async fn serve_http(self: Arc<Self>, listener: Listener) -> anyhow::Result<()> {
         self.print_startup_msgs("http", &listener)?;
         loop {
-            let (stream, client_addr) = listener.accept().await?;
-            self.clone()
-                .serve_connection(stream, Scheme::HTTP, client_addr);
+            let (stream, client_addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                () = self.shutdown_requested() => return Ok(()),
+            };
+            let server = self.clone();
+            self.spawn_tracked(async move {
+                if let Some((stream, client_addr)) =
+                    server.read_proxy_header(stream, client_addr).await
+                {
+                    server.serve_connection(stream, Scheme::HTTP, client_addr, None);
+                }
+            });
         }
     }
 
     async fn serve_https(
         self: Arc<Self>,
-        listener: TcpListener,
-        tls_config: TlsConfig,
+        listener: Listener,
+        acceptor: TlsAcceptor,
     ) -> anyhow::Result<()> {
         self.print_startup_msgs("https", &listener)?;
-        let acceptor = tls_config.server_config()?;
         loop {
-            let (stream, client_addr) = listener.accept().await?;
-            match acceptor.accept(stream).await {
-                Ok(stream) => self
-                    .clone()
-                    .serve_connection(stream, Scheme::HTTPS, client_addr),
-                Err(err) => tracing::error!(?err, "Failed to start TLS session"),
+            let (stream, client_addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                () = self.shutdown_requested() => return Ok(()),
+            };
+            let server = self.clone();
+            let acceptor = acceptor.clone();
+            self.spawn_tracked(async move {
+                let Some((stream, client_addr)) =
+                    server.read_proxy_header(stream, client_addr).await
+                else {
+                    return;
+                };
+                match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let client_cert = ClientCertificate::from_peer_certificates(
+                            stream.get_ref().1.peer_certificates(),
+                        );
+                        server.serve_connection(stream, Scheme::HTTPS, client_addr, client_cert)
+                    }
+                    Err(err) => tracing::error!(?err, "Failed to start TLS session"),
+                }
+            });
+        }
+    }
+
+    /// Reads the PROXY protocol header of a new connection if the server
+    /// expects one, returning the connection with its client address. Returns
+    /// `None` if the connection should be closed.
+    async fn read_proxy_header(
+        &self,
+        mut stream: Box<dyn Connection>,
+        peer_addr: SocketAddr,
+    ) -> Option<(Box<dyn Connection>, SocketAddr)> {
+        if !self.proxy_protocol {
+            return Some((stream, peer_addr));
+        }
+        let header = tokio::time::timeout(
+            PROXY_HEADER_TIMEOUT,
+            proxy_protocol::read_header(&mut stream),
+        )
+        .await;
+        match header {
+            Ok(Ok(client_addr)) => Some((stream, client_addr.unwrap_or(peer_addr))),
+            Ok(Err(err)) => {
+                tracing::warn!(%peer_addr, "Closing connection: {err:#}");
+                None
+            }
+            Err(_) => {
+                tracing::warn!(%peer_addr, "Closing connection: no PROXY protocol header received");
+                None
             }
         }
     }
@@ -310,22 +723,71 @@ impl<F: RuntimeFactors> HttpServer<F> {
 
         // Handle well-known spin paths
         if let Some(well_known) = path.strip_prefix(spin_http::WELL_KNOWN_PREFIX) {
-            return match well_known {
-                "health" => Ok(MatchedRoute::with_response_extension(
+            return match (well_known, &self.status_endpoints) {
+                ("health", None) => Ok(MatchedRoute::with_response_extension(
                     Response::new(body::full(Bytes::from_static(b"OK"))),
                     path,
                 )),
-                "info" => self.app_info(path),
-                _ => Self::not_found(NotFoundRouteKind::WellKnown),
+                ("health" | "ready", Some(status_endpoints)) => {
+                    let mut report = status_endpoints.report(&self.trigger_app).await;
+                    report.shutting_down = self.shutdown.is_requested();
+                    // The server is live while it is running, but only ready
+                    // if it can serve requests.
+                    let status = if well_known == "health" || report.ready() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    Ok(MatchedRoute::with_response_extension(
+                        report.response(status)?,
+                        path,
+                    ))
+                }
+                ("routes", Some(_)) => self.routes(path),
+                ("info", _) => self.app_info(path),
+                ("cache/purge", _) => match &self.cache_purge_token {
+                    Some(token) => {
+                        // The body isn't needed, and isn't `Sync` so can't be borrowed across awaits.
+                        let req = Request::from_parts(req.into_parts().0, ());
+                        Ok(MatchedRoute::with_response_extension(
+                            cache::purge(self.caches.values(), &req, token).await?,
+                            path,
+                        ))
+                    }
+                    None => Self::not_found(NotFoundRouteKind::WellKnown),
+                },
+                (_, _) => Self::not_found(NotFoundRouteKind::WellKnown),
             };
         }
 
-        match self.router.route(&path) {
+        let host = request_host(&req);
+
+        // Answer CORS preflight requests for routes with a CORS policy. The preflight is
+        // routed as the request it precedes, since the route may be restricted to that method.
+        if let Some(method) = Cors::preflight_method(&req)
+            && let Ok(route_match) =
+                self.router
+                    .route_request(host.as_deref(), &method, req.headers(), &path)
+            && let Some(cors) = self.cors.get(route_match.lookup_key())
+        {
+            return Ok(MatchedRoute::with_response_extension(
+                cors.preflight(req.headers(), &method)?,
+                route_match.raw_route(),
+            ));
+        }
+
+        match self
+            .router
+            .route_request(host.as_deref(), req.method(), req.headers(), &path)
+        {
             Ok(route_match) => {
                 self.handle_trigger_route(req, route_match, server_scheme, client_addr)
                     .await
             }
-            Err(_) => Self::not_found(NotFoundRouteKind::Normal(path.to_string())),
+            Err(err) => match err.downcast_ref::<MethodNotAllowed>() {
+                Some(not_allowed) => Self::method_not_allowed(not_allowed),
+                None => Self::not_found(NotFoundRouteKind::Normal(path.to_string())),
+            },
         }
     }
 
@@ -337,7 +799,28 @@ impl<F: RuntimeFactors> HttpServer<F> {
         server_scheme: Scheme,
         client_addr: SocketAddr,
     ) -> anyhow::Result<Response<Body>> {
+        // The shadow's route is found again, for the host the request was routed by.
+        let host = request_host(&req);
         set_req_uri(&mut req, server_scheme.clone())?;
+
+        if route_match.shadow().is_some() {
+            let (original, copy) = split::copy_request(req).await?;
+            req = original;
+            if let Some(copy) = copy {
+                self.send_to_shadow(copy, host, server_scheme.clone(), client_addr);
+            }
+        }
+
+        // Requests to a split route go to one of its triggers.
+        let mut set_cookie = None;
+        let route_match = match self.choose_split(&route_match, &req) {
+            Some(choice) => {
+                set_cookie = choice.set_cookie;
+                route_match.into_split(choice.index)
+            }
+            None => route_match,
+        };
+
         let app_id = self
             .trigger_app
             .app()
@@ -358,33 +841,181 @@ impl<F: RuntimeFactors> HttpServer<F> {
             .get(lookup_key)
             .with_context(|| format!("unknown routing destination '{lookup_key}'"))?;
 
-        match (&trigger_config.component, &trigger_config.static_response) {
-            (Some(component), None) => {
-                self.respond_wasm_component(
-                    req,
-                    route_match,
-                    server_scheme,
-                    client_addr,
-                    component,
-                    &trigger_config.executor,
-                )
-                .await
+        // Requests beyond the trigger's concurrency limit wait their turn, or
+        // are turned away if the wait is too long.
+        let permit = match self.limits.get(lookup_key) {
+            Some(limit) => match limit.acquire().await {
+                Some(permit) => Some(SharedPermit::new(permit)),
+                None => {
+                    return Ok(MatchedRoute::with_response_extension(
+                        HostError::with_response_extension(
+                            concurrency::service_unavailable()?,
+                            None,
+                        ),
+                        route_match.raw_route(),
+                    ));
+                }
+            },
+            None => None,
+        };
+        // A WebSocket connection outlasts its response, so holds the permit too.
+        if let Some(permit) = &permit
+            && websocket::is_upgrade_request(&req)
+        {
+            req.extensions_mut().insert(permit.clone());
+        }
+
+        // Keep the request headers the CORS response headers depend on.
+        let cors = self
+            .cors
+            .get(lookup_key)
+            .map(|cors| (cors, req.headers().clone()));
+
+        let res = match (
+            &trigger_config.component,
+            &trigger_config.static_response,
+            &trigger_config.static_files,
+            &trigger_config.proxy,
+        ) {
+            (Some(component), None, None, None) => match self.caches.get(lookup_key) {
+                Some(cache) => {
+                    let raw_route = route_match.raw_route().to_owned();
+                    let res = cache
+                        .handle(req, |req| {
+                            self.respond_wasm_component(
+                                req,
+                                route_match,
+                                server_scheme,
+                                client_addr,
+                                component,
+                                &trigger_config.executor,
+                            )
+                        })
+                        .await;
+                    // Responses served from the cache still count as the component's.
+                    res.map(|res| {
+                        MatchedRoute::with_response_extension(
+                            RouteComponent::with_response_extension(res, component),
+                            raw_route,
+                        )
+                    })
+                }
+                None => {
+                    self.respond_wasm_component(
+                        req,
+                        route_match,
+                        server_scheme,
+                        client_addr,
+                        component,
+                        &trigger_config.executor,
+                    )
+                    .await
+                }
+            },
+            (None, Some(static_response), None, None) => {
+                Self::respond_static_response(static_response)
             }
-            (None, Some(static_response)) => Self::respond_static_response(static_response),
+            (None, None, Some(_), None) => self.respond_static_files(req, route_match).await,
+            (None, None, None, Some(_)) => self.respond_proxy(req, route_match, client_addr).await,
             // These error cases should have been ruled out by this point but belt and braces
-            (None, None) => Err(anyhow::anyhow!(
-                "Triggers must specify either component or static_response - neither is specified for {}",
+            (None, None, None, None) => Err(anyhow::anyhow!(
+                "Triggers must specify one of component, static_response, static_files or proxy - none is specified for {}",
                 route_match.raw_route()
             )),
-            (Some(_), Some(_)) => Err(anyhow::anyhow!(
-                "Triggers must specify either component or static_response - both are specified for {}",
+            _ => Err(anyhow::anyhow!(
+                "Triggers must specify only one of component, static_response, static_files or proxy - more than one is specified for {}",
                 route_match.raw_route()
             )),
+        }?;
+        let mut res = match cors {
+            Some((cors, req_headers)) => cors.apply(&req_headers, res),
+            None => res,
+        };
+        if let Some(cookie) = set_cookie {
+            res.headers_mut().append(http::header::SET_COOKIE, cookie);
         }
+        Ok(match permit {
+            Some(permit) => concurrency::hold_permit(res, permit),
+            None => res,
+        })
     }
 
-    fn get_local_addr(&self) -> SocketAddr {
-        self.local_addr.get().copied().unwrap_or(self.listen_addr)
+    /// Chooses which trigger handles a request to a split route, or `None` if
+    /// requests to the route are not split.
+    fn choose_split(
+        &self,
+        route_match: &RouteMatch<'_, '_>,
+        req: &Request<Body>,
+    ) -> Option<split::Choice> {
+        let split = route_match.split().collect::<Vec<_>>();
+        if split.is_empty() {
+            return None;
+        }
+        let sticky = split
+            .iter()
+            .find_map(|(key, _)| self.component_trigger_configs.get(*key)?.sticky.as_ref());
+        Some(split::choose(&split, sticky, req))
+    }
+
+    /// Sends a copy of a request to the shadow component of its route in the
+    /// background, discarding the response.
+    fn send_to_shadow(
+        self: &Arc<Self>,
+        req: Request<Body>,
+        host: Option<String>,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) {
+        let server = self.clone();
+        self.spawn_tracked(async move {
+            let path = req.uri().path().to_owned();
+            let Some(route_match) = server
+                .router
+                .route_request(host.as_deref(), req.method(), req.headers(), &path)
+                .ok()
+                .and_then(RouteMatch::into_shadow)
+            else {
+                return;
+            };
+            let Some(trigger_config) = server
+                .component_trigger_configs
+                .get(route_match.lookup_key())
+            else {
+                return;
+            };
+            let Some(component_id) = &trigger_config.component else {
+                return;
+            };
+            // Shadow requests never wait for a saturated shadow component.
+            let permit = match server.limits.get(route_match.lookup_key()) {
+                Some(limit) => match limit.try_acquire() {
+                    Some(permit) => Some(permit),
+                    None => return,
+                },
+                None => None,
+            };
+            tracing::debug!("Sending a copy of the request to shadow component '{component_id}'");
+            let res = server
+                .respond_wasm_component(
+                    req,
+                    route_match,
+                    server_scheme,
+                    client_addr,
+                    component_id,
+                    &trigger_config.executor,
+                )
+                .await;
+            // Let the component finish sending its response.
+            if let Ok(res) = res {
+                _ = res.into_body().collect().await;
+            }
+            drop(permit);
+        });
+    }
+
+    /// The TCP address the server is listening on, if it is listening on TCP.
+    pub(crate) fn get_local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr.get().copied().or(self.listen_addr.tcp())
     }
 
     async fn respond_wasm_component(
@@ -396,22 +1027,8 @@ impl<F: RuntimeFactors> HttpServer<F> {
         component_id: &str,
         executor: &Option<HttpExecutorType>,
     ) -> anyhow::Result<Response<Body>> {
-        let mut instance_builder = self.trigger_app.prepare(component_id)?;
-
-        // Set up outbound HTTP request origin and service chaining
-        // The outbound HTTP factor is required since both inbound and outbound wasi HTTP
-        // implementations assume they use the same underlying wasmtime resource storage.
-        // Eventually, we may be able to factor this out to a separate factor.
-        let outbound_http = instance_builder
-            .factor_builder::<OutboundHttpFactor>()
-            .context(
-            "The wasi HTTP trigger was configured without the required wasi outbound http support",
-        )?;
-
-        let self_addr = self.get_local_addr();
-        let origin = SelfRequestOrigin::create(server_scheme, &self_addr.to_string())?;
-        outbound_http.set_self_request_origin(origin);
-        outbound_http.set_request_interceptor(OutboundHttpInterceptor::new(self.clone()))?;
+        // Reused instances were prepared when they were first instantiated.
+        let prepare = || self.prepare_instance(component_id, server_scheme.clone());
 
         // Prepare HTTP executor
         let handler_type = self
@@ -420,15 +1037,36 @@ impl<F: RuntimeFactors> HttpServer<F> {
             .with_context(|| format!("unknown component ID {component_id:?}"))?;
         let executor = executor.as_ref().unwrap_or(&HttpExecutorType::Http);
 
+        let compression = self.compression.get(route_match.lookup_key());
+        let encoding = compression.and_then(|compression| compression.negotiate(&req));
+
+        // WASIp3 instances enforce their own request timeouts.
+        let deadline = self
+            .request_timeout
+            .map(|range| Instant::now() + rand::rng().random_range(range));
+
         let res = match executor {
             HttpExecutorType::Http => match handler_type {
                 HandlerType::Spin => {
                     SpinHttpExecutor
-                        .execute(instance_builder, &route_match, req, client_addr)
+                        .execute(prepare()?, &route_match, req, client_addr, deadline)
                         .await
                 }
+                HandlerType::Wasi0_3(_)
+                    if websocket::is_upgrade_request(&req)
+                        && self.websocket_handlers.contains_key(component_id) =>
+                {
+                    self.respond_websocket(
+                        &self.websocket_handlers[component_id],
+                        prepare()?,
+                        &route_match,
+                        req,
+                        client_addr,
+                    )
+                    .await
+                }
                 HandlerType::Wasi0_3(handler) => {
-                    Wasip3HttpExecutor(handler)
+                    Wasip3HttpExecutor(handler, &self.reuse)
                         .execute(&route_match, req, client_addr)
                         .await
                 }
@@ -436,9 +1074,12 @@ impl<F: RuntimeFactors> HttpServer<F> {
                 | HandlerType::Wasi2023_11_10(_)
                 | HandlerType::Wasi2023_10_18(_)
                 | HandlerType::Wasi2026_03_15(_) => {
-                    WasiHttpExecutor { handler_type }
-                        .execute(instance_builder, &route_match, req, client_addr)
-                        .await
+                    WasiHttpExecutor {
+                        handler_type,
+                        pool: self.pools.get(component_id),
+                    }
+                    .execute(prepare, &route_match, req, client_addr, deadline)
+                    .await
                 }
                 HandlerType::Wagi(_) => unreachable!(),
             },
@@ -452,23 +1093,112 @@ impl<F: RuntimeFactors> HttpServer<F> {
                     indices,
                 };
                 executor
-                    .execute(instance_builder, &route_match, req, client_addr)
+                    .execute(prepare()?, &route_match, req, client_addr, deadline)
                     .await
             }
         };
         match res {
-            Ok(res) => Ok(MatchedRoute::with_response_extension(
-                res,
-                route_match.raw_route(),
-            )),
+            Ok(res) => {
+                let res = match compression {
+                    Some(compression) => compression.apply(res, encoding),
+                    None => res,
+                };
+                Ok(MatchedRoute::with_response_extension(
+                    RouteComponent::with_response_extension(res, component_id),
+                    route_match.raw_route(),
+                ))
+            }
+            Err(err) if is_timeout(&err) => {
+                tracing::warn!("Component '{component_id}' timed out handling request: {err:?}");
+                instrument_error(&err);
+                Self::gateway_timeout(route_match.raw_route()).map(|res| {
+                    let res = HostError::with_response_extension(res, Some(format!("{err:?}")));
+                    RouteComponent::with_response_extension(res, component_id)
+                })
+            }
             Err(err) => {
                 tracing::error!("Error processing request: {err:?}");
                 instrument_error(&err);
-                Self::internal_error(None, route_match.raw_route())
+                Self::internal_error(None, route_match.raw_route()).map(|res| {
+                    let res = HostError::with_response_extension(res, Some(format!("{err:?}")));
+                    RouteComponent::with_response_extension(res, component_id)
+                })
             }
         }
     }
 
+    /// Completes a WebSocket handshake, handing the upgraded connection to the
+    /// component's `inbound-websocket` export.
+    /// Prepares an instance of a component to handle a request received over
+    /// `server_scheme`.
+    fn prepare_instance(
+        self: &Arc<Self>,
+        component_id: &str,
+        server_scheme: Scheme,
+    ) -> anyhow::Result<TriggerInstanceBuilder<'_, F>> {
+        let mut instance_builder = self.trigger_app.prepare(component_id)?;
+
+        // Set up outbound HTTP request origin and service chaining
+        // The outbound HTTP factor is required since both inbound and outbound wasi HTTP
+        // implementations assume they use the same underlying wasmtime resource storage.
+        // Eventually, we may be able to factor this out to a separate factor.
+        let outbound_http = instance_builder
+            .factor_builder::<OutboundHttpFactor>()
+            .context(
+            "The wasi HTTP trigger was configured without the required wasi outbound http support",
+        )?;
+
+        // Requests to relative URLs are sent to the server's TCP address, so
+        // aren't available when listening on a Unix domain socket.
+        if let Some(self_addr) = self.get_local_addr() {
+            let origin = SelfRequestOrigin::create(server_scheme, &self_addr.to_string())?;
+            outbound_http.set_self_request_origin(origin);
+        }
+        outbound_http.set_request_interceptor(OutboundHttpInterceptor::new(self.clone()))?;
+
+        Ok(instance_builder)
+    }
+
+    async fn respond_websocket(
+        &self,
+        indices: &inbound_websocket::GuestIndices,
+        instance_builder: TriggerInstanceBuilder<'_, F>,
+        route_match: &RouteMatch<'_, '_>,
+        mut req: Request<Body>,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        let accept_key = match websocket::accept_key(&req) {
+            Ok(accept_key) => accept_key,
+            Err(reason) => {
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(body::full(reason.into()))?);
+            }
+        };
+        crate::wasi::prepare_request(route_match, &mut req, client_addr)?;
+        let request = websocket::upgrade_request(&req);
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        let permit = req.extensions_mut().remove::<SharedPermit>();
+
+        // Each connection gets its own instance, which lives as long as the connection.
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        let guest = indices.load(&mut store, &instance)?;
+        let (decision_tx, decision_rx) = oneshot::channel();
+        let shutdown = self.shutdown.clone();
+        self.spawn_tracked(async move {
+            let res =
+                websocket::run(store, guest, request, on_upgrade, decision_tx, shutdown).await;
+            if let Err(err) = res {
+                tracing::error!("Error handling WebSocket connection: {err:?}");
+            }
+            drop(permit);
+        });
+        let decision = decision_rx
+            .await
+            .context("WebSocket handler failed before accepting the connection")?;
+        websocket::response(accept_key, decision)
+    }
+
     fn respond_static_response(
         sr: &spin_http::config::StaticResponse,
     ) -> anyhow::Result<Response<Body>> {
@@ -487,6 +1217,93 @@ impl<F: RuntimeFactors> HttpServer<F> {
         Ok(response.body(body)?)
     }
 
+    async fn respond_static_files(
+        &self,
+        req: Request<Body>,
+        route_match: RouteMatch<'_, '_>,
+    ) -> anyhow::Result<Response<Body>> {
+        let lookup_key = route_match.lookup_key();
+        let static_files = self
+            .static_files
+            .get(lookup_key)
+            .with_context(|| format!("no static files configured for '{lookup_key}'"))?;
+        // The body isn't needed, and isn't `Sync` so can't be borrowed across awaits.
+        let req = Request::from_parts(req.into_parts().0, ());
+        match static_files
+            .serve(&req, &route_match.trailing_wildcard())
+            .await
+        {
+            Ok(res) => Ok(MatchedRoute::with_response_extension(
+                res,
+                route_match.raw_route(),
+            )),
+            Err(err) => {
+                tracing::error!("Error serving static files: {err:?}");
+                Self::internal_error(None, route_match.raw_route())
+            }
+        }
+    }
+
+    async fn respond_proxy(
+        &self,
+        req: Request<Body>,
+        route_match: RouteMatch<'_, '_>,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        let lookup_key = route_match.lookup_key();
+        let proxy = self
+            .proxies
+            .get(lookup_key)
+            .with_context(|| format!("no proxy configured for '{lookup_key}'"))?;
+        match proxy
+            .forward(req, &route_match.trailing_wildcard(), client_addr)
+            .await
+        {
+            Ok(res) => Ok(MatchedRoute::with_response_extension(
+                res,
+                route_match.raw_route(),
+            )),
+            Err(err) => {
+                tracing::error!("Error forwarding request upstream: {err:?}");
+                Self::internal_error(None, route_match.raw_route())
+            }
+        }
+    }
+
+    /// Returns the routing table.
+    fn routes(&self, route: String) -> anyhow::Result<Response<Body>> {
+        #[derive(serde::Serialize)]
+        struct RoutesOutput {
+            routes: Vec<RouteEntry>,
+        }
+
+        let body = serde_json::to_vec_pretty(&RoutesOutput {
+            routes: self.route_entries()?,
+        })?;
+        Ok(MatchedRoute::with_response_extension(
+            Response::builder()
+                .header("content-type", "application/json")
+                .body(body::full(body.into()))?,
+            route,
+        ))
+    }
+
+    /// Describes the routes the server handles.
+    fn route_entries(&self) -> anyhow::Result<Vec<RouteEntry>> {
+        self.router
+            .routes()
+            .map(|(route, key)| {
+                Ok(RouteEntry {
+                    id: key.to_string(),
+                    host: route.host().map(str::to_owned),
+                    route: route.path().to_string(),
+                    wildcard: route.is_wildcard(),
+                    description: self.get_description_for_route(key)?,
+                })
+            })
+            .collect()
+    }
+
     /// Returns spin status information.
     fn app_info(&self, route: String) -> anyhow::Result<Response<Body>> {
         let info = AppInfo::new(self.trigger_app.app());
@@ -509,29 +1326,56 @@ impl<F: RuntimeFactors> HttpServer<F> {
             None => body::empty(),
         };
 
+        let res = Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(body)?;
         Ok(MatchedRoute::with_response_extension(
-            Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(body)?,
+            HostError::with_response_extension(res, None),
+            route,
+        ))
+    }
+
+    /// Creates an HTTP 504 response, for a component which didn't respond in time.
+    fn gateway_timeout(route: impl Into<String>) -> anyhow::Result<Response<Body>> {
+        let res = Response::builder()
+            .status(StatusCode::GATEWAY_TIMEOUT)
+            .body(body::empty())?;
+        Ok(MatchedRoute::with_response_extension(
+            HostError::with_response_extension(res, None),
             route,
         ))
     }
 
     /// Creates an HTTP 404 response.
     fn not_found(kind: NotFoundRouteKind) -> anyhow::Result<Response<Body>> {
-        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::atomic::AtomicBool;
         static SHOWN_GENERIC_404_WARNING: AtomicBool = AtomicBool::new(false);
-        if let NotFoundRouteKind::Normal(route) = kind
+        if let NotFoundRouteKind::Normal(route) = &kind
             && !SHOWN_GENERIC_404_WARNING.fetch_or(true, Ordering::Relaxed)
             && std::io::stderr().is_terminal()
         {
             terminal::warn!(
-                "Request to {route} matched no pattern, and received a generic 404 response. To serve a more informative 404 page, add a catch-all (/...) route."
+                "Request to {route} matched no pattern, and received a generic 404 response. To serve a more informative 404 page, add a catch-all (/...) route or a 404 error page."
             );
         }
-        Ok(Response::builder()
+        let res = Response::builder()
             .status(StatusCode::NOT_FOUND)
-            .body(body::empty())?)
+            .body(body::empty())?;
+        // Only the app's own routes get the app's error pages.
+        Ok(match kind {
+            NotFoundRouteKind::Normal(_) => HostError::with_response_extension(res, None),
+            NotFoundRouteKind::WellKnown => res,
+        })
+    }
+
+    fn method_not_allowed(not_allowed: &MethodNotAllowed) -> anyhow::Result<Response<Body>> {
+        Ok(HostError::with_response_extension(
+            Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(http::header::ALLOW, not_allowed.allow_header_value())
+                .body(body::empty())?,
+            None,
+        ))
     }
 
     fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
@@ -539,27 +1383,40 @@ impl<F: RuntimeFactors> HttpServer<F> {
         stream: S,
         server_scheme: Scheme,
         client_addr: SocketAddr,
+        client_cert: Option<ClientCertificate>,
     ) {
-        task::spawn(async move {
+        let server = self.clone();
+        self.spawn_tracked(async move {
             let mut server_builder = Builder::new(TokioExecutor::new());
 
-            if let Some(http1_max_buf_size) = self.http1_max_buf_size {
+            if let Some(http1_max_buf_size) = server.http1_max_buf_size {
                 server_builder.http1().max_buf_size(http1_max_buf_size);
             }
 
-            if let Err(err) = server_builder
-                .serve_connection(
-                    TokioIo::new(stream),
-                    service_fn(move |request| {
-                        self.clone().instrumented_service_fn(
-                            server_scheme.clone(),
-                            client_addr,
-                            request,
-                        )
-                    }),
-                )
-                .await
-            {
+            let shutdown = server.shutdown.clone();
+            let conn = server_builder.serve_connection_with_upgrades(
+                TokioIo::new(stream),
+                service_fn(move |mut request: Request<Incoming>| {
+                    if let Some(client_cert) = &client_cert {
+                        request.extensions_mut().insert(client_cert.clone());
+                    }
+                    server.clone().instrumented_service_fn(
+                        server_scheme.clone(),
+                        client_addr,
+                        request,
+                    )
+                }),
+            );
+            tokio::pin!(conn);
+            // On shutdown, finish in-flight requests and then close the connection.
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                () = shutdown.requested() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(err) = result {
                 tracing::warn!("Error serving HTTP connection: {err:?}");
             }
         });
@@ -571,25 +1428,183 @@ impl<F: RuntimeFactors> HttpServer<F> {
         client_addr: SocketAddr,
         request: Request<Incoming>,
     ) -> anyhow::Result<Response<HyperOutgoingBody>> {
+        let alt_svc = self.http3_alt_svc();
+        let mut result = self
+            .instrumented_handle(
+                server_scheme,
+                client_addr,
+                request.map(|body: Incoming| {
+                    body.map_err(wasmtime_wasi_http::p2::hyper_response_error)
+                        .boxed_unsync()
+                }),
+            )
+            .await;
+        if let (Ok(response), Some(alt_svc)) = (&mut result, alt_svc) {
+            response
+                .headers_mut()
+                .entry(http::header::ALT_SVC)
+                .or_insert(alt_svc);
+        }
+        result
+    }
+
+    /// Handles a request within an HTTP request tracing span.
+    pub(crate) async fn instrumented_handle(
+        self: Arc<Self>,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+        request: Request<Body>,
+    ) -> anyhow::Result<Response<Body>> {
+        let client_addr = self
+            .trusted_proxies
+            .client_addr(client_addr, request.headers());
         let span = http_span!(request, client_addr);
         let method = request.method().to_string();
-        async {
-            let result = self
-                .handle(
-                    request.map(|body: Incoming| {
-                        body.map_err(wasmtime_wasi_http::p2::hyper_response_error)
-                            .boxed_unsync()
-                    }),
-                    server_scheme,
-                    client_addr,
-                )
+        // Error pages are made for the request, which is consumed by handling it.
+        let error_page_request = self
+            .error_pages
+            .is_some()
+            .then(|| (request.uri().clone(), request.headers().clone()));
+        let access_log_entry = self
+            .access_log
+            .as_ref()
+            .map(|access_log| access_log.start(&request, client_addr));
+        let result = async {
+            let mut result = self
+                .handle(request, server_scheme.clone(), client_addr)
                 .await;
+            if let (Ok(res), Some((uri, headers))) = (&mut result, error_page_request) {
+                let original = std::mem::replace(res, Response::new(body::empty()));
+                *res = self
+                    .apply_error_page(original, uri, headers, server_scheme, client_addr)
+                    .await;
+            }
             finalize_http_span(result, method)
         }
         .instrument(span)
+        .await;
+        match (access_log_entry, result) {
+            (Some(entry), Ok(res)) => Ok(entry.finish(res)),
+            (Some(entry), Err(err)) => {
+                entry.fail();
+                Err(err)
+            }
+            (None, result) => result,
+        }
+    }
+
+    /// Replaces the body of an error response made by the host with the app's
+    /// error page for its status, if it has one.
+    async fn apply_error_page(
+        self: &Arc<Self>,
+        res: Response<Body>,
+        uri: Uri,
+        req_headers: http::HeaderMap,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) -> Response<Body> {
+        let (Some(error_pages), Some(error)) =
+            (&self.error_pages, res.extensions().get::<HostError>())
+        else {
+            return res;
+        };
+        let status = res.status();
+        let Some(page) = error_pages.page(status) else {
+            return res;
+        };
+        match page {
+            ErrorPage::File {
+                content,
+                content_type,
+            } => error_pages::replace_body(res, content_type, content.clone()),
+            ErrorPage::Template(template) => {
+                let (content_type, content) = error_pages::render_template(
+                    template,
+                    status,
+                    uri.path(),
+                    error_pages.details(error),
+                    &req_headers,
+                );
+                error_pages::replace_body(res, content_type, content)
+            }
+            ErrorPage::Component(component_id) => {
+                let page = self
+                    .respond_error_page_component(
+                        component_id,
+                        status,
+                        uri,
+                        req_headers,
+                        server_scheme,
+                        client_addr,
+                    )
+                    .await;
+                match page {
+                    // The page keeps the status and extensions of the original response.
+                    Ok(page) if page.status().is_success() => {
+                        let (page_parts, page_body) = page.into_parts();
+                        let (mut parts, _) = res.into_parts();
+                        parts.headers = page_parts.headers;
+                        Response::from_parts(parts, page_body)
+                    }
+                    Ok(page) => {
+                        tracing::error!(
+                            "Error page component '{component_id}' responded with {}",
+                            page.status()
+                        );
+                        res
+                    }
+                    Err(err) => {
+                        tracing::error!("Error page component '{component_id}' failed: {err:?}");
+                        res
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a component a `GET` request for the page of an error response to
+    /// a request.
+    async fn respond_error_page_component(
+        self: &Arc<Self>,
+        component_id: &str,
+        status: StatusCode,
+        uri: Uri,
+        req_headers: http::HeaderMap,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        let trigger_config = self
+            .component_trigger_configs
+            .get(&spin_http::routes::TriggerLookupKey::Component(
+                component_id.into(),
+            ))
+            .with_context(|| format!("unknown component ID {component_id:?}"))?;
+        let path = uri.path().to_owned();
+        let mut req = Request::builder().uri(uri).body(body::empty())?;
+        *req.headers_mut() = req_headers;
+        req.headers_mut()
+            .insert(ERROR_STATUS_HEADER, status.as_u16().into());
+        set_req_uri(&mut req, server_scheme.clone())?;
+        self.respond_wasm_component(
+            req,
+            RouteMatch::synthetic(component_id.into(), path),
+            server_scheme,
+            client_addr,
+            component_id,
+            &trigger_config.executor,
+        )
         .await
     }
 
+    /// The `Alt-Svc` header to add to TCP responses, if HTTP/3 is enabled.
+    fn http3_alt_svc(&self) -> Option<http::HeaderValue> {
+        self.tls_config
+            .as_ref()
+            .filter(|tls_config| tls_config.http3)
+            .and_then(|_| self.get_local_addr())
+            .map(|addr| http3::alt_svc_header_value(addr.port()))
+    }
+
     fn get_description_for_route(
         &self,
         key: &spin_http::routes::TriggerLookupKey,
@@ -606,17 +1621,26 @@ impl<F: RuntimeFactors> HttpServer<F> {
         }
     }
 
-    fn print_startup_msgs(&self, scheme: &str, listener: &TcpListener) -> anyhow::Result<()> {
-        let local_addr = listener.local_addr()?;
-        let base_url = format!("{scheme}://{local_addr:?}");
+    fn print_startup_msgs(&self, scheme: &str, listener: &Listener) -> anyhow::Result<()> {
+        let local_addr = listener.local_addr();
+        let base_url = listener.base_url(scheme);
         tracing::info!("Serving {base_url}");
 
         match self.output_format {
             OutputFormat::Plain => {
                 terminal::step!("\nServing", "{base_url}");
+                if let (Some(local_addr), Some(_)) = (local_addr, self.http3_alt_svc()) {
+                    println!("HTTP/3 is also available on UDP port {}", local_addr.port());
+                }
                 println!("Available Routes:");
                 for (route, key) in self.router.routes() {
-                    println!("  {key}: {base_url}{route}");
+                    match (route.host(), local_addr) {
+                        (Some(host), Some(local_addr)) => {
+                            println!("  {key}: {scheme}://{host}:{}{route}", local_addr.port())
+                        }
+                        (Some(host), None) => println!("  {key}: {scheme}://{host}{route}"),
+                        (None, _) => println!("  {key}: {base_url}{route}"),
+                    }
                     if let Some(description) = self.get_description_for_route(key)? {
                         println!("    {description}");
                     }
@@ -626,28 +1650,16 @@ impl<F: RuntimeFactors> HttpServer<F> {
                 #[derive(serde::Serialize)]
                 struct RoutesOutput {
                     base_url: String,
+                    #[serde(skip_serializing_if = "std::ops::Not::not")]
+                    http3: bool,
                     routes: Vec<RouteEntry>,
                 }
 
-                #[derive(serde::Serialize)]
-                struct RouteEntry {
-                    id: String,
-                    route: String,
-                    wildcard: bool,
-                    #[serde(skip_serializing_if = "Option::is_none")]
-                    description: Option<String>,
-                }
-                let mut routes = Vec::new();
-                for (route, key) in self.router.routes() {
-                    routes.push(RouteEntry {
-                        id: key.to_string(),
-                        route: route.path().to_string(),
-                        wildcard: route.is_wildcard(),
-                        description: self.get_description_for_route(key)?,
-                    });
-                }
-
-                let output = RoutesOutput { base_url, routes };
+                let output = RoutesOutput {
+                    base_url,
+                    http3: self.http3_alt_svc().is_some(),
+                    routes: self.route_entries()?,
+                };
                 println!("{}", serde_json::to_string_pretty(&output)?);
             }
         }
@@ -655,6 +1667,61 @@ impl<F: RuntimeFactors> HttpServer<F> {
     }
 }
 
+/// Merges the configs of triggers by lookup key. Triggers for the same
+/// component share one key, and so one set of per-route settings, so it is
+/// an error for them to configure those settings differently.
+fn merge_component_trigger_configs(
+    configs: Vec<(TriggerLookupKey, HttpTriggerConfig)>,
+) -> anyhow::Result<HashMap<TriggerLookupKey, HttpTriggerConfig>> {
+    let mut merged = HashMap::<_, HttpTriggerConfig>::new();
+    for (key, config) in configs {
+        if let Some(existing) = merged.get(&key) {
+            let differences = [
+                ("compression", existing.compression != config.compression),
+                ("cors", existing.cors != config.cors),
+                ("cache", existing.cache != config.cache),
+                (
+                    "instance_reuse",
+                    existing.instance_reuse != config.instance_reuse,
+                ),
+                ("concurrency", existing.concurrency != config.concurrency),
+            ];
+            if let Some((setting, _)) = differences.iter().find(|(_, differs)| *differs) {
+                bail!(
+                    "Triggers for component '{key}' have different {setting} settings, but all routes to a component must have the same settings"
+                );
+            }
+        }
+        merged.insert(key, config);
+    }
+    Ok(merged)
+}
+
+/// A route, as printed at startup and returned by the `routes` endpoint.
+#[derive(serde::Serialize)]
+struct RouteEntry {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    route: String,
+    wildcard: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+/// The host the request is addressed to, without any port.
+///
+/// This uses the request URI's authority if present (as in HTTP/2 and HTTP/3),
+/// otherwise the `Host` header.
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    if let Some(host) = req.uri().host() {
+        return Some(host.to_owned());
+    }
+    let host_header = req.headers().get(http::header::HOST)?.to_str().ok()?;
+    let authority: Authority = host_header.parse().ok()?;
+    Some(authority.host().to_owned())
+}
+
 /// The incoming request's scheme and authority
 ///
 /// The incoming request's URI is relative to the server, so we need to set the scheme and authority.
@@ -699,15 +1766,57 @@ fn set_req_uri(req: &mut Request<Body>, scheme: Scheme) -> anyhow::Result<()> {
 
 /// An HTTP executor.
 pub(crate) trait HttpExecutor {
+    /// Handles the request with a new instance.
+    ///
+    /// If the guest is still running at the deadline, it is stopped and
+    /// execution fails with [`RequestTimedOut`].
     fn execute<F: RuntimeFactors>(
         &self,
         instance_builder: TriggerInstanceBuilder<F>,
         route_match: &RouteMatch<'_, '_>,
         req: Request<Body>,
         client_addr: SocketAddr,
+        deadline: Option<Instant>,
     ) -> impl Future<Output = anyhow::Result<Response<Body>>>;
 }
 
+/// The error when a component is still handling a request at its deadline.
+#[derive(Debug)]
+pub(crate) struct RequestTimedOut;
+
+impl std::fmt::Display for RequestTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the component did not finish handling the request in time")
+    }
+}
+
+impl std::error::Error for RequestTimedOut {}
+
+/// Runs a guest call, failing with [`RequestTimedOut`] if it has not
+/// finished by the deadline.
+///
+/// Guest code which doesn't yield is interrupted by the store's epoch
+/// deadline instead, so the store should have the same deadline.
+pub(crate) async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    call: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), call)
+            .await
+            .unwrap_or_else(|_| Err(RequestTimedOut.into())),
+        None => call.await,
+    }
+}
+
+/// Whether the error is because the guest was still running at its deadline.
+fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<RequestTimedOut>()
+            || cause.downcast_ref::<wasmtime::Trap>() == Some(&wasmtime::Trap::Interrupt)
+    })
+}
+
 pin_project! {
     pub(crate) struct HttpWorkerExpiration {
         idle_timeout: Duration,
@@ -750,12 +1859,19 @@ pub(crate) struct HttpWorkerState<F: RuntimeFactors> {
     request_timeout: Duration,
     max_instance_reuse_count: usize,
     max_instance_concurrent_reuse_count: usize,
+    /// Keeps shutdown waiting until the instance is dropped.
+    _in_flight: TaskTrackerToken,
+    /// Identifies the instance in access logs.
+    instance_id: u64,
+    /// How many requests the instance has started.
+    requests_started: AtomicU64,
+    reuse: ReuseTracker,
     _phantom: PhantomData<F>,
 }
 
 impl<F: RuntimeFactors> WorkerState for HttpWorkerState<F> {
     type StoreData = InstanceState<F::InstanceState, ()>;
-    type RequestId = ();
+    type RequestId = u64;
 
     fn should_accept_request(&self, concurrent_count: usize, total_count: usize) -> ShouldAccept {
         if total_count >= self.max_instance_reuse_count {
@@ -770,9 +1886,17 @@ impl<F: RuntimeFactors> WorkerState for HttpWorkerState<F> {
     fn on_request_start(
         &self,
         _: StoreContextMut<'_, Self::StoreData>,
-        _: Self::RequestId,
+        request_id: Self::RequestId,
         _: GuestTaskId,
     ) -> Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>> {
+        let request = self.requests_started.fetch_add(1, Ordering::Relaxed) + 1;
+        self.reuse.started(
+            request_id,
+            InstanceReuse {
+                instance: self.instance_id,
+                request,
+            },
+        );
         Box::pin(tokio::time::sleep(self.request_timeout))
     }
 
@@ -789,6 +1913,8 @@ pub(crate) struct HttpHandlerState<F: RuntimeFactors> {
     trigger_app: Arc<TriggerApp<F>>,
     component_id: String,
     reuse_config: InstanceReuseConfig,
+    tasks: TaskTracker,
+    reuse: ReuseTracker,
 }
 
 impl<F: RuntimeFactors> HandlerState for HttpHandlerState<F> {
@@ -838,8 +1964,60 @@ impl<F: RuntimeFactors> HandlerState for HttpHandlerState<F> {
                     .random_range(self.reuse_config.max_instance_reuse_count),
                 max_instance_concurrent_reuse_count: rand::rng()
                     .random_range(self.reuse_config.max_instance_concurrent_reuse_count),
+                _in_flight: self.tasks.token(),
+                instance_id: self.reuse.next_instance_id(),
+                requests_started: AtomicU64::new(0),
+                reuse: self.reuse.clone(),
                 _phantom: PhantomData,
             },
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component_trigger(
+        route: &str,
+        extra: serde_json::Value,
+    ) -> (TriggerLookupKey, HttpTriggerConfig) {
+        let mut config = serde_json::json!({ "component": "shared", "route": route });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        let config: HttpTriggerConfig = serde_json::from_value(config).unwrap();
+        (config.lookup_key(route).unwrap(), config)
+    }
+
+    #[test]
+    fn triggers_for_one_component_must_share_settings() {
+        let cors = serde_json::json!({ "cors": { "allowed_origins": ["https://example.com"] } });
+
+        let merged = merge_component_trigger_configs(vec![
+            component_trigger("/a", cors.clone()),
+            component_trigger("/b", cors.clone()),
+        ])
+        .unwrap();
+        assert_eq!(merged.len(), 1);
+
+        let err = merge_component_trigger_configs(vec![
+            component_trigger("/a", cors),
+            component_trigger("/b", serde_json::json!({})),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("different cors settings"), "{err}");
+
+        let err = merge_component_trigger_configs(vec![
+            component_trigger("/a", serde_json::json!({ "instance_reuse": true })),
+            component_trigger("/b", serde_json::json!({})),
+        ])
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("different instance_reuse settings"),
+            "{err}"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Instant};
 
 use anyhow::Result;
 use http_body_util::BodyExt;
@@ -12,7 +12,7 @@ use tracing::{Level, instrument};
 use crate::{
     Body, TriggerInstanceBuilder,
     headers::{append_headers, prepare_request_headers},
-    server::HttpExecutor,
+    server::{HttpExecutor, with_deadline},
 };
 
 /// An [`HttpExecutor`] that uses the `fermyon:spin/inbound-http` interface.
@@ -27,6 +27,7 @@ impl HttpExecutor for SpinHttpExecutor {
         route_match: &RouteMatch<'_, '_>,
         req: Request<Body>,
         client_addr: SocketAddr,
+        deadline: Option<Instant>,
     ) -> Result<Response<Body>> {
         let spin_http::routes::TriggerLookupKey::Component(component_id) = route_match.lookup_key()
         else {
@@ -36,6 +37,9 @@ impl HttpExecutor for SpinHttpExecutor {
         tracing::trace!("Executing request using the Spin executor for component {component_id}");
 
         let (instance, mut store) = instance_builder.instantiate(()).await?;
+        if let Some(deadline) = deadline {
+            store.set_deadline(deadline);
+        }
 
         let headers = prepare_request_headers(&req, route_match, client_addr)?;
         // Expects here are safe since we have already checked that this
@@ -80,7 +84,10 @@ impl HttpExecutor for SpinHttpExecutor {
             body: Some(bytes),
         };
 
-        let (resp,) = func.call_async(&mut store, (req,)).await?;
+        let (resp,) = with_deadline(deadline, async {
+            Ok(func.call_async(&mut store, (req,)).await?)
+        })
+        .await?;
 
         if resp.status < 100 || resp.status > 600 {
             tracing::error!("malformed HTTP status code");
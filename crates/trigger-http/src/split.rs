@@ -0,0 +1,222 @@
+//! Splitting a route's requests between triggers by weight, and copying
+//! requests to shadow components.
+
+use http::{HeaderValue, Request, header};
+use http_body::Body as _;
+use http_body_util::BodyExt;
+use rand::RngExt;
+use sha2::{Digest, Sha256};
+use spin_http::{body, config::StickyConfig, routes::TriggerLookupKey};
+
+use crate::{Body, ClientCertificate};
+
+/// The largest request body copied to a shadow component. Requests with
+/// larger bodies, or bodies of unknown length, are not copied.
+const MAX_SHADOW_BODY_SIZE: u64 = 1024 * 1024;
+
+/// The handler chosen for a request to a split route.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Choice {
+    /// The index of the handler in the split.
+    pub index: usize,
+    /// The cookie to set on the response, so later requests go to the same handler.
+    pub set_cookie: Option<HeaderValue>,
+}
+
+/// Chooses which of the handlers a request to a split route goes to.
+pub(crate) fn choose<B>(
+    split: &[(&TriggerLookupKey, u32)],
+    sticky: Option<&StickyConfig>,
+    req: &Request<B>,
+) -> Choice {
+    let total: u64 = split.iter().map(|(_, weight)| u64::from(*weight)).sum();
+    let random = || rand::rng().random_range(0..total);
+    match sticky {
+        Some(StickyConfig::Cookie(name)) => {
+            let assigned = cookie(req, name).and_then(|value| {
+                split
+                    .iter()
+                    .position(|(key, weight)| *weight > 0 && key.to_string() == value)
+            });
+            match assigned {
+                Some(index) => Choice {
+                    index,
+                    set_cookie: None,
+                },
+                None => {
+                    let index = pick(split, random());
+                    let cookie =
+                        format!("{name}={}; Path=/; HttpOnly; SameSite=Lax", split[index].0);
+                    Choice {
+                        index,
+                        set_cookie: HeaderValue::try_from(cookie).ok(),
+                    }
+                }
+            }
+        }
+        Some(StickyConfig::Header(name)) => {
+            // The same header value always maps to the same point.
+            let point = match req.headers().get(name) {
+                Some(value) => {
+                    let digest = Sha256::digest(value.as_bytes());
+                    u64::from_be_bytes(digest[..8].try_into().unwrap()) % total
+                }
+                None => random(),
+            };
+            Choice {
+                index: pick(split, point),
+                set_cookie: None,
+            }
+        }
+        None => Choice {
+            index: pick(split, random()),
+            set_cookie: None,
+        },
+    }
+}
+
+/// Returns the index of the handler whose share of the total weight
+/// contains the point.
+fn pick(split: &[(&TriggerLookupKey, u32)], mut point: u64) -> usize {
+    for (index, (_, weight)) in split.iter().enumerate() {
+        match point.checked_sub(u64::from(*weight)) {
+            Some(rest) => point = rest,
+            None => return index,
+        }
+    }
+    split.len() - 1
+}
+
+fn cookie<'a, B>(req: &'a Request<B>, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find_map(|(key, value)| (key == name).then_some(value))
+}
+
+/// Copies the request for a shadow component, if its body is small enough to
+/// be buffered. Returns the original request, with its body buffered if it
+/// was copied.
+pub(crate) async fn copy_request(
+    req: Request<Body>,
+) -> anyhow::Result<(Request<Body>, Option<Request<Body>>)> {
+    if req
+        .body()
+        .size_hint()
+        .upper()
+        .is_none_or(|size| size > MAX_SHADOW_BODY_SIZE)
+    {
+        tracing::debug!("Not copying request to shadow component: body is too large");
+        return Ok((req, None));
+    }
+
+    let (parts, req_body) = req.into_parts();
+    let bytes = req_body.collect().await?.to_bytes();
+
+    let mut copy = Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(body::full(bytes.clone()))?;
+    *copy.headers_mut() = parts.headers.clone();
+    if let Some(client_cert) = parts.extensions.get::<ClientCertificate>() {
+        copy.extensions_mut().insert(client_cert.clone());
+    }
+
+    Ok((Request::from_parts(parts, body::full(bytes)), Some(copy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> [TriggerLookupKey; 2] {
+        [
+            TriggerLookupKey::Component("cart-v1".into()),
+            TriggerLookupKey::Component("cart-v2".into()),
+        ]
+    }
+
+    #[test]
+    fn picks_by_weight() {
+        let [v1, v2] = keys();
+        let split = [(&v1, 90), (&v2, 10)];
+        assert_eq!(pick(&split, 0), 0);
+        assert_eq!(pick(&split, 89), 0);
+        assert_eq!(pick(&split, 90), 1);
+        assert_eq!(pick(&split, 99), 1);
+
+        // A handler with no weight is never picked.
+        let split = [(&v1, 0), (&v2, 10)];
+        assert_eq!(pick(&split, 0), 1);
+    }
+
+    #[test]
+    fn sticky_cookie_is_set_and_honoured() {
+        let [v1, v2] = keys();
+        let split = [(&v1, 0), (&v2, 1)];
+        let sticky = StickyConfig::Cookie("cart".into());
+
+        let choice = choose(&split, Some(&sticky), &Request::new(()));
+        assert_eq!(choice.index, 1);
+        assert_eq!(
+            choice.set_cookie.unwrap(),
+            "cart=cart-v2; Path=/; HttpOnly; SameSite=Lax"
+        );
+
+        // The cookie is honoured while its handler has weight.
+        let split = [(&v1, 1), (&v2, 1)];
+        let req = Request::builder()
+            .header(header::COOKIE, "theme=dark; cart=cart-v2")
+            .body(())
+            .unwrap();
+        let choice = choose(&split, Some(&sticky), &req);
+        assert_eq!(
+            choice,
+            Choice {
+                index: 1,
+                set_cookie: None
+            }
+        );
+    }
+
+    #[test]
+    fn sticky_header_is_consistent() {
+        let [v1, v2] = keys();
+        let split = [(&v1, 50), (&v2, 50)];
+        let sticky = StickyConfig::Header("x-user-id".into());
+        let choices = ["alice", "bob", "carol", "dave"].map(|user| {
+            let req = Request::builder()
+                .header("x-user-id", user)
+                .body(())
+                .unwrap();
+            let first = choose(&split, Some(&sticky), &req);
+            assert_eq!(first, choose(&split, Some(&sticky), &req));
+            assert!(first.set_cookie.is_none());
+            first.index
+        });
+        assert!(choices.iter().all(|index| *index < 2));
+    }
+
+    #[tokio::test]
+    async fn copies_small_requests() {
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/cart")
+            .header("x-test", "1")
+            .body(body::full("order".into()))
+            .unwrap();
+        let (req, copy) = copy_request(req).await.unwrap();
+        let copy = copy.unwrap();
+        assert_eq!(copy.method(), "POST");
+        assert_eq!(copy.uri(), "http://example.com/cart");
+        assert_eq!(copy.headers()["x-test"], "1");
+        let copied = copy.into_body().collect().await.unwrap().to_bytes();
+        let original = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(copied, "order");
+        assert_eq!(original, "order");
+    }
+}
@@ -0,0 +1,725 @@
+//! Serving files from a host directory for `static_files` triggers, without
+//! instantiating a component.
+
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use bytes::BytesMut;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri, header};
+use http_body_util::{BodyExt, StreamBody};
+use httpdate::HttpDate;
+use hyper::body::Frame;
+use spin_app::{App, MetadataKey};
+use spin_http::body;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use wasmtime_wasi_http::p2::bindings::http::types::ErrorCode;
+
+use crate::{Body, error_pages::HostError};
+
+/// The URL of the manifest the app was loaded from, if any.
+const ORIGIN_KEY: MetadataKey = MetadataKey::new("origin");
+
+/// The size of the chunks file contents are streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Precompressed variants which may be served in place of a file, in order of
+/// preference, as (content coding, file extension) pairs.
+const PRECOMPRESSED_VARIANTS: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+/// A directory of files served by a `static_files` trigger.
+pub(crate) struct StaticFiles {
+    root: PathBuf,
+    index_files: Vec<String>,
+}
+
+impl StaticFiles {
+    /// Prepares to serve the given directory, relative to the directory
+    /// containing the app manifest.
+    pub fn new(app: &App, directory: &Path, index_files: Vec<String>) -> anyhow::Result<Self> {
+        let root = manifest_relative_path(app, directory)
+            .context("static files can only be served by apps run from a local manifest")?;
+        anyhow::ensure!(
+            root.is_dir(),
+            "static files directory '{}' does not exist or is not a directory",
+            root.display()
+        );
+        // Resolve the root, so that symlinks out of it can be detected.
+        let root = root.canonicalize().with_context(|| {
+            format!(
+                "failed to resolve static files directory '{}'",
+                root.display()
+            )
+        })?;
+        Ok(Self { root, index_files })
+    }
+
+    /// Responds to a request for `path`, relative to the served directory.
+    pub async fn serve<B>(&self, req: &Request<B>, path: &str) -> anyhow::Result<Response<Body>> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(body::empty())?);
+        }
+
+        let Some(relative_path) = sanitize_path(path) else {
+            return not_found();
+        };
+        let mut file_path = self.root.join(&relative_path);
+        let Some(mut metadata) = file_metadata(&self.root, &file_path).await? else {
+            return not_found();
+        };
+
+        if metadata.is_dir() {
+            // Redirect so that relative links in the index file resolve within the directory.
+            if !relative_path.as_os_str().is_empty() && !req.uri().path().ends_with('/') {
+                return redirect_to_directory(req.uri());
+            }
+            let Some((index_path, index_metadata)) = self.find_index_file(&file_path).await? else {
+                return not_found();
+            };
+            file_path = index_path;
+            metadata = index_metadata;
+        }
+        if !metadata.is_file() {
+            return not_found();
+        }
+
+        let content_type = content_type(&file_path);
+
+        // Look for precompressed variants, and use the first the client accepts.
+        let mut has_variants = false;
+        let mut content_encoding = None;
+        for (coding, extension) in PRECOMPRESSED_VARIANTS {
+            let variant_path = with_appended_extension(&file_path, extension);
+            let Some(variant_metadata) = metadata_if_file(&self.root, &variant_path).await? else {
+                continue;
+            };
+            has_variants = true;
+            if content_encoding.is_none() && accepts_encoding(req.headers(), coding) {
+                content_encoding = Some(*coding);
+                file_path = variant_path;
+                metadata = variant_metadata;
+            }
+        }
+
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let etag = etag(len, modified, content_encoding);
+        let last_modified = modified.map(HttpDate::from);
+
+        let mut response = Response::builder()
+            .header(header::ETAG, &etag)
+            .header(header::ACCEPT_RANGES, "bytes");
+        if let Some(last_modified) = last_modified {
+            response = response.header(header::LAST_MODIFIED, last_modified.to_string());
+        }
+        if has_variants {
+            response = response.header(header::VARY, "accept-encoding");
+        }
+
+        if is_not_modified(req.headers(), &etag, last_modified) {
+            return Ok(response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(body::empty())?);
+        }
+
+        response = response.header(header::CONTENT_TYPE, content_type);
+        if let Some(content_encoding) = content_encoding {
+            response = response.header(header::CONTENT_ENCODING, content_encoding);
+        }
+
+        let (start, end) = match requested_range(req.headers(), len, &etag, last_modified) {
+            ByteRange::Full => (0, len),
+            ByteRange::Partial { first, last } => {
+                response = response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_RANGE, format!("bytes {first}-{last}/{len}"));
+                (first, last + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                    .body(body::empty())?);
+            }
+        };
+
+        let response = response.header(header::CONTENT_LENGTH, end - start);
+        let body = if req.method() == Method::HEAD {
+            body::empty()
+        } else {
+            file_body(&file_path, start, end - start).await?
+        };
+        Ok(response.body(body)?)
+    }
+
+    async fn find_index_file(
+        &self,
+        dir: &Path,
+    ) -> anyhow::Result<Option<(PathBuf, std::fs::Metadata)>> {
+        for index_file in &self.index_files {
+            let index_path = dir.join(index_file);
+            if let Some(metadata) = metadata_if_file(&self.root, &index_path).await? {
+                return Ok(Some((index_path, metadata)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Resolves a relative path against the directory containing the manifest
+/// the app was loaded from.
+///
+/// Such files are read from the host, not from the app's packaged files, so
+/// they are only available to apps run from a local manifest: they are not
+/// pushed with the app to a registry. Absolute paths are rejected so that a
+/// manifest can't refer to arbitrary host files.
+pub(crate) fn manifest_relative_path(app: &App, path: &Path) -> anyhow::Result<PathBuf> {
+    anyhow::ensure!(
+        path.is_relative(),
+        "'{}' must be a path relative to the app manifest",
+        path.display()
+    );
+    let origin = app
+        .get_metadata(ORIGIN_KEY)?
+        .context("the app was not loaded from a manifest file")?;
+    let manifest_path = url::Url::parse(&origin)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .with_context(|| {
+            format!("the app was not loaded from a local manifest file, but from {origin}")
+        })?;
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    Ok(manifest_dir.join(path))
+}
+
+/// Converts a percent-encoded request path into a relative file path, or
+/// `None` if it would escape the served directory.
+fn sanitize_path(path: &str) -> Option<PathBuf> {
+    let decoded = percent_encoding::percent_decode_str(path)
+        .decode_utf8()
+        .ok()?;
+    let mut relative_path = PathBuf::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            s if s.contains(['\\', '\0']) => return None,
+            s => relative_path.push(s),
+        }
+    }
+    Some(relative_path)
+}
+
+/// The metadata of `path`, or `None` if nothing exists there or if it resolves,
+/// through symlinks, to somewhere outside `root`.
+async fn file_metadata(root: &Path, path: &Path) -> anyhow::Result<Option<std::fs::Metadata>> {
+    let metadata = match tokio::fs::canonicalize(path).await {
+        Ok(resolved) if !resolved.starts_with(root) => return Ok(None),
+        Ok(resolved) => tokio::fs::metadata(resolved).await,
+        Err(err) => Err(err),
+    };
+    match metadata {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            Ok(None)
+        }
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to read metadata for '{}'", path.display()))
+        }
+    }
+}
+
+async fn metadata_if_file(root: &Path, path: &Path) -> anyhow::Result<Option<std::fs::Metadata>> {
+    Ok(file_metadata(root, path)
+        .await?
+        .filter(std::fs::Metadata::is_file))
+}
+
+fn with_appended_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+/// Whether the `Accept-Encoding` header allows the given content coding.
+fn accepts_encoding(headers: &HeaderMap, coding: &str) -> bool {
+    let mut wildcard = false;
+    for value in headers.get_all(header::ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            if name.eq_ignore_ascii_case(coding) {
+                return quality > 0.0;
+            }
+            if name == "*" {
+                wildcard = quality > 0.0;
+            }
+        }
+    }
+    wildcard
+}
+
+/// A strong entity tag derived from the size and modification time of the file.
+fn etag(len: u64, modified: Option<SystemTime>, content_encoding: Option<&str>) -> String {
+    let modified = modified
+        .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    match content_encoding {
+        Some(coding) => format!("\"{len:x}-{modified:x}-{coding}\""),
+        None => format!("\"{len:x}-{modified:x}\""),
+    }
+}
+
+/// Whether any of the entity tags in an `If-None-Match` or `If-Match` style
+/// header value match `etag`, using weak comparison.
+fn etag_list_matches(value: &HeaderValue, etag: &str) -> bool {
+    value.to_str().is_ok_and(|list| {
+        list.split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<HttpDate>) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since.
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return etag_list_matches(if_none_match, etag);
+    }
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<HttpDate>().ok());
+    match (if_modified_since, last_modified) {
+        (Some(since), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    /// Serve the whole file.
+    Full,
+    /// Serve the bytes from `first` to `last` inclusive.
+    Partial { first: u64, last: u64 },
+    /// The requested range lies outside the file.
+    Unsatisfiable,
+}
+
+/// Determines the part of the file to serve from the `Range` and `If-Range` headers.
+///
+/// Only single ranges are supported; requests for multiple ranges get the whole file.
+fn requested_range(
+    headers: &HeaderMap,
+    len: u64,
+    etag: &str,
+    last_modified: Option<HttpDate>,
+) -> ByteRange {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return ByteRange::Full;
+    };
+    if let Some(if_range) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        let current = if if_range.starts_with('"') {
+            if_range == etag
+        } else {
+            last_modified.is_some_and(|m| if_range.parse::<HttpDate>().ok() == Some(m))
+        };
+        if !current {
+            return ByteRange::Full;
+        }
+    }
+    parse_range(range, len)
+}
+
+fn parse_range(range: &str, len: u64) -> ByteRange {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+    match (first.trim(), last.trim()) {
+        ("", "") => ByteRange::Full,
+        ("", suffix_len) => match suffix_len.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix_len) => ByteRange::Partial {
+                first: len.saturating_sub(suffix_len),
+                last: len - 1,
+            },
+            Err(_) => ByteRange::Full,
+        },
+        (first, last) => {
+            let Ok(first) = first.parse::<u64>() else {
+                return ByteRange::Full;
+            };
+            let last = match last {
+                "" => u64::MAX,
+                last => match last.parse::<u64>() {
+                    Ok(last) if last >= first => last,
+                    _ => return ByteRange::Full,
+                },
+            };
+            if first >= len {
+                ByteRange::Unsatisfiable
+            } else {
+                ByteRange::Partial {
+                    first,
+                    last: last.min(len - 1),
+                }
+            }
+        }
+    }
+}
+
+/// Streams `len` bytes of the file, starting at `start`.
+async fn file_body(path: &Path, start: u64, len: u64) -> anyhow::Result<Body> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("failed to open '{}'", path.display()))?;
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let frames = futures::stream::unfold(Some(file.take(len)), |reader| async move {
+        let mut reader = reader?;
+        let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+        match reader.read_buf(&mut buf).await {
+            Ok(0) => None,
+            Ok(_) => Some((Ok(Frame::data(buf.freeze())), Some(reader))),
+            Err(err) => {
+                tracing::warn!("Error reading static file: {err:?}");
+                Some((Err(ErrorCode::InternalError(Some(err.to_string()))), None))
+            }
+        }
+    });
+    Ok(StreamBody::new(frames).boxed_unsync())
+}
+
+fn redirect_to_directory(uri: &Uri) -> anyhow::Result<Response<Body>> {
+    let location = match uri.query() {
+        Some(query) => format!("{}/?{query}", uri.path()),
+        None => format!("{}/", uri.path()),
+    };
+    Ok(Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .body(body::empty())?)
+}
+
+fn not_found() -> anyhow::Result<Response<Body>> {
+    Ok(HostError::with_response_extension(
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(body::empty())?,
+        None,
+    ))
+}
+
+/// Guesses the content type of a file from its extension.
+pub(crate) fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTDATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/static-files");
+
+    fn app_from(origin: Option<&str>) -> App {
+        let metadata = match origin {
+            Some(origin) => serde_json::json!({ "origin": origin }),
+            None => serde_json::json!({}),
+        };
+        let locked = serde_json::json!({
+            "spin_lock_version": 1,
+            "metadata": metadata,
+            "triggers": [],
+            "components": [],
+        });
+        let locked = spin_app::locked::LockedApp::from_json(locked.to_string().as_bytes()).unwrap();
+        App::new("test-app", locked)
+    }
+
+    #[test]
+    fn paths_are_relative_to_a_local_manifest() {
+        let app = app_from(Some("file:///srv/app/spin.toml"));
+        assert_eq!(
+            manifest_relative_path(&app, Path::new("public")).unwrap(),
+            Path::new("/srv/app/public")
+        );
+        assert!(manifest_relative_path(&app, Path::new("/etc")).is_err());
+
+        let app = app_from(Some("oci://registry.example.com/app:v1"));
+        assert!(manifest_relative_path(&app, Path::new("public")).is_err());
+        let app = app_from(None);
+        assert!(manifest_relative_path(&app, Path::new("public")).is_err());
+    }
+
+    fn static_files() -> StaticFiles {
+        StaticFiles {
+            root: Path::new(TESTDATA_DIR).canonicalize().unwrap(),
+            index_files: vec!["index.html".into()],
+        }
+    }
+
+    fn get(uri: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut req = Request::get(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(()).unwrap()
+    }
+
+    async fn body_bytes(resp: Response<Body>) -> Vec<u8> {
+        resp.into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .to_vec()
+    }
+
+    #[test]
+    fn sanitize_path_rejects_traversal() {
+        assert_eq!(sanitize_path("/a/./b/"), Some(PathBuf::from("a/b")));
+        assert_eq!(sanitize_path("/a%20b.txt"), Some(PathBuf::from("a b.txt")));
+        assert_eq!(sanitize_path(""), Some(PathBuf::new()));
+        assert_eq!(sanitize_path("/a/../../etc/passwd"), None);
+        assert_eq!(sanitize_path("/%2e%2e/secret"), None);
+        assert_eq!(sanitize_path("/a%5c..%5csecret"), None);
+    }
+
+    #[test]
+    fn parse_range_handles_all_forms() {
+        assert_eq!(
+            parse_range("bytes=0-4", 10),
+            ByteRange::Partial { first: 0, last: 4 }
+        );
+        assert_eq!(
+            parse_range("bytes=5-", 10),
+            ByteRange::Partial { first: 5, last: 9 }
+        );
+        assert_eq!(
+            parse_range("bytes=-3", 10),
+            ByteRange::Partial { first: 7, last: 9 }
+        );
+        assert_eq!(
+            parse_range("bytes=-30", 10),
+            ByteRange::Partial { first: 0, last: 9 }
+        );
+        assert_eq!(
+            parse_range("bytes=8-100", 10),
+            ByteRange::Partial { first: 8, last: 9 }
+        );
+        assert_eq!(parse_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=4-2", 10), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 10), ByteRange::Full);
+    }
+
+    #[test]
+    fn accept_encoding_respects_quality() {
+        let headers = |value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+            headers
+        };
+        assert!(accepts_encoding(&headers("gzip, br"), "br"));
+        assert!(accepts_encoding(&headers("GZIP;q=0.5"), "gzip"));
+        assert!(!accepts_encoding(&headers("gzip;q=0, br"), "gzip"));
+        assert!(accepts_encoding(&headers("*"), "br"));
+        assert!(!accepts_encoding(&headers("*, br;q=0"), "br"));
+        assert!(!accepts_encoding(&HeaderMap::new(), "gzip"));
+    }
+
+    #[tokio::test]
+    async fn serves_file_with_validators() {
+        let resp = static_files()
+            .serve(&get("/style.css", &[]), "/style.css")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/css; charset=utf-8"
+        );
+        assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert!(resp.headers().contains_key(header::LAST_MODIFIED));
+        let etag = resp.headers()[header::ETAG].clone();
+        assert_eq!(body_bytes(resp).await, b"body { color: red; }\n");
+
+        let resp = static_files()
+            .serve(
+                &get("/style.css", &[("if-none-match", etag.to_str().unwrap())]),
+                "/style.css",
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert!(body_bytes(resp).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_precompressed_variant() {
+        let resp = static_files()
+            .serve(
+                &get("/style.css", &[("accept-encoding", "gzip, deflate")]),
+                "/style.css",
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/css; charset=utf-8"
+        );
+        let expected = std::fs::read(Path::new(TESTDATA_DIR).join("style.css.gz")).unwrap();
+        assert_eq!(body_bytes(resp).await, expected);
+    }
+
+    #[tokio::test]
+    async fn serves_ranges() {
+        let resp = static_files()
+            .serve(&get("/style.css", &[("range", "bytes=0-3")]), "/style.css")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes 0-3/21");
+        assert_eq!(resp.headers()[header::CONTENT_LENGTH], "4");
+        assert_eq!(body_bytes(resp).await, b"body");
+
+        let resp = static_files()
+            .serve(&get("/style.css", &[("range", "bytes=100-")]), "/style.css")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes */21");
+    }
+
+    #[tokio::test]
+    async fn serves_index_files_and_redirects_directories() {
+        let resp = static_files().serve(&get("/", &[]), "").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+
+        let resp = static_files()
+            .serve(&get("/docs?x=1", &[]), "/docs")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[header::LOCATION], "/docs/?x=1");
+
+        let resp = static_files()
+            .serve(&get("/docs/", &[]), "/docs/")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_files_and_other_methods() {
+        let resp = static_files()
+            .serve(&get("/missing.txt", &[]), "/missing.txt")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = static_files()
+            .serve(&get("/../Cargo.toml", &[]), "/../Cargo.toml")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = Request::post("/style.css").body(()).unwrap();
+        let resp = static_files().serve(&req, "/style.css").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()[header::ALLOW], "GET, HEAD");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_symlinks_out_of_the_directory() {
+        let dir = std::env::temp_dir().join(format!("spin-static-files-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let root = dir.join("public");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("public.txt"), "public").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("secret.txt")).unwrap();
+        std::os::unix::fs::symlink("public.txt", root.join("alias.txt")).unwrap();
+        let static_files = StaticFiles {
+            root: root.canonicalize().unwrap(),
+            index_files: vec![],
+        };
+
+        let resp = static_files
+            .serve(&get("/secret.txt", &[]), "/secret.txt")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = static_files
+            .serve(&get("/alias.txt", &[]), "/alias.txt")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_bytes(resp).await, b"public");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
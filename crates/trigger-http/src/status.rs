@@ -0,0 +1,161 @@
+//! The `health`, `ready` and `routes` endpoints under the well-known prefix.
+
+use std::collections::BTreeMap;
+
+use http::{Response, StatusCode, header};
+use serde::Serialize;
+use spin_factor_key_value::KeyValueFactor;
+use spin_factor_sqlite::SqliteFactor;
+use spin_factors::RuntimeFactors;
+use spin_http::body;
+use tokio::sync::Mutex;
+
+use crate::{Body, TriggerApp};
+
+/// Checks whether the app is ready to serve requests, for the `health` and
+/// `ready` endpoints.
+#[derive(Default)]
+pub(crate) struct StatusEndpoints {
+    /// The last report. Once every check has passed, the checks are not run again.
+    report: Mutex<Option<Report>>,
+}
+
+impl StatusEndpoints {
+    /// Reports whether the stores and databases the components use can be
+    /// opened. The components themselves were prepared when the server started.
+    pub async fn report<F: RuntimeFactors>(&self, trigger_app: &TriggerApp<F>) -> Report {
+        let mut report = self.report.lock().await;
+        if let Some(report) = &*report
+            && report.passed()
+        {
+            return report.clone();
+        }
+        let checked = Report::check(trigger_app).await;
+        *report = Some(checked.clone());
+        checked
+    }
+}
+
+/// The results of the status checks.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct Report {
+    /// Whether the server is draining requests before shutting down.
+    pub shutting_down: bool,
+    key_value_stores: BTreeMap<String, Check>,
+    sqlite_databases: BTreeMap<String, Check>,
+}
+
+impl Report {
+    async fn check<F: RuntimeFactors>(trigger_app: &TriggerApp<F>) -> Self {
+        let mut report = Self::default();
+
+        let configured_app = trigger_app.configured_app();
+        if let Ok(kv) = configured_app.app_state::<KeyValueFactor>() {
+            for label in kv.used_stores() {
+                let check = match kv.get_store(label).await {
+                    Some(_) => Check::Ok,
+                    None => Check::failed("the store could not be opened"),
+                };
+                report.key_value_stores.insert(label.to_owned(), check);
+            }
+        }
+        if let Ok(sqlite) = configured_app.app_state::<SqliteFactor>() {
+            for label in sqlite.used_databases() {
+                let check = match sqlite.get_connection(label).await {
+                    Some(Ok(_)) => Check::Ok,
+                    Some(Err(err)) => Check::failed(format!("{err:?}")),
+                    None => Check::failed("the database is not configured"),
+                };
+                report.sqlite_databases.insert(label.to_owned(), check);
+            }
+        }
+        report
+    }
+
+    /// Returns whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.key_value_stores
+            .values()
+            .chain(self.sqlite_databases.values())
+            .all(|check| matches!(check, Check::Ok))
+    }
+
+    /// Returns whether the app is ready to serve requests.
+    pub fn ready(&self) -> bool {
+        self.passed() && !self.shutting_down
+    }
+
+    /// The JSON response for the report.
+    pub fn response(&self, status: StatusCode) -> anyhow::Result<Response<Body>> {
+        #[derive(Serialize)]
+        struct Output<'a> {
+            ready: bool,
+            #[serde(flatten)]
+            report: &'a Report,
+        }
+
+        let body = serde_json::to_vec_pretty(&Output {
+            ready: self.ready(),
+            report: self,
+        })?;
+        Ok(Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(body::full(body.into()))?)
+    }
+}
+
+/// The result of a status check.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Check {
+    Ok,
+    Failed { error: String },
+}
+
+impl Check {
+    fn failed(error: impl Into<String>) -> Self {
+        Self::Failed {
+            error: error.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_failed_checks() {
+        let mut report = Report::default();
+        report.sqlite_databases.insert("default".into(), Check::Ok);
+        assert!(report.ready());
+
+        report
+            .key_value_stores
+            .insert("default".into(), Check::failed("oops"));
+        assert!(!report.ready());
+
+        let res = report.response(StatusCode::SERVICE_UNAVAILABLE).unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["ready"], false);
+        assert_eq!(json["sqlite_databases"]["default"]["status"], "ok");
+        assert_eq!(json["key_value_stores"]["default"]["status"], "failed");
+        assert_eq!(json["key_value_stores"]["default"]["error"], "oops");
+    }
+
+    #[test]
+    fn shutting_down_is_not_ready() {
+        let report = Report {
+            shutting_down: true,
+            ..Default::default()
+        };
+        assert!(report.passed());
+        assert!(!report.ready());
+    }
+}
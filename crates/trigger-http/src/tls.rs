@@ -1,10 +1,19 @@
-use anyhow::Context;
-use rustls_pki_types::pem::PemObject;
+use anyhow::{Context, bail};
+use rustls_pki_types::{CertificateDer, pem::PemObject};
+use sha2::{Digest, Sha256};
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
 };
-use tokio_rustls::{TlsAcceptor, rustls};
+use tokio_rustls::{
+    TlsAcceptor, rustls,
+    rustls::{
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+};
+use x509_parser::extensions::GeneralName;
 
 // TODO: dedupe with spin-factor-outbound-networking (spin-tls crate?)
 
@@ -15,21 +24,317 @@ pub struct TlsConfig {
     pub cert_path: PathBuf,
     /// Path to TLS key.
     pub key_path: PathBuf,
+    /// Additional certificates, selected by the SNI hostname sent by the client.
+    pub sni_certs: Vec<SniCertificate>,
+    /// Whether to also serve HTTP/3 over QUIC using this certificate.
+    pub http3: bool,
+    /// Path to a bundle of CA certificates used to verify client certificates.
+    /// If this is not set, clients are not asked for a certificate.
+    pub client_ca_path: Option<PathBuf>,
+    /// Whether clients must present a certificate signed by the client CA.
+    pub client_auth: ClientAuth,
+}
+
+/// A certificate and key to serve for a specific SNI hostname.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SniCertificate {
+    /// The hostname, e.g. `api.example.com`, or a wildcard such as `*.example.com`.
+    pub hostname: String,
+    /// Path to TLS certificate.
+    pub cert_path: PathBuf,
+    /// Path to TLS key.
+    pub key_path: PathBuf,
+}
+
+/// How client certificates are verified when a client CA bundle is configured.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Reject connections that do not present a valid client certificate (the default).
+    #[default]
+    Required,
+    /// Accept connections without a client certificate, but reject invalid ones.
+    Optional,
 }
 
 impl TlsConfig {
-    // Creates a TLS acceptor from server config.
-    pub(super) fn server_config(&self) -> anyhow::Result<TlsAcceptor> {
-        let certs = load_certs(&self.cert_path)?;
-        let private_key = load_key(&self.key_path)?;
+    /// Loads the configured certificates into a resolver which can be shared
+    /// between listeners and reloaded while the server is running.
+    pub(super) fn cert_resolver(&self) -> anyhow::Result<Arc<CertResolver>> {
+        let default = CertSlot::load(&self.cert_path, &self.key_path)?;
+        let sni = self
+            .sni_certs
+            .iter()
+            .map(|sni| {
+                let slot = CertSlot::load(&sni.cert_path, &sni.key_path)?;
+                Ok((sni.hostname.to_ascii_lowercase(), slot))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Arc::new(CertResolver { default, sni }))
+    }
 
-        let cfg = rustls::ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, private_key)
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
+    // Creates a TLS acceptor from server config.
+    pub(super) fn server_config(&self, resolver: Arc<CertResolver>) -> anyhow::Result<TlsAcceptor> {
+        let builder = rustls::ServerConfig::builder();
+        let cfg = match self.client_cert_verifier()? {
+            Some(verifier) => builder.with_client_cert_verifier(verifier),
+            None => builder.with_no_client_auth(),
+        }
+        .with_cert_resolver(resolver);
 
         Ok(Arc::new(cfg).into())
     }
+
+    // Creates a client certificate verifier from the client CA bundle, if one is configured.
+    fn client_cert_verifier(
+        &self,
+    ) -> anyhow::Result<Option<Arc<dyn rustls::server::danger::ClientCertVerifier>>> {
+        let Some(ca_path) = &self.client_ca_path else {
+            return Ok(None);
+        };
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in load_certs(ca_path)? {
+            roots.add(cert).with_context(|| {
+                format!("invalid client CA certificate in '{}'", ca_path.display())
+            })?;
+        }
+
+        let builder = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots));
+        let builder = match self.client_auth {
+            ClientAuth::Required => builder,
+            ClientAuth::Optional => builder.allow_unauthenticated(),
+        };
+        let verifier = builder.build().with_context(|| {
+            format!(
+                "failed to build client certificate verifier from '{}'",
+                ca_path.display()
+            )
+        })?;
+        Ok(Some(verifier))
+    }
+
+    // Creates a QUIC server config for HTTP/3 from the same certificates.
+    pub(super) fn quic_server_config(
+        &self,
+        resolver: Arc<CertResolver>,
+    ) -> anyhow::Result<quinn::ServerConfig> {
+        // QUIC requires TLS 1.3.
+        let builder =
+            rustls::ServerConfig::builder_with_protocol_versions(&[&rustls::version::TLS13]);
+        let mut cfg = match self.client_cert_verifier()? {
+            Some(verifier) => builder.with_client_cert_verifier(verifier),
+            None => builder.with_no_client_auth(),
+        }
+        .with_cert_resolver(resolver);
+        cfg.alpn_protocols = vec![b"h3".to_vec()];
+
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(cfg)
+            .context("TLS configuration is not usable for QUIC")?;
+        Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+    }
+}
+
+/// Resolves the server certificate for a TLS handshake, by SNI hostname if
+/// one matches and otherwise the default certificate.
+///
+/// Certificates can be swapped out while the server is running: new handshakes
+/// pick up the new certificate and existing connections are unaffected.
+#[derive(Debug)]
+pub(crate) struct CertResolver {
+    default: CertSlot,
+    /// Lowercased hostname (possibly a `*.` wildcard) -> certificate
+    sni: Vec<(String, CertSlot)>,
+}
+
+impl CertResolver {
+    fn slots(&self) -> impl Iterator<Item = &CertSlot> {
+        std::iter::once(&self.default).chain(self.sni.iter().map(|(_, slot)| slot))
+    }
+
+    fn lookup(&self, server_name: &str) -> &CertSlot {
+        let server_name = server_name.to_ascii_lowercase();
+        let exact = self
+            .sni
+            .iter()
+            .find(|(hostname, _)| *hostname == server_name);
+        let wildcard = || {
+            let (_, parent) = server_name.split_once('.')?;
+            self.sni
+                .iter()
+                .find(|(hostname, _)| hostname.strip_prefix("*.") == Some(parent))
+        };
+        exact
+            .or_else(wildcard)
+            .map(|(_, slot)| slot)
+            .unwrap_or(&self.default)
+    }
+
+    /// Polls the certificate and key files, reloading any that change.
+    ///
+    /// If a reload fails (for example because the certificate has been replaced
+    /// but the key has not yet), the previous certificate remains in use, and
+    /// the reload is retried at the next poll.
+    pub(crate) async fn reload_on_change(self: Arc<Self>, poll_interval: Duration) {
+        let mut stamps: Vec<_> = self.slots().map(CertSlot::stamp).collect();
+        let mut interval = tokio::time::interval(poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            for (slot, stamp) in self.slots().zip(stamps.iter_mut()) {
+                let current = slot.stamp();
+                if current == *stamp {
+                    continue;
+                }
+                match slot.reload() {
+                    Ok(()) => {
+                        *stamp = current;
+                        tracing::info!(
+                            "Reloaded TLS certificate from '{}'",
+                            slot.cert_path.display()
+                        );
+                    }
+                    Err(err) => tracing::warn!(
+                        "Failed to reload TLS certificate from '{}', continuing with the previous certificate: {err:?}",
+                        slot.cert_path.display()
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let slot = match client_hello.server_name() {
+            Some(server_name) => self.lookup(server_name),
+            None => &self.default,
+        };
+        Some(slot.current())
+    }
+}
+
+/// A certificate and key loaded from a pair of files.
+#[derive(Debug)]
+struct CertSlot {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+/// File modification times used to detect certificate changes.
+type FileStamp = (Option<SystemTime>, Option<SystemTime>);
+
+impl CertSlot {
+    fn load(cert_path: &Path, key_path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            cert_path: cert_path.to_owned(),
+            key_path: key_path.to_owned(),
+            key: RwLock::new(load_certified_key(cert_path, key_path)?),
+        })
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        self.key.read().unwrap().clone()
+    }
+
+    fn reload(&self) -> anyhow::Result<()> {
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.key.write().unwrap() = key;
+        Ok(())
+    }
+
+    fn stamp(&self) -> FileStamp {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certs = load_certs(cert_path)?;
+    let private_key = load_key(key_path)?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&private_key)
+        .with_context(|| format!("unsupported private key in '{}'", key_path.display()))?;
+    let certified_key = CertifiedKey::new(certs, signing_key);
+    match certified_key.keys_match() {
+        // Not every key type can report its public key, so trust those that can't.
+        Ok(()) | Err(rustls::Error::InconsistentKeys(rustls::InconsistentKeys::Unknown)) => {}
+        Err(err) => bail!(
+            "private key in '{}' does not match the certificate in '{}': {err}",
+            key_path.display(),
+            cert_path.display()
+        ),
+    }
+    Ok(Arc::new(certified_key))
+}
+
+/// Identity of a client that presented a verified TLS certificate.
+///
+/// This is attached to incoming requests as an extension so that it can be
+/// forwarded to components.
+#[derive(Clone, Debug)]
+pub struct ClientCertificate {
+    /// The certificate subject distinguished name.
+    pub subject: String,
+    /// The subject alternative names, formatted as `TYPE:value` and separated by commas.
+    pub sans: String,
+    /// The lowercase hex SHA-256 fingerprint of the DER-encoded certificate.
+    pub fingerprint: String,
+}
+
+impl ClientCertificate {
+    /// Extracts the client identity from the leaf certificate of a verified chain.
+    pub(crate) fn from_peer_certificates(certs: Option<&[CertificateDer<'_>]>) -> Option<Self> {
+        let leaf = certs?.first()?;
+        Self::from_der(leaf)
+            .inspect_err(|err| tracing::warn!("Unable to parse client certificate: {err:?}"))
+            .ok()
+    }
+
+    fn from_der(der: &CertificateDer<'_>) -> anyhow::Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der.as_ref())
+            .context("invalid X.509 certificate")?;
+
+        let sans = cert
+            .subject_alternative_name()
+            .context("invalid subject alternative name extension")?
+            .map(|ext| {
+                ext.value
+                    .general_names
+                    .iter()
+                    .filter_map(format_general_name)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+
+        let fingerprint = Sha256::digest(der.as_ref())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            sans,
+            fingerprint,
+        })
+    }
+}
+
+fn format_general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+        GeneralName::RFC822Name(email) => Some(format!("email:{email}")),
+        GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+        GeneralName::IPAddress(bytes) => {
+            let ip = match bytes.len() {
+                4 => std::net::IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?),
+                16 => std::net::IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?),
+                _ => return None,
+            };
+            Some(format!("IP:{ip}"))
+        }
+        _ => None,
+    }
 }
 
 // load_certs parse and return the certs from the provided file
@@ -89,6 +394,89 @@ mod tests {
         assert_eq!(certs.len(), 2);
     }
 
+    fn valid_sni_cert(hostname: &str) -> SniCertificate {
+        SniCertificate {
+            hostname: hostname.into(),
+            cert_path: Path::new(TESTDATA_DIR).join("valid-cert.pem"),
+            key_path: Path::new(TESTDATA_DIR).join("valid-private-key.pem"),
+        }
+    }
+
+    #[test]
+    fn test_sni_lookup() {
+        let config = TlsConfig {
+            cert_path: Path::new(TESTDATA_DIR).join("valid-cert.pem"),
+            key_path: Path::new(TESTDATA_DIR).join("valid-private-key.pem"),
+            sni_certs: vec![
+                valid_sni_cert("api.example.com"),
+                valid_sni_cert("*.example.org"),
+            ],
+            http3: false,
+            client_ca_path: None,
+            client_auth: ClientAuth::default(),
+        };
+        let resolver = config.cert_resolver().unwrap();
+
+        let is_default = |name| std::ptr::eq(resolver.lookup(name), &resolver.default);
+        let is_sni = |name, idx: usize| std::ptr::eq(resolver.lookup(name), &resolver.sni[idx].1);
+
+        assert!(is_sni("api.example.com", 0));
+        assert!(is_sni("API.Example.com", 0));
+        assert!(is_default("www.example.com"));
+        assert!(is_sni("www.example.org", 1));
+        assert!(is_default("example.org"));
+        assert!(is_default("a.b.example.org"));
+    }
+
+    #[test]
+    fn test_cert_resolver_reports_missing_sni_cert() {
+        let mut missing = valid_sni_cert("api.example.com");
+        missing.cert_path = Path::new(TESTDATA_DIR).join("non-existing-file.pem");
+        let config = TlsConfig {
+            cert_path: Path::new(TESTDATA_DIR).join("valid-cert.pem"),
+            key_path: Path::new(TESTDATA_DIR).join("valid-private-key.pem"),
+            sni_certs: vec![missing],
+            http3: false,
+            client_ca_path: None,
+            client_auth: ClientAuth::default(),
+        };
+        assert!(config.cert_resolver().is_err());
+    }
+
+    #[test]
+    fn test_mismatched_key_is_rejected() {
+        let cert_path = Path::new(TESTDATA_DIR).join("valid-cert.pem");
+        load_certified_key(
+            &cert_path,
+            &Path::new(TESTDATA_DIR).join("valid-private-key.pem"),
+        )
+        .unwrap();
+        load_certified_key(
+            &cert_path,
+            &Path::new(TESTDATA_DIR).join("other-private-key.pem"),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_client_certificate_identity() {
+        let path = Path::new(TESTDATA_DIR).join("valid-cert.pem");
+        let certs = load_certs(path).unwrap();
+        let identity = ClientCertificate::from_peer_certificates(Some(certs.as_slice())).unwrap();
+        assert!(identity.subject.contains("CN=system:admin"));
+        assert_eq!(identity.sans, "");
+        assert_eq!(
+            identity.fingerprint,
+            "c1cdf1f59d9845c0aeda6dac4aa896a21764b224cefd789655761d8a297482ff"
+        );
+    }
+
+    #[test]
+    fn test_client_certificate_absent() {
+        assert!(ClientCertificate::from_peer_certificates(None).is_none());
+        assert!(ClientCertificate::from_peer_certificates(Some(&[][..])).is_none());
+    }
+
     #[test]
     fn test_read_non_existing_private_key() {
         let path = Path::new(TESTDATA_DIR).join("non-existing-file.pem");
@@ -1,4 +1,4 @@
-use std::{io::Cursor, net::SocketAddr};
+use std::{io::Cursor, net::SocketAddr, time::Instant};
 
 use anyhow::{Context, Result, ensure};
 use http_body_util::BodyExt;
@@ -11,7 +11,11 @@ use wasmtime_wasi::p2::bindings::CommandIndices;
 use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
 use wasmtime_wasi_http::p2::body::HyperIncomingBody as Body;
 
-use crate::{TriggerInstanceBuilder, headers::compute_default_headers, server::HttpExecutor};
+use crate::{
+    ClientCertificate, TriggerInstanceBuilder,
+    headers::compute_default_headers,
+    server::{HttpExecutor, with_deadline},
+};
 
 pub struct WagiHttpExecutor<'a> {
     pub wagi_config: &'a WagiTriggerConfig,
@@ -26,6 +30,7 @@ impl HttpExecutor for WagiHttpExecutor<'_> {
         route_match: &RouteMatch<'_, '_>,
         req: Request<Body>,
         client_addr: SocketAddr,
+        deadline: Option<Instant>,
     ) -> Result<Response<Body>> {
         let spin_http::routes::TriggerLookupKey::Component(component) = route_match.lookup_key()
         else {
@@ -73,7 +78,10 @@ impl HttpExecutor for WagiHttpExecutor<'_> {
         // This sets the current environment variables Wagi expects (such as
         // `PATH_INFO`, or `X_FULL_URL`).
         // Note that this overrides any existing headers previously set by Wagi.
-        for (keys, val) in compute_default_headers(&parts.uri, host, route_match, client_addr)? {
+        let client_cert = parts.extensions.get::<ClientCertificate>();
+        for (keys, val) in
+            compute_default_headers(&parts.uri, host, route_match, client_addr, client_cert)?
+        {
             headers.insert(keys[1].to_string(), val.into_owned());
         }
 
@@ -90,15 +98,18 @@ impl HttpExecutor for WagiHttpExecutor<'_> {
         wasi_builder.stdout(stdout.clone());
 
         let (instance, mut store) = instance_builder.instantiate(()).await?;
+        if let Some(deadline) = deadline {
+            store.set_deadline(deadline);
+        }
 
         let command = self.indices.load(&mut store, &instance)?;
 
         tracing::trace!("Calling Wasm entry point");
-        if let Err(()) = command
-            .wasi_cli_run()
-            .call_run(&mut store)
-            .await
-            .or_else(ignore_successful_proc_exit_trap)?
+        let run = command.wasi_cli_run().call_run(&mut store);
+        if let Err(()) = with_deadline(deadline, async {
+            run.await.or_else(ignore_successful_proc_exit_trap)
+        })
+        .await?
         {
             tracing::error!("Wagi main function returned unsuccessful result");
         }
@@ -1,6 +1,8 @@
 use std::future;
 use std::io::IsTerminal;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{Context, Result, anyhow};
 use futures::TryFutureExt;
@@ -18,12 +20,22 @@ use spin_http::trigger::HandlerType;
 use tokio::{sync::oneshot, task};
 use tracing::{Instrument, Level, instrument};
 use wasmtime::AsContextMut;
-use wasmtime_wasi_http::handler::HandlerState;
 use wasmtime_wasi_http::p2::bindings::http::types::Scheme;
 use wasmtime_wasi_http::p2::{bindings::Proxy, body::HyperIncomingBody as Body};
 use wasmtime_wasi_http::p3;
 
-use crate::{TriggerInstanceBuilder, headers::prepare_request_headers, server::HttpExecutor};
+use crate::{
+    TriggerInstanceBuilder,
+    headers::prepare_request_headers,
+    pool::InstancePool,
+    server::{HttpHandlerState, RequestTimedOut, with_deadline},
+};
+
+/// A component instance which may handle further requests.
+pub(crate) type ReusableInstance<F> = (
+    spin_core::Instance,
+    Store<InstanceState<<F as RuntimeFactors>::InstanceState, ()>>,
+);
 
 pub(super) fn prepare_request(
     route_match: &RouteMatch<'_, '_>,
@@ -53,23 +65,55 @@ pub(super) fn prepare_request(
     Ok(())
 }
 
-/// An [`HttpExecutor`] that uses the `wasi:http/incoming-handler` interface.
-pub struct WasiHttpExecutor<'a, S: HandlerState> {
-    pub handler_type: &'a HandlerType<S>,
+/// An [`HttpExecutor`](crate::server::HttpExecutor) that uses the
+/// `wasi:http/incoming-handler` interface.
+pub(super) struct WasiHttpExecutor<'a, F: RuntimeFactors> {
+    pub handler_type: &'a HandlerType<HttpHandlerState<F>>,
+    /// Idle instances of the component, if it may reuse them.
+    pub pool: Option<&'a Arc<InstancePool<ReusableInstance<F>>>>,
 }
 
-impl<S: HandlerState> HttpExecutor for WasiHttpExecutor<'_, S> {
+impl<F: RuntimeFactors> WasiHttpExecutor<'_, F> {
     #[instrument(name = "spin_trigger_http.execute_wasm", skip_all, err(level = Level::INFO), fields(otel.name = format!("execute_wasm_component {}", route_match.lookup_key().to_string())))]
-    async fn execute<F: RuntimeFactors>(
+    pub async fn execute<'b>(
         &self,
-        instance_builder: TriggerInstanceBuilder<'_, F>,
+        prepare: impl FnOnce() -> Result<TriggerInstanceBuilder<'b, F>>,
         route_match: &RouteMatch<'_, '_>,
         mut req: Request<Body>,
         client_addr: SocketAddr,
+        deadline: Option<Instant>,
     ) -> Result<Response<Body>> {
         prepare_request(route_match, &mut req, client_addr)?;
 
-        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        // These instances may still be running tasks after responding, so
+        // are never reused.
+        if let HandlerType::Wasi2026_03_15(indices) = self.handler_type {
+            let (instance, mut store) = prepare()?.instantiate(()).await?;
+            if let Some(deadline) = deadline {
+                store.set_deadline(deadline);
+            }
+            let guest = indices.load(&mut store, &instance)?;
+            return handle_2026_03_15(store, guest, req, deadline).await;
+        }
+
+        // A reused instance goes back to the pool if the guest returns successfully.
+        let (instance, mut store, lease, reuse) = match self.pool {
+            Some(pool) => {
+                let ((instance, store), mut lease) = match pool.take() {
+                    Some(idle) => idle,
+                    None => (prepare()?.instantiate(()).await?, pool.lease()),
+                };
+                let reuse = lease.start_request();
+                (instance, store, Some((pool.clone(), lease)), Some(reuse))
+            }
+            None => {
+                let (instance, store) = prepare()?.instantiate(()).await?;
+                (instance, store, None, None)
+            }
+        };
+        if let Some(deadline) = deadline {
+            store.set_deadline(deadline);
+        }
 
         enum Handler {
             Latest(Proxy),
@@ -86,10 +130,7 @@ impl<S: HandlerState> HttpExecutor for WasiHttpExecutor<'_, S> {
                 let guest = indices.load(&mut store, &instance)?;
                 Handler::Handler2023_11_10(guest)
             }
-            HandlerType::Wasi2026_03_15(indices) => {
-                let guest = indices.load(&mut store, &instance)?;
-                return handle_2026_03_15(store, guest, req).await;
-            }
+            HandlerType::Wasi2026_03_15(_) => unreachable!("handled above"),
             HandlerType::Wasi0_2(indices) => Handler::Latest(indices.load(&mut store, &instance)?),
             HandlerType::Wasi0_3(_) => unreachable!("should have used Wasip3HttpExecutor"),
             HandlerType::Spin => unreachable!("should have used SpinHttpExecutor"),
@@ -108,35 +149,43 @@ impl<S: HandlerState> HttpExecutor for WasiHttpExecutor<'_, S> {
 
         let handle = task::spawn(
             async move {
-                let result = match handler {
-                    Handler::Latest(handler) => {
-                        handler
-                            .wasi_http_incoming_handler()
-                            .call_handle(&mut store, request, response)
-                            .in_current_span()
-                            .await
-                    }
-                    Handler::Handler2023_10_18(handler) => {
-                        handler
-                            .wasi_http0_2_0_rc_2023_10_18_incoming_handler()
-                            .call_handle(&mut store, request, response)
-                            .in_current_span()
-                            .await
-                    }
-                    Handler::Handler2023_11_10(handler) => {
-                        handler
-                            .wasi_http0_2_0_rc_2023_11_10_incoming_handler()
-                            .call_handle(&mut store, request, response)
-                            .in_current_span()
-                            .await
-                    }
-                };
+                let result = with_deadline(deadline, async {
+                    let result = match handler {
+                        Handler::Latest(handler) => {
+                            handler
+                                .wasi_http_incoming_handler()
+                                .call_handle(&mut store, request, response)
+                                .in_current_span()
+                                .await
+                        }
+                        Handler::Handler2023_10_18(handler) => {
+                            handler
+                                .wasi_http0_2_0_rc_2023_10_18_incoming_handler()
+                                .call_handle(&mut store, request, response)
+                                .in_current_span()
+                                .await
+                        }
+                        Handler::Handler2023_11_10(handler) => {
+                            handler
+                                .wasi_http0_2_0_rc_2023_11_10_incoming_handler()
+                                .call_handle(&mut store, request, response)
+                                .in_current_span()
+                                .await
+                        }
+                    };
+                    Ok(result?)
+                })
+                .await;
 
                 tracing::trace!(
                     "wasi-http memory consumed: {}",
                     store.data().core_state().memory_consumed()
                 );
 
+                if let (Ok(()), Some((pool, lease))) = (&result, lease) {
+                    pool.put((instance, store), lease);
+                }
+
                 result
             }
             .in_current_span(),
@@ -149,7 +198,6 @@ impl<S: HandlerState> HttpExecutor for WasiHttpExecutor<'_, S> {
                         handle
                             .await
                             .context("guest invocation panicked")?
-                            .map_err(anyhow::Error::from)
                             .context("guest invocation failed")?;
 
                         Ok(())
@@ -163,14 +211,17 @@ impl<S: HandlerState> HttpExecutor for WasiHttpExecutor<'_, S> {
                     }),
                 );
 
-                Ok(response.context("guest failed to produce a response")?)
+                let mut response = response.context("guest failed to produce a response")?;
+                if let Some(reuse) = reuse {
+                    response.extensions_mut().insert(reuse);
+                }
+                Ok(response)
             }
 
             Err(_) => {
                 handle
                     .await
                     .context("guest invocation panicked")?
-                    .map_err(anyhow::Error::from)
                     .context("guest invocation failed")?;
 
                 Err(anyhow!(
@@ -185,6 +236,7 @@ async fn handle_2026_03_15<T: RuntimeFactorsInstanceState, U: Send>(
     mut store: Store<InstanceState<T, U>>,
     guest: Service2026_03_15,
     req: Request<Body>,
+    deadline: Option<Instant>,
 ) -> Result<Response<Body>> {
     let (request, request_io_result) = p3::Request::from_http(req);
     let view: fn(&mut InstanceState<_, _>) -> p3::WasiHttpCtxView =
@@ -199,28 +251,31 @@ async fn handle_2026_03_15<T: RuntimeFactorsInstanceState, U: Send>(
     let (tx, rx) = oneshot::channel();
     task::spawn(
         async move {
-            store
-                .as_context_mut()
-                .run_concurrent(async |accessor| {
-                    let response = guest
-                        .wasi_http0_3_0_rc_2026_03_15_handler()
-                        .call_handle(accessor, request)
-                        .await??;
-
-                    let response = accessor.with(|mut store| {
-                        view(store.get())
-                            .table
-                            .delete(response)?
-                            .into_http_with_getter(&mut store, request_io_result, view)
-                    })?;
-
-                    _ = tx.send(response);
-
-                    future::poll_fn(|cx| accessor.poll_no_interesting_tasks(cx)).await;
-
-                    Ok(())
-                })
-                .await?
+            with_deadline(deadline, async {
+                store
+                    .as_context_mut()
+                    .run_concurrent(async |accessor| {
+                        let response = guest
+                            .wasi_http0_3_0_rc_2026_03_15_handler()
+                            .call_handle(accessor, request)
+                            .await??;
+
+                        let response = accessor.with(|mut store| {
+                            view(store.get())
+                                .table
+                                .delete(response)?
+                                .into_http_with_getter(&mut store, request_io_result, view)
+                        })?;
+
+                        _ = tx.send(response);
+
+                        future::poll_fn(|cx| accessor.poll_no_interesting_tasks(cx)).await;
+
+                        Ok(())
+                    })
+                    .await?
+            })
+            .await
         }
         .map_err(|e: anyhow::Error| {
             if std::io::stderr().is_terminal() {
@@ -235,7 +290,12 @@ async fn handle_2026_03_15<T: RuntimeFactorsInstanceState, U: Send>(
         .in_current_span(),
     );
 
-    Ok(rx
-        .await?
-        .map(|body| body.map_err(|e| e.into()).boxed_unsync()))
+    let response = match rx.await {
+        Ok(response) => response,
+        Err(_) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+            return Err(RequestTimedOut.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    Ok(response.map(|body| body.map_err(|e| e.into()).boxed_unsync()))
 }
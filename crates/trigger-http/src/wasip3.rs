@@ -1,4 +1,4 @@
-use crate::server::HttpHandlerState;
+use crate::{access_log::ReuseTracker, server::HttpHandlerState};
 use anyhow::Result;
 use http_body_util::BodyExt;
 use spin_factors::RuntimeFactors;
@@ -14,6 +14,7 @@ use wasmtime_wasi_http::{
 /// An [`HttpExecutor`] that uses the `wasi:http@0.3.*/handler` interface.
 pub(super) struct Wasip3HttpExecutor<'a, F: RuntimeFactors>(
     pub(super) &'a ProxyHandler<HttpHandlerState<F>>,
+    pub(super) &'a ReuseTracker,
 );
 
 impl<F: RuntimeFactors> Wasip3HttpExecutor<'_, F> {
@@ -26,19 +27,26 @@ impl<F: RuntimeFactors> Wasip3HttpExecutor<'_, F> {
     ) -> Result<http::Response<Body>> {
         super::wasi::prepare_request(route_match, &mut req, client_addr)?;
 
-        Ok(self
+        let request = self.1.dispatch();
+        let res = self
             .0
             .handle(
-                (),
+                request.id(),
                 req.map(|body| body.map_err(ErrorCode::from).boxed_unsync()),
             )
-            .await?
-            .map(|body| {
-                body.map_err(|e| match e.downcast::<p3_types::ErrorCode>() {
-                    Ok(e) => e.into(),
-                    Err(e) => p2_types::ErrorCode::InternalError(Some(e.to_string())),
-                })
-                .boxed_unsync()
-            }))
+            .await;
+        let reuse = request.finished();
+
+        let mut res = res?.map(|body| {
+            body.map_err(|e| match e.downcast::<p3_types::ErrorCode>() {
+                Ok(e) => e.into(),
+                Err(e) => p2_types::ErrorCode::InternalError(Some(e.to_string())),
+            })
+            .boxed_unsync()
+        });
+        if let Some(reuse) = reuse {
+            res.extensions_mut().insert(reuse);
+        }
+        Ok(res)
     }
 }
@@ -0,0 +1,327 @@
+//! WebSocket connections to WASIp3 components which export `spin:websocket/inbound-websocket`.
+
+use anyhow::Context as _;
+use futures::{SinkExt, StreamExt};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, header};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use spin_http::body;
+use spin_trigger::GracefulShutdown;
+use spin_world::exports::spin::websocket::inbound_websocket as ws;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{
+        Message,
+        handshake::derive_accept_key,
+        protocol::{CloseFrame, Role, frame::coding::CloseCode},
+    },
+};
+use wasmtime::component::StreamReader;
+
+use crate::Body;
+
+/// The WebSocket protocol version supported (RFC 6455).
+const WEBSOCKET_VERSION: &str = "13";
+
+/// The number of messages buffered in each direction before applying backpressure.
+const MESSAGE_BUFFER: usize = 16;
+
+/// The component's decision on an upgrade request: the subprotocol to use
+/// if accepted.
+pub(crate) type Decision = Result<Option<String>, ws::Error>;
+
+/// Returns whether the request asks to upgrade the connection to a WebSocket.
+pub(crate) fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    has_token(req.headers(), header::CONNECTION, "upgrade")
+        && has_token(req.headers(), header::UPGRADE, "websocket")
+}
+
+/// Validates a WebSocket upgrade request, returning the `Sec-WebSocket-Accept`
+/// value for the response.
+pub(crate) fn accept_key<B>(req: &Request<B>) -> Result<HeaderValue, &'static str> {
+    if req.method() != Method::GET {
+        return Err("WebSocket upgrades must use the GET method");
+    }
+    if req.version() != http::Version::HTTP_11 {
+        return Err("WebSocket upgrades are only supported over HTTP/1.1");
+    }
+    if req
+        .headers()
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .is_none_or(|v| v != WEBSOCKET_VERSION)
+    {
+        return Err("unsupported Sec-WebSocket-Version");
+    }
+    let key = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .ok_or("missing Sec-WebSocket-Key")?;
+    HeaderValue::from_str(&derive_accept_key(key.as_bytes()))
+        .map_err(|_| "invalid Sec-WebSocket-Key")
+}
+
+/// Builds the `upgrade-request` passed to the component.
+pub(crate) fn upgrade_request<B>(req: &Request<B>) -> ws::UpgradeRequest {
+    let uri = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "/".into());
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+        .collect();
+    let protocols = req
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|p| p.trim().to_owned())
+        .filter(|p| !p.is_empty())
+        .collect();
+    ws::UpgradeRequest {
+        uri,
+        headers,
+        protocols,
+    }
+}
+
+/// The response to send for the component's decision on an upgrade request.
+pub(crate) fn response(
+    accept_key: HeaderValue,
+    decision: Decision,
+) -> anyhow::Result<Response<Body>> {
+    match decision {
+        Ok(protocol) => {
+            let mut response = Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(header::CONNECTION, "upgrade")
+                .header(header::UPGRADE, "websocket")
+                .header(header::SEC_WEBSOCKET_ACCEPT, accept_key);
+            if let Some(protocol) = protocol {
+                response = response.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+            }
+            Ok(response.body(body::empty())?)
+        }
+        Err(ws::Error::Rejected) => Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(body::empty())?),
+        Err(ws::Error::Other(err)) => {
+            anyhow::bail!("component failed to accept WebSocket connection: {err}")
+        }
+    }
+}
+
+/// Runs a WebSocket connection: calls the component's `handle-upgrade`, sends
+/// its decision to `decision_tx`, and then relays messages until either side
+/// closes the connection or the server shuts down.
+pub(crate) async fn run<T: Send + 'static>(
+    mut store: spin_core::Store<T>,
+    guest: ws::Guest,
+    request: ws::UpgradeRequest,
+    on_upgrade: OnUpgrade,
+    decision_tx: oneshot::Sender<Decision>,
+    shutdown: GracefulShutdown,
+) -> anyhow::Result<()> {
+    let offered_protocols = request.protocols.clone();
+    let res = std::pin::pin!(store.as_mut().run_concurrent(async |accessor| {
+        let (incoming_tx, incoming_rx) = mpsc::channel(MESSAGE_BUFFER);
+        let incoming = accessor.with(|mut access| {
+            StreamReader::new(&mut access, spin_wasi_async::stream::producer(incoming_rx))
+        })?;
+
+        let accepted = match guest
+            .call_handle_upgrade(accessor, request, incoming)
+            .await?
+        {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                _ = decision_tx.send(Err(err));
+                return Ok(());
+            }
+        };
+        if let Some(protocol) = &accepted.protocol
+            && !offered_protocols.contains(protocol)
+        {
+            _ = decision_tx.send(Err(ws::Error::Other(format!(
+                "subprotocol {protocol:?} was not offered by the client"
+            ))));
+            return Ok(());
+        }
+
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(MESSAGE_BUFFER);
+        accessor.with(|mut access| {
+            accepted
+                .outgoing
+                .pipe(&mut access, spin_wasi_async::stream::consumer(outgoing_tx))
+        })?;
+
+        if decision_tx.send(Ok(accepted.protocol)).is_err() {
+            // The client went away before the handshake completed.
+            return Ok(());
+        }
+        let upgraded = on_upgrade
+            .await
+            .context("failed to upgrade connection to a WebSocket")?;
+        let socket =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        relay(socket, incoming_tx, outgoing_rx, shutdown).await
+    }))
+    .await;
+
+    res.map_err(|e| anyhow::anyhow!("{e}"))
+        .context("WebSocket handler failed (run_concurrent)")?
+}
+
+/// Relays messages between the client and the component.
+async fn relay<S>(
+    socket: WebSocketStream<S>,
+    incoming: mpsc::Sender<ws::Message>,
+    mut outgoing: mpsc::Receiver<ws::Message>,
+    shutdown: GracefulShutdown,
+) -> anyhow::Result<()>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut sink, mut stream) = socket.split();
+
+    let client_to_component = async {
+        while let Some(message) = stream.next().await {
+            let message = match message? {
+                Message::Text(text) => ws::Message::Text(text.as_str().to_owned()),
+                Message::Binary(data) => ws::Message::Binary(data.to_vec()),
+                // Pings are answered by the WebSocket implementation.
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+                Message::Close(_) => break,
+            };
+            // If the component has stopped reading, messages are discarded
+            // until one side closes the connection.
+            _ = incoming.send(message).await;
+        }
+        anyhow::Ok(())
+    };
+    let component_to_client = async {
+        while let Some(message) = outgoing.recv().await {
+            let message = match message {
+                ws::Message::Text(text) => Message::text(text),
+                ws::Message::Binary(data) => Message::binary(data),
+            };
+            sink.send(message).await?;
+        }
+        anyhow::Ok(CloseCode::Normal)
+    };
+
+    let close_code = tokio::select! {
+        res = client_to_component => {
+            // The client closed the connection: end the component's `incoming` stream.
+            res?;
+            return Ok(());
+        }
+        res = component_to_client => res?,
+        () = shutdown.requested() => CloseCode::Away,
+    };
+    drop(incoming);
+    let close = CloseFrame {
+        code: close_code,
+        reason: Default::default(),
+    };
+    // The client may already have gone away, so failing to say goodbye is not an error.
+    if let Err(err) = sink.send(Message::Close(Some(close))).await {
+        tracing::debug!("Failed to close WebSocket connection: {err}");
+    }
+    Ok(())
+}
+
+/// Returns whether a comma-separated header contains the given token.
+fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade(headers: &[(&str, &str)]) -> Request<()> {
+        let mut req = Request::builder().uri("/chat?room=1");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(()).unwrap()
+    }
+
+    fn handshake_headers() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("connection", "keep-alive, Upgrade"),
+            ("upgrade", "websocket"),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+        ]
+    }
+
+    #[test]
+    fn detects_upgrade_requests() {
+        assert!(is_upgrade_request(&upgrade(&handshake_headers())));
+        assert!(!is_upgrade_request(&upgrade(&[("upgrade", "websocket")])));
+        assert!(!is_upgrade_request(&upgrade(&[
+            ("connection", "upgrade"),
+            ("upgrade", "h2c")
+        ])));
+    }
+
+    #[test]
+    fn validates_handshake() {
+        // The example handshake from RFC 6455.
+        let accept = accept_key(&upgrade(&handshake_headers())).unwrap();
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let mut headers = handshake_headers();
+        headers[2].1 = "8";
+        accept_key(&upgrade(&headers)).unwrap_err();
+
+        let mut post = upgrade(&handshake_headers());
+        *post.method_mut() = Method::POST;
+        accept_key(&post).unwrap_err();
+
+        accept_key(&upgrade(&handshake_headers()[..3])).unwrap_err();
+    }
+
+    #[test]
+    fn builds_upgrade_request() {
+        let mut headers = handshake_headers();
+        headers.push(("sec-websocket-protocol", "chat.v2, chat.v1"));
+        let request = upgrade_request(&upgrade(&headers));
+        assert_eq!(request.uri, "/chat?room=1");
+        assert_eq!(request.protocols, ["chat.v2", "chat.v1"]);
+        assert!(
+            request
+                .headers
+                .contains(&("upgrade".to_owned(), b"websocket".to_vec()))
+        );
+    }
+
+    #[test]
+    fn responds_to_decision() {
+        let accept = HeaderValue::from_static("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let accepted = response(accept.clone(), Ok(Some("chat.v1".into()))).unwrap();
+        assert_eq!(accepted.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(accepted.headers()[header::SEC_WEBSOCKET_ACCEPT], accept);
+        assert_eq!(
+            accepted.headers()[header::SEC_WEBSOCKET_PROTOCOL],
+            "chat.v1"
+        );
+
+        let rejected = response(accept.clone(), Err(ws::Error::Rejected)).unwrap();
+        assert_eq!(rejected.status(), StatusCode::FORBIDDEN);
+
+        response(accept, Err(ws::Error::Other("oops".into()))).unwrap_err();
+    }
+}
@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use anyhow::{Context, bail, ensure};
+use futures::{StreamExt, stream::FuturesUnordered};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS, SubscribeFilter};
+use serde::Deserialize;
+use spin_factor_variables::VariablesFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{App, Backoff, Trigger, TriggerApp, cli::NoCliArgs};
+use spin_world::exports::spin::mqtt_trigger::inbound_mqtt as v3;
+use tokio::time::Instant;
+use tracing::{Level, instrument};
+
+/// The capacity of the channel of requests, such as acknowledgements, from a
+/// subscriber to its event loop.
+const REQUEST_CHANNEL_CAPACITY: usize = 100;
+/// How many messages from each connection may be handled at once, if not
+/// configured. The connection is not polled while this many are in flight, so
+/// it is kept small enough for handlers to finish before the broker's
+/// keep-alive expires.
+const DEFAULT_MAX_CONCURRENT_MESSAGES: usize = 16;
+
+pub struct MqttTrigger;
+
+/// MQTT trigger metadata.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerMetadata {
+    address: String,
+    /// How many messages from each connection may be handled at once
+    max_concurrent_messages: Option<usize>,
+}
+
+/// MQTT trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+    /// Topic filters to subscribe to, which may contain `+` and `#` wildcards
+    topics: Vec<String>,
+    /// QoS level to subscribe with: 0 (at most once, the default), 1 (at
+    /// least once) or 2 (exactly once)
+    qos: Option<u8>,
+    /// Client ID to connect with (defaults to `spin-<app>-<component>`)
+    client_id: Option<String>,
+    /// Username to connect with
+    username: Option<String>,
+    /// Password to connect with
+    password: Option<String>,
+    /// Whether the broker should discard the session when the connection
+    /// closes (defaults to true). Set to false to receive the messages
+    /// published while disconnected, at QoS 1 or 2.
+    clean_session: Option<bool>,
+    /// Optionally override address for trigger
+    address: Option<String>,
+}
+
+impl<F: RuntimeFactors> Trigger<F> for MqttTrigger {
+    const TYPE: &'static str = "mqtt-subscription";
+
+    type CliArgs = NoCliArgs;
+
+    type InstanceState = ();
+
+    fn new(_cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let app_variables = trigger_app
+            .configured_app()
+            .app_state::<VariablesFactor>()
+            .context("MqttTrigger depends on VariablesFactor")?;
+
+        let app = trigger_app.app();
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let metadata = app
+            .get_trigger_metadata::<TriggerMetadata>(trigger_type)?
+            .unwrap_or_default();
+        let default_address_expr = &metadata.address;
+        let default_address = app_variables
+            .resolve_expression(default_address_expr.clone())
+            .await
+            .with_context(|| {
+                format!("failed to resolve mqtt trigger default address {default_address_expr:?}")
+            })?;
+        let max_concurrent_messages = metadata
+            .max_concurrent_messages
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_MESSAGES);
+        ensure!(
+            max_concurrent_messages > 0,
+            "mqtt trigger max_concurrent_messages must be at least 1"
+        );
+
+        let resolve = async |expr: &str, what: &str, component_id: &str| {
+            app_variables
+                .resolve_expression(expr.to_owned())
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to resolve mqtt trigger {what} {expr:?} for component {component_id}"
+                    )
+                })
+        };
+
+        // Resolve trigger configs before starting any subscribers. Each
+        // trigger has its own connection, since client IDs must be unique.
+        let mut subscribers = Vec::new();
+        for (_, config) in app
+            .trigger_configs::<TriggerConfig>(trigger_type)?
+            .into_iter()
+            .collect::<Vec<_>>()
+        {
+            let component_id = config.component;
+
+            let address_expr = config.address.as_ref().unwrap_or(&default_address);
+            let address = resolve(address_expr, "address", &component_id).await?;
+
+            ensure!(
+                !config.topics.is_empty(),
+                "mqtt trigger for component {component_id} must have at least one topic"
+            );
+            let mut topics = Vec::new();
+            for expr in &config.topics {
+                let topic = resolve(expr, "topic", &component_id).await?;
+                validate_topic_filter(&topic).with_context(|| {
+                    format!("invalid mqtt trigger topic for component {component_id}")
+                })?;
+                topics.push(topic);
+            }
+
+            let qos = match config.qos.unwrap_or(0) {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                2 => QoS::ExactlyOnce,
+                qos => bail!(
+                    "mqtt trigger for component {component_id} has invalid qos {qos}: expected 0, 1 or 2"
+                ),
+            };
+
+            let client_id = match &config.client_id {
+                Some(expr) => resolve(expr, "client ID", &component_id).await?,
+                None => format!("spin-{}-{component_id}", app.id()),
+            };
+
+            let mut options = mqtt_options(&address, &client_id).with_context(|| {
+                format!("invalid mqtt trigger address {address:?} for component {component_id}")
+            })?;
+            match (&config.username, &config.password) {
+                (Some(username), password) => {
+                    let username = resolve(username, "username", &component_id).await?;
+                    let password = match password {
+                        Some(expr) => resolve(expr, "password", &component_id).await?,
+                        None => String::new(),
+                    };
+                    options.set_credentials(username, password);
+                }
+                (None, Some(_)) => bail!(
+                    "mqtt trigger for component {component_id} has a password, so must have a username"
+                ),
+                (None, None) => {}
+            }
+            options.set_clean_session(config.clean_session.unwrap_or(true));
+            // Messages are acknowledged once they have been handled.
+            options.set_manual_acks(true);
+
+            let pre = trigger_app.get_instance_pre(&component_id)?;
+            let indices = v3::GuestIndices::new(pre)
+                .map_err(anyhow::Error::from)
+                .with_context(|| {
+                    format!(
+                        "mqtt component {component_id} must export spin:mqtt-trigger/inbound-mqtt@3.0.0"
+                    )
+                })?;
+
+            subscribers.push((address, component_id, topics, qos, options, indices));
+        }
+
+        // Start subscriber(s)
+        let trigger_app = Arc::new(trigger_app);
+        let mut subscriber_tasks = Vec::new();
+        for (address, component_id, topics, qos, options, indices) in subscribers {
+            let subscriber = Subscriber {
+                address,
+                trigger_app: trigger_app.clone(),
+                component_id,
+                topics,
+                qos,
+                options,
+                indices,
+                max_concurrent_messages,
+            };
+            let task = tokio::spawn(subscriber.run_listener());
+            subscriber_tasks.push(task);
+        }
+
+        // Wait for any task to complete
+        let (res, _, _) = futures::future::select_all(subscriber_tasks).await;
+        res?
+    }
+}
+
+/// Builds the connection options for an `mqtt://` or `mqtts://` broker address.
+fn mqtt_options(address: &str, client_id: &str) -> anyhow::Result<MqttOptions> {
+    let mut url = url::Url::parse(address)?;
+    url.query_pairs_mut().append_pair("client_id", client_id);
+    Ok(MqttOptions::try_from(url)?)
+}
+
+/// Checks that the wildcards in a topic filter each occupy a whole level, and
+/// that `#` is only used as the last level.
+fn validate_topic_filter(filter: &str) -> anyhow::Result<()> {
+    ensure!(!filter.is_empty(), "topic filter may not be empty");
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        match level {
+            "#" => ensure!(
+                levels.peek().is_none(),
+                "invalid topic filter {filter:?}: '#' must be the last level"
+            ),
+            "+" => {}
+            level => ensure!(
+                !level.contains(['#', '+']),
+                "invalid topic filter {filter:?}: wildcards must occupy a whole level"
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Subscribes to topics from a single broker connection for one component.
+struct Subscriber<F: RuntimeFactors> {
+    address: String,
+    trigger_app: Arc<TriggerApp<MqttTrigger, F>>,
+    component_id: String,
+    topics: Vec<String>,
+    qos: QoS,
+    options: MqttOptions,
+    indices: v3::GuestIndices,
+    max_concurrent_messages: usize,
+}
+
+impl<F: RuntimeFactors> Subscriber<F> {
+    /// Handles messages until the trigger stops, reconnecting and
+    /// resubscribing whenever the connection drops. Only the first connection
+    /// is fatal if it fails.
+    async fn run_listener(self) -> anyhow::Result<()> {
+        let address = &self.address;
+        let client_id = self.options.client_id();
+        let (client, mut event_loop) =
+            AsyncClient::new(self.options.clone(), REQUEST_CHANNEL_CAPACITY);
+
+        tracing::info!("Connecting to MQTT broker at {address}");
+        loop {
+            let event = event_loop
+                .poll()
+                .await
+                .with_context(|| format!("MQTT trigger failed to connect to {address}"))?;
+            if let Event::Incoming(Packet::ConnAck(_)) = event {
+                break;
+            }
+        }
+        self.subscribe(&client).await?;
+
+        println!("Active Topics on {address} (client {client_id}):");
+        for topic in &self.topics {
+            println!("\t{topic}: [{}]", self.component_id);
+        }
+
+        let mut backoff = Backoff::default();
+        // When to next poll the connection, which is delayed after it drops.
+        let mut resume_at = Instant::now();
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                event = async {
+                    tokio::time::sleep_until(resume_at).await;
+                    event_loop.poll().await
+                }, if in_flight.len() < self.max_concurrent_messages => match event {
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        let (this, client) = (&self, &client);
+                        in_flight.push(async move {
+                            // Errors are recorded by the span. The message is
+                            // acknowledged regardless, as MQTT has no way to
+                            // ask for it again.
+                            _ = this.handle_message(&publish).await;
+                            if let Err(err) = client.ack(&publish).await {
+                                tracing::warn!("Failed to acknowledge message from {address}: {err}");
+                            }
+                        });
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        tracing::info!("Reconnected to MQTT broker at {address}");
+                        backoff.reset();
+                        // The broker may not have kept the subscriptions.
+                        if let Err(err) = self.subscribe(&client).await {
+                            tracing::warn!("{err:#}");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        tracing::warn!("Disconnected from MQTT broker at {address}: {err}");
+                        let delay = backoff.next_delay();
+                        tracing::info!("Reconnecting to MQTT broker at {address} in {delay:?}");
+                        resume_at = Instant::now() + delay;
+                    }
+                },
+                Some(()) = in_flight.next(), if !in_flight.is_empty() => {}
+            }
+        }
+    }
+
+    /// Asks the event loop to subscribe to all the topic filters.
+    async fn subscribe(&self, client: &AsyncClient) -> anyhow::Result<()> {
+        let address = &self.address;
+        for topic in &self.topics {
+            tracing::info!("Subscribing to topic {topic:?} on {address}");
+        }
+        let filters = self
+            .topics
+            .iter()
+            .map(|topic| SubscribeFilter::new(topic.clone(), self.qos));
+        client
+            .subscribe_many(filters)
+            .await
+            .with_context(|| format!("MQTT trigger failed to subscribe on {address}"))
+    }
+
+    #[instrument(name = "spin_trigger_mqtt.handle_message", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("{} receive", topic_name(publish)),
+        otel.kind = "consumer",
+        messaging.operation = "receive",
+        messaging.system = "mqtt"
+    ))]
+    async fn handle_message(&self, publish: &Publish) -> anyhow::Result<()> {
+        let address = &self.address;
+        let topic = topic_name(publish);
+        tracing::trace!(%address, %topic, "Received message");
+
+        let component_id = &self.component_id;
+        tracing::trace!("Executing MQTT component {component_id}");
+        self.dispatch_handler(topic, publish.payload.to_vec())
+            .await
+            .inspect_err(|err| {
+                tracing::info!("Component {component_id} handler failed: {err}");
+            })
+    }
+
+    /// Invokes the component's MQTT handler with a message's topic and payload.
+    async fn dispatch_handler(&self, topic: String, payload: Vec<u8>) -> anyhow::Result<()> {
+        let component_id = &self.component_id;
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = "mqtt-subscription",
+            app_id = self.trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        let instance_builder = self.trigger_app.prepare(component_id)?;
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        let guest = self.indices.load(&mut store, &instance)?;
+
+        let res = std::pin::pin!(store.as_mut().run_concurrent(async |accessor| {
+            guest.call_handle_message(accessor, topic, payload).await
+        }))
+        .await;
+
+        res.map_err(|e| anyhow::anyhow!("{e}"))
+            .context("MQTT handler returned an error (run_concurrent)")?
+            .map_err(|e| anyhow::anyhow!("{e}"))
+            .context("MQTT handler returned an error")?
+            .context("MQTT handler returned an error")
+    }
+}
+
+/// The topic a message was published to.
+fn topic_name(publish: &Publish) -> String {
+    let topic: &[u8] = publish.topic.as_ref();
+    String::from_utf8_lossy(topic).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_topic_filters() {
+        for filter in ["sensors", "sensors/+/temperature", "sensors/#", "#", "+/+"] {
+            validate_topic_filter(filter).unwrap();
+        }
+        for filter in ["", "sensors/#/temperature", "sensors/temp+", "sensors#"] {
+            assert!(validate_topic_filter(filter).is_err(), "{filter:?}");
+        }
+    }
+}
@@ -1,16 +1,32 @@
-use std::{collections::HashMap, sync::Arc};
+mod stream;
 
-use anyhow::Context;
-use futures::{StreamExt, TryFutureExt};
-use redis::{Client, Msg};
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+
+use anyhow::{Context, bail, ensure};
+use futures::{Stream, StreamExt, TryFutureExt, stream::FuturesUnordered};
+use redis::{Client, Msg, aio::PubSub};
 use serde::Deserialize;
 use spin_factor_variables::VariablesFactor;
+use spin_factor_wasi::WasiFactor;
 use spin_factors::RuntimeFactors;
-use spin_trigger::{App, Trigger, TriggerApp, cli::NoCliArgs};
+use spin_trigger::{App, Backoff, Trigger, TriggerApp, cli::NoCliArgs};
 use spin_world::exports::fermyon::spin::inbound_redis as v1;
 use spin_world::exports::spin::redis::inbound_redis as v3;
 use tracing::{Level, instrument};
 
+use crate::stream::{StreamConfig, StreamConsumer};
+
+/// The environment variable which tells a handler the channel a message was
+/// published to, since the handler interfaces take only the payload.
+const CHANNEL_ENV_VAR: &str = "SPIN_REDIS_CHANNEL";
+
+/// How many times a stream entry's handler may fail before the entry is
+/// dead-lettered, if not configured.
+const DEFAULT_MAX_FAILURES: u32 = 3;
+/// How long, in milliseconds, a stream entry must have been pending before
+/// it is reclaimed, if not configured.
+const DEFAULT_CLAIM_IDLE_MS: u64 = 30_000;
+
 pub struct RedisTrigger;
 
 /// Redis trigger metadata.
@@ -18,6 +34,9 @@ pub struct RedisTrigger;
 #[serde(deny_unknown_fields)]
 struct TriggerMetadata {
     address: String,
+    /// How many messages from each server may be handled at once (defaults to 1,
+    /// handling messages in the order they are published)
+    max_concurrent_messages: Option<usize>,
 }
 
 /// Redis trigger configuration.
@@ -27,11 +46,75 @@ struct TriggerConfig {
     /// Component ID to invoke
     component: String,
     /// Channel to subscribe to
-    channel: String,
+    channel: Option<String>,
+    /// Glob-style pattern of channels to subscribe to, instead of a channel
+    pattern: Option<String>,
+    /// Stream to consume, instead of a channel
+    stream: Option<String>,
+    /// Consumer group to read the stream as
+    group: Option<String>,
+    /// Consumer name within the group (defaults to the component ID)
+    consumer: Option<String>,
+    /// Stream that entries are moved to when their handler keeps failing
+    /// (defaults to `<stream>:dead`)
+    dead_letter_stream: Option<String>,
+    /// How many times the handler may fail for an entry before it is moved
+    /// to the dead-letter stream
+    max_failures: Option<u32>,
+    /// How long an entry must have been pending before it is reclaimed and
+    /// handled again
+    claim_idle_ms: Option<u64>,
     /// Optionally override address for trigger
     address: Option<String>,
 }
 
+/// Where a trigger gets its messages from, as unresolved expressions.
+#[derive(Debug, PartialEq)]
+enum Source<'a> {
+    Channel(&'a str),
+    Pattern(&'a str),
+    Stream { stream: &'a str, group: &'a str },
+}
+
+impl TriggerConfig {
+    /// Checks that the config has exactly one of `channel`, `pattern` or
+    /// `stream`, and has stream settings only if it consumes a stream.
+    fn source(&self) -> anyhow::Result<Source<'_>> {
+        let component_id = &self.component;
+        let (source, kind) = match (&self.channel, &self.pattern, &self.stream) {
+            (Some(channel), None, None) => (Source::Channel(channel), "channel"),
+            (None, Some(pattern), None) => (Source::Pattern(pattern), "pattern"),
+            (None, None, Some(stream)) => {
+                let group = self.group.as_ref().with_context(|| {
+                    format!(
+                        "redis trigger for component {component_id} consumes a stream, so must have a group"
+                    )
+                })?;
+                ensure!(
+                    self.max_failures != Some(0),
+                    "redis trigger for component {component_id} must allow at least 1 failure"
+                );
+                return Ok(Source::Stream { stream, group });
+            }
+            (Some(_), Some(_), None) => bail!(
+                "redis trigger for component {component_id} may not have both `channel` and `pattern`"
+            ),
+            _ => bail!(
+                "redis trigger for component {component_id} must have exactly one of `channel`, `pattern` or `stream`"
+            ),
+        };
+        ensure!(
+            self.group.is_none()
+                && self.consumer.is_none()
+                && self.dead_letter_stream.is_none()
+                && self.max_failures.is_none()
+                && self.claim_idle_ms.is_none(),
+            "redis trigger for component {component_id} subscribes to a {kind}, so may not have stream settings"
+        );
+        Ok(source)
+    }
+}
+
 impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
     const TYPE: &'static str = "redis";
 
@@ -61,9 +144,27 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
             .with_context(|| {
                 format!("failed to resolve redis trigger default address {default_address_expr:?}")
             })?;
+        let max_concurrent_messages = metadata.max_concurrent_messages.unwrap_or(1);
+        ensure!(
+            max_concurrent_messages > 0,
+            "redis trigger max_concurrent_messages must be at least 1"
+        );
 
-        // Maps <server address> -> <channel> -> <component IDs>
+        // Maps <server address> -> <channel or pattern> -> <component IDs>
         let mut server_channel_components: HashMap<String, ChannelComponents> = HashMap::new();
+        // <server address>, <component ID>, <stream config>
+        let mut stream_consumers = Vec::new();
+
+        let resolve = async |expr: &str, what: &str, component_id: &str| {
+            app_variables
+                .resolve_expression(expr.to_owned())
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to resolve redis trigger {what} {expr:?} for component {component_id}"
+                    )
+                })
+        };
 
         // Resolve trigger configs before starting any subscribers
         for (_, config) in app
@@ -71,44 +172,75 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
             .into_iter()
             .collect::<Vec<_>>()
         {
-            let component_id = config.component;
+            let component_id = &config.component;
 
             let address_expr = config.address.as_ref().unwrap_or(&default_address);
-            let address = app_variables
-                .resolve_expression(address_expr.clone())
-                .await
-                .with_context(|| {
-                    format!(
-                        "failed to resolve redis trigger address {address_expr:?} for component {component_id}"
-                    )
-                })?;
-
-            let channel_expr = &config.channel;
-            let channel = app_variables
-                .resolve_expression(channel_expr.clone())
-                .await
-                .with_context(|| {
-                    format!(
-                        "failed to resolve redis trigger channel {channel_expr:?} for component {component_id}"
-                    )
-                })?;
-
+            let address = resolve(address_expr, "address", component_id).await?;
+
+            let subscription = match config.source()? {
+                Source::Channel(expr) => {
+                    Subscription::Channel(resolve(expr, "channel", component_id).await?)
+                }
+                Source::Pattern(expr) => {
+                    Subscription::Pattern(resolve(expr, "pattern", component_id).await?)
+                }
+                Source::Stream { stream, group } => {
+                    let stream = resolve(stream, "stream", component_id).await?;
+                    let group = resolve(group, "group", component_id).await?;
+                    let consumer = match &config.consumer {
+                        Some(expr) => resolve(expr, "consumer", component_id).await?,
+                        None => component_id.clone(),
+                    };
+                    let dead_letter_stream = match &config.dead_letter_stream {
+                        Some(expr) => resolve(expr, "dead letter stream", component_id).await?,
+                        None => format!("{stream}:dead"),
+                    };
+                    let max_failures = config.max_failures.unwrap_or(DEFAULT_MAX_FAILURES);
+                    let claim_idle = Duration::from_millis(
+                        config.claim_idle_ms.unwrap_or(DEFAULT_CLAIM_IDLE_MS),
+                    );
+
+                    stream_consumers.push((
+                        address,
+                        component_id.clone(),
+                        StreamConfig {
+                            stream,
+                            group,
+                            consumer,
+                            dead_letter_stream,
+                            max_failures,
+                            claim_idle,
+                        },
+                    ));
+                    continue;
+                }
+            };
             server_channel_components
                 .entry(address)
                 .or_default()
-                .entry(channel)
+                .entry(subscription)
                 .or_default()
-                .push(component_id);
+                .push(component_id.clone());
         }
 
         // Start subscriber(s)
         let trigger_app = Arc::new(trigger_app);
         let mut subscriber_tasks = Vec::new();
         for (address, channel_components) in server_channel_components {
-            let subscriber = Subscriber::new(address, trigger_app.clone(), channel_components)?;
+            let subscriber = Subscriber::new(
+                address,
+                trigger_app.clone(),
+                channel_components,
+                max_concurrent_messages,
+            )?;
             let task = tokio::spawn(subscriber.run_listener());
             subscriber_tasks.push(task);
         }
+        for (address, component_id, config) in stream_consumers {
+            let consumer = StreamConsumer::new(address, trigger_app.clone(), component_id, config)?;
+            let task = tokio::spawn(consumer.run_consumer());
+            subscriber_tasks.push(task);
+        }
 
         // Wait for any task to complete
         let (res, _, _) = futures::future::select_all(subscriber_tasks).await;
@@ -116,14 +248,50 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
     }
 }
 
-/// Maps <channel> -> <component IDs>
-type ChannelComponents = HashMap<String, Vec<String>>;
+/// Maps <channel or pattern> -> <component IDs>
+type ChannelComponents = HashMap<Subscription, Vec<String>>;
+
+/// What a subscriber subscribes to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Subscription {
+    /// A channel, with `SUBSCRIBE`.
+    Channel(String),
+    /// The channels matching a glob-style pattern, with `PSUBSCRIBE`.
+    Pattern(String),
+}
+
+impl Subscription {
+    /// The subscription which a message was received through.
+    fn of(msg: &Msg) -> anyhow::Result<Self> {
+        Ok(if msg.from_pattern() {
+            Self::Pattern(msg.get_pattern()?)
+        } else {
+            Self::Channel(msg.get_channel_name().to_owned())
+        })
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Channel(name) | Self::Pattern(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Channel(channel) => write!(f, "channel {channel:?}"),
+            Self::Pattern(pattern) => write!(f, "pattern {pattern:?}"),
+        }
+    }
+}
 
 /// Subscribes to channels from a single Redis server.
 struct Subscriber<F: RuntimeFactors> {
     client: Client,
     trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
     channel_components: ChannelComponents,
+    max_concurrent_messages: usize,
 }
 
 impl<F: RuntimeFactors> Subscriber<F> {
@@ -131,18 +299,46 @@ impl<F: RuntimeFactors> Subscriber<F> {
         address: String,
         trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
         channel_components: ChannelComponents,
+        max_concurrent_messages: usize,
     ) -> anyhow::Result<Self> {
         let client = Client::open(address)?;
         Ok(Self {
             client,
             trigger_app,
             channel_components,
+            max_concurrent_messages,
         })
     }
 
+    /// Handles messages until the trigger stops, reconnecting and
+    /// resubscribing whenever the connection drops. Only the first connection
+    /// is fatal if it fails.
     async fn run_listener(self) -> anyhow::Result<()> {
         let server_addr = &self.client.get_connection_info().addr;
 
+        let mut pubsub = self.subscribe().await?;
+
+        println!("Active Channels on {server_addr}:");
+        for (subscription, components) in &self.channel_components {
+            println!(
+                "\t{server_addr}/{}: [{}]",
+                subscription.name(),
+                components.join(",")
+            );
+        }
+
+        let mut backoff = Backoff::default();
+        loop {
+            self.listen(pubsub).await;
+            tracing::warn!("Disconnected from Redis server at {server_addr}");
+            pubsub = reconnect(server_addr, &mut backoff, || self.subscribe()).await;
+        }
+    }
+
+    /// Connects to the server and subscribes to all the channels and patterns.
+    async fn subscribe(&self) -> anyhow::Result<PubSub> {
+        let server_addr = &self.client.get_connection_info().addr;
+
         tracing::info!("Connecting to Redis server at {server_addr}");
         let mut pubsub = self
             .client
@@ -150,24 +346,34 @@ impl<F: RuntimeFactors> Subscriber<F> {
             .await
             .with_context(|| format!("Redis trigger failed to connect to {server_addr}"))?;
 
-        println!("Active Channels on {server_addr}:");
-
-        // Subscribe to channels
-        for (channel, components) in &self.channel_components {
-            tracing::info!("Subscribing to {channel:?} on {server_addr}");
-            pubsub.subscribe(channel).await.with_context(|| {
-                format!("Redis trigger failed to subscribe to channel {channel:?} on {server_addr}")
+        for subscription in self.channel_components.keys() {
+            tracing::info!("Subscribing to {subscription} on {server_addr}");
+            match subscription {
+                Subscription::Channel(channel) => pubsub.subscribe(channel).await,
+                Subscription::Pattern(pattern) => pubsub.psubscribe(pattern).await,
+            }
+            .with_context(|| {
+                format!("Redis trigger failed to subscribe to {subscription} on {server_addr}")
             })?;
-            println!("\t{server_addr}/{channel}: [{}]", components.join(","));
         }
+        Ok(pubsub)
+    }
 
-        let mut message_stream = pubsub.on_message();
-        while let Some(msg) = message_stream.next().await {
-            if let Err(err) = self.handle_message(msg).await {
-                tracing::error!("Error handling message from {server_addr}: {err}");
-            }
-        }
-        Err(anyhow::anyhow!("disconnected from {server_addr}"))
+    /// Handles messages until the connection drops, handling at most
+    /// `max_concurrent_messages` at once.
+    async fn listen(&self, mut pubsub: PubSub) {
+        let server_addr = &self.client.get_connection_info().addr;
+
+        handle_concurrently(
+            pubsub.on_message(),
+            self.max_concurrent_messages,
+            |msg| async move {
+                if let Err(err) = self.handle_message(msg).await {
+                    tracing::error!("Error handling message from {server_addr}: {err}");
+                }
+            },
+        )
+        .await;
     }
 
     #[instrument(name = "spin_trigger_redis.handle_message", skip_all, err(level = Level::INFO), fields(
@@ -181,64 +387,122 @@ impl<F: RuntimeFactors> Subscriber<F> {
         let channel = msg.get_channel_name();
         tracing::trace!(%server_addr, %channel, "Received message");
 
-        let Some(component_ids) = self.channel_components.get(channel) else {
-            anyhow::bail!("message from unexpected channel {channel:?}");
+        let subscription = Subscription::of(&msg)?;
+        let Some(component_ids) = self.channel_components.get(&subscription) else {
+            anyhow::bail!("message from unexpected {subscription}");
         };
 
         let dispatch_futures = component_ids.iter().map(|component_id| {
             tracing::trace!("Executing Redis component {component_id}");
-            self.dispatch_handler(&msg, component_id)
-                .inspect_err(move |err| {
-                    tracing::info!("Component {component_id} handler failed: {err}");
-                })
+            dispatch_handler(
+                &self.trigger_app,
+                component_id,
+                Some(channel),
+                msg.get_payload_bytes().to_vec(),
+            )
+            .inspect_err(move |err| {
+                tracing::info!("Component {component_id} handler failed: {err}");
+            })
         });
         futures::future::join_all(dispatch_futures).await;
 
         Ok(())
     }
+}
 
-    async fn dispatch_handler(&self, msg: &Msg, component_id: &str) -> anyhow::Result<()> {
-        spin_telemetry::metrics::monotonic_counter!(
-            spin.request_count = 1,
-            trigger_type = "redis",
-            app_id = self.trigger_app.app().id(),
-            component_id = component_id
-        );
+/// Waits and connects again until it succeeds, backing off between attempts.
+/// The backoff starts again from the shortest delay once connected.
+async fn reconnect<T, Fut>(
+    server_addr: impl fmt::Display,
+    backoff: &mut Backoff,
+    mut connect: impl FnMut() -> Fut,
+) -> T
+where
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let conn = loop {
+        let delay = backoff.next_delay();
+        tracing::info!("Reconnecting to Redis server at {server_addr} in {delay:?}");
+        tokio::time::sleep(delay).await;
+        match connect().await {
+            Ok(conn) => break conn,
+            Err(err) => tracing::warn!("{err:#}"),
+        }
+    };
+    backoff.reset();
+    conn
+}
 
-        let (instance, mut store) = self
-            .trigger_app
-            .prepare(component_id)?
-            .instantiate(())
-            .await?;
+/// Handles messages until the stream of them ends, handling at most `limit`
+/// at once, then waits for the messages already received.
+async fn handle_concurrently<T, Fut>(
+    messages: impl Stream<Item = T>,
+    limit: usize,
+    handle: impl Fn(T) -> Fut,
+) where
+    Fut: Future<Output = ()>,
+{
+    let mut messages = std::pin::pin!(messages);
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            msg = messages.next(), if in_flight.len() < limit => {
+                let Some(msg) = msg else { break };
+                in_flight.push(handle(msg));
+            }
+            Some(()) = in_flight.next(), if !in_flight.is_empty() => {}
+        }
+    }
+    while in_flight.next().await.is_some() {}
+}
 
-        let pre = instance.instance_pre(&store);
+/// Invokes a component's Redis handler with a message payload, and the
+/// channel it was published to if it came from one.
+async fn dispatch_handler<F: RuntimeFactors>(
+    trigger_app: &TriggerApp<RedisTrigger, F>,
+    component_id: &str,
+    channel: Option<&str>,
+    payload: Vec<u8>,
+) -> anyhow::Result<()> {
+    spin_telemetry::metrics::monotonic_counter!(
+        spin.request_count = 1,
+        trigger_type = "redis",
+        app_id = trigger_app.app().id(),
+        component_id = component_id
+    );
+
+    let mut instance_builder = trigger_app.prepare(component_id)?;
+    if let (Some(channel), Some(wasi_builder)) =
+        (channel, instance_builder.factor_builder::<WasiFactor>())
+    {
+        wasi_builder.env([(CHANNEL_ENV_VAR, channel)]);
+    }
+    let (instance, mut store) = instance_builder.instantiate(()).await?;
 
-        match HandlerType::from_instance_pre(&pre)? {
-            HandlerType::V1(guest_indices) => {
-                let guest = guest_indices.load(&mut store, &instance)?;
+    let pre = instance.instance_pre(&store);
 
-                let payload = msg.get_payload_bytes().to_vec();
+    match HandlerType::from_instance_pre(&pre)? {
+        HandlerType::V1(guest_indices) => {
+            let guest = guest_indices.load(&mut store, &instance)?;
 
-                guest
-                    .call_handle_message(&mut store, &payload)
-                    .await?
-                    .context("Redis handler returned an error")
-            }
-            HandlerType::V3(guest_indices) => {
-                let guest = guest_indices.load(&mut store, &instance)?;
-
-                let payload = msg.get_payload_bytes().to_vec();
-                let res = std::pin::pin!(store.as_mut().run_concurrent(async |accessor| {
-                    guest.call_handle_message(accessor, payload).await
-                }))
-                .await;
-
-                res.map_err(|e| anyhow::anyhow!("{e}"))
-                    .context("Redis handler returned an error (run_concurrent)")?
-                    .map_err(|e| anyhow::anyhow!("{e}"))
-                    .context("Redis handler returned an error")?
-                    .context("Redis handler returned an error")
-            }
+            guest
+                .call_handle_message(&mut store, &payload)
+                .await?
+                .context("Redis handler returned an error")
+        }
+        HandlerType::V3(guest_indices) => {
+            let guest = guest_indices.load(&mut store, &instance)?;
+
+            let res = std::pin::pin!(store.as_mut().run_concurrent(async |accessor| {
+                guest.call_handle_message(accessor, payload).await
+            }))
+            .await;
+
+            res.map_err(|e| anyhow::anyhow!("{e}"))
+                .context("Redis handler returned an error (run_concurrent)")?
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .context("Redis handler returned an error")?
+                .context("Redis handler returned an error")
         }
     }
 }
@@ -269,3 +533,190 @@ impl HandlerType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_owned())
+    }
+
+    fn stream_config() -> TriggerConfig {
+        TriggerConfig {
+            component: "orders".into(),
+            stream: some("orders"),
+            group: some("billing"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn configs_have_exactly_one_source() {
+        let channel = TriggerConfig {
+            channel: some("orders"),
+            ..Default::default()
+        };
+        assert_eq!(channel.source().unwrap(), Source::Channel("orders"));
+        let pattern = TriggerConfig {
+            pattern: some("orders.*"),
+            ..Default::default()
+        };
+        assert_eq!(pattern.source().unwrap(), Source::Pattern("orders.*"));
+        assert_eq!(
+            stream_config().source().unwrap(),
+            Source::Stream {
+                stream: "orders",
+                group: "billing"
+            }
+        );
+
+        for config in [
+            TriggerConfig::default(),
+            TriggerConfig {
+                channel: some("orders"),
+                pattern: some("orders.*"),
+                ..Default::default()
+            },
+            TriggerConfig {
+                channel: some("orders"),
+                ..stream_config()
+            },
+            TriggerConfig {
+                pattern: some("orders.*"),
+                ..stream_config()
+            },
+        ] {
+            assert!(config.source().is_err(), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn only_streams_have_stream_settings() {
+        let channel = || TriggerConfig {
+            channel: some("orders"),
+            ..Default::default()
+        };
+        for config in [
+            TriggerConfig {
+                group: some("billing"),
+                ..channel()
+            },
+            TriggerConfig {
+                consumer: some("billing-1"),
+                ..channel()
+            },
+            TriggerConfig {
+                dead_letter_stream: some("orders:failed"),
+                ..channel()
+            },
+            TriggerConfig {
+                max_failures: Some(5),
+                ..channel()
+            },
+            TriggerConfig {
+                claim_idle_ms: Some(1000),
+                ..channel()
+            },
+        ] {
+            assert!(config.source().is_err(), "{config:?}");
+        }
+
+        let no_group = TriggerConfig {
+            group: None,
+            ..stream_config()
+        };
+        let err = no_group.source().unwrap_err();
+        assert!(err.to_string().contains("must have a group"), "{err}");
+        let no_failures = TriggerConfig {
+            max_failures: Some(0),
+            ..stream_config()
+        };
+        assert!(no_failures.source().is_err());
+    }
+
+    #[test]
+    fn channels_and_patterns_are_looked_up_separately() {
+        let channel_components = ChannelComponents::from([
+            (Subscription::Channel("orders".into()), vec!["exact".into()]),
+            (Subscription::Pattern("orders".into()), vec!["glob".into()]),
+        ]);
+        assert_eq!(
+            channel_components[&Subscription::Channel("orders".into())],
+            ["exact"]
+        );
+        assert_eq!(
+            channel_components[&Subscription::Pattern("orders".into())],
+            ["glob"]
+        );
+        assert!(!channel_components.contains_key(&Subscription::Channel("orders.new".into())));
+    }
+
+    fn msg(kind: &str, fields: &[&str]) -> Msg {
+        let value = redis::Value::Array(
+            std::iter::once(kind)
+                .chain(fields.iter().copied())
+                .map(|field| redis::Value::BulkString(field.as_bytes().to_vec()))
+                .collect(),
+        );
+        Msg::from_owned_value(value).unwrap()
+    }
+
+    #[test]
+    fn messages_are_routed_by_their_subscription() {
+        let channel_msg = msg("message", &["orders.new", "payload"]);
+        assert_eq!(
+            Subscription::of(&channel_msg).unwrap(),
+            Subscription::Channel("orders.new".into())
+        );
+        let pattern_msg = msg("pmessage", &["orders.*", "orders.new", "payload"]);
+        assert_eq!(
+            Subscription::of(&pattern_msg).unwrap(),
+            Subscription::Pattern("orders.*".into())
+        );
+        // The handler is told the channel, not the pattern.
+        assert_eq!(pattern_msg.get_channel_name(), "orders.new");
+    }
+
+    #[tokio::test]
+    async fn messages_are_handled_up_to_the_limit_at_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        for limit in [1, 3] {
+            let in_flight = AtomicUsize::new(0);
+            let peak = AtomicUsize::new(0);
+            let handled = AtomicUsize::new(0);
+            handle_concurrently(futures::stream::iter(0..10), limit, |_| async {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                // Hold on until as many messages as allowed are in flight.
+                while peak.load(Ordering::SeqCst) < limit {
+                    tokio::task::yield_now().await;
+                }
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                handled.fetch_add(1, Ordering::SeqCst);
+            })
+            .await;
+            assert_eq!(peak.into_inner(), limit);
+            assert_eq!(handled.into_inner(), 10);
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_until_connected() {
+        let mut backoff = Backoff::default();
+        let mut attempts = 0;
+        let conn = reconnect("redis://test", &mut backoff, || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                anyhow::ensure!(attempt > 1, "connection refused");
+                Ok(attempt)
+            }
+        })
+        .await;
+        assert_eq!(conn, 2);
+        // The backoff was reset.
+        assert!(backoff.next_delay() <= Duration::from_millis(500));
+    }
+}
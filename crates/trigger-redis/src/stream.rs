@@ -0,0 +1,442 @@
+//! Consuming Redis streams as part of a consumer group, so that entries are
+//! processed at least once.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use redis::{
+    AsyncCommands, AsyncConnectionConfig, Client, RedisResult,
+    aio::MultiplexedConnection,
+    streams::{
+        StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamPendingCountReply,
+        StreamReadOptions, StreamReadReply,
+    },
+};
+use spin_factors::RuntimeFactors;
+use spin_trigger::{Backoff, TriggerApp};
+use tokio::time::Instant;
+use tracing::{Level, instrument};
+
+use crate::{RedisTrigger, dispatch_handler, reconnect};
+
+/// The field of a stream entry whose value is passed to the handler.
+const PAYLOAD_FIELD: &str = "payload";
+/// How long a read waits for new entries before checking for stale ones.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// The most entries read or reclaimed at once.
+const BATCH_SIZE: usize = 16;
+
+/// Where and how a stream is consumed.
+pub(crate) struct StreamConfig {
+    pub stream: String,
+    pub group: String,
+    pub consumer: String,
+    /// The stream which entries are moved to once their handler has failed
+    /// `max_failures` times.
+    pub dead_letter_stream: String,
+    pub max_failures: u32,
+    /// How long an entry must have been pending before it is reclaimed and
+    /// handled again.
+    pub claim_idle: Duration,
+}
+
+/// Consumes a stream from a single Redis server for one component.
+///
+/// Entries are acknowledged only once the handler succeeds. Entries which
+/// are left pending, because the handler failed or the consumer which read
+/// them went away, are reclaimed with `XAUTOCLAIM` and handled again.
+pub(crate) struct StreamConsumer<F: RuntimeFactors> {
+    client: Client,
+    trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
+    component_id: String,
+    config: StreamConfig,
+}
+
+impl<F: RuntimeFactors> StreamConsumer<F> {
+    pub fn new(
+        address: String,
+        trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
+        component_id: String,
+        config: StreamConfig,
+    ) -> anyhow::Result<Self> {
+        let client = Client::open(address)?;
+        Ok(Self {
+            client,
+            trigger_app,
+            component_id,
+            config,
+        })
+    }
+
+    /// Consumes the stream until the trigger stops, reconnecting whenever
+    /// the connection drops. Only the first connection is fatal if it fails.
+    pub async fn run_consumer(self) -> anyhow::Result<()> {
+        let server_addr = &self.client.get_connection_info().addr;
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            ..
+        } = &self.config;
+
+        let mut conn = self.connect().await?;
+        println!(
+            "Active Stream on {server_addr}/{stream} (group {group}, consumer {consumer}): [{}]",
+            self.component_id
+        );
+
+        let mut backoff = Backoff::default();
+        loop {
+            let err = self.consume(&mut conn).await;
+            tracing::warn!("{err:#}");
+            conn = reconnect(server_addr, &mut backoff, || self.connect()).await;
+        }
+    }
+
+    /// Connects to the server and makes sure the consumer group exists.
+    async fn connect(&self) -> anyhow::Result<MultiplexedConnection> {
+        let server_addr = &self.client.get_connection_info().addr;
+        let StreamConfig { stream, group, .. } = &self.config;
+
+        tracing::info!("Connecting to Redis server at {server_addr}");
+        // Blocking reads take up to `BLOCK_TIMEOUT` to respond, so they mustn't
+        // time out sooner.
+        let connection_config =
+            AsyncConnectionConfig::new().set_response_timeout(BLOCK_TIMEOUT * 2);
+        let mut conn = self
+            .client
+            .get_multiplexed_async_connection_with_config(&connection_config)
+            .await
+            .with_context(|| format!("Redis trigger failed to connect to {server_addr}"))?;
+
+        create_group(&mut conn, stream, group).await.with_context(|| {
+            format!(
+                "Redis trigger failed to create consumer group {group:?} for stream {stream:?} on {server_addr}"
+            )
+        })?;
+        Ok(conn)
+    }
+
+    /// Reads and handles entries until Redis returns an error.
+    async fn consume(&self, conn: &mut MultiplexedConnection) -> anyhow::Error {
+        let server_addr = &self.client.get_connection_info().addr;
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            ..
+        } = &self.config;
+
+        let read_options = StreamReadOptions::default()
+            .group(group, consumer)
+            .count(BATCH_SIZE)
+            .block(BLOCK_TIMEOUT.as_millis() as usize);
+        let mut next_reclaim = Instant::now();
+        loop {
+            if Instant::now() >= next_reclaim {
+                if let Err(err) = self.reclaim(conn).await {
+                    return err.context(format!(
+                        "Redis trigger failed to reclaim entries of stream {stream:?} on {server_addr}"
+                    ));
+                }
+                next_reclaim = Instant::now() + self.config.claim_idle;
+            }
+
+            // `>` reads only entries never delivered to the group.
+            let reply: Option<StreamReadReply> =
+                match conn.xread_options(&[stream], &[">"], &read_options).await {
+                    Ok(reply) => reply,
+                    Err(err) => {
+                        return anyhow::Error::from(err).context(format!(
+                            "Redis trigger failed to read stream {stream:?} on {server_addr}"
+                        ));
+                    }
+                };
+            for entry in reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids) {
+                if let Err(err) = self.process_entry(conn, entry, 0).await {
+                    return err;
+                }
+            }
+        }
+    }
+
+    /// Claims the group's entries which have been pending for too long, and
+    /// handles them again, or dead-letters them if their handler has failed
+    /// too many times.
+    async fn reclaim(&self, conn: &mut MultiplexedConnection) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            claim_idle,
+            ..
+        } = &self.config;
+        let min_idle_ms = claim_idle.as_millis() as u64;
+
+        let mut start = "0-0".to_owned();
+        loop {
+            let options = StreamAutoClaimOptions::default().count(BATCH_SIZE);
+            let reply: StreamAutoClaimReply = conn
+                .xautoclaim_options(stream, group, consumer, min_idle_ms, &start, options)
+                .await?;
+            for entry in reply.claimed {
+                let pending: StreamPendingCountReply = conn
+                    .xpending_count(stream, group, &entry.id, &entry.id, 1)
+                    .await?;
+                let failures = claimed_failures(&pending);
+                self.process_entry(conn, entry, failures).await?;
+            }
+            match next_claim_start(reply.next_stream_id) {
+                Some(next) => start = next,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Handles an entry whose handler has already failed `failures` times,
+    /// acknowledging it if the handler succeeds. Entries which can't be
+    /// handled are dead-lettered instead. Returns an error only if Redis does.
+    async fn process_entry(
+        &self,
+        conn: &mut MultiplexedConnection,
+        entry: StreamId,
+        failures: usize,
+    ) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            max_failures,
+            ..
+        } = &self.config;
+        let payload = match disposition(&entry, failures, *max_failures) {
+            Disposition::Handle(payload) => payload,
+            Disposition::MissingPayload => {
+                tracing::warn!(
+                    "Entry {} of stream {stream:?} has no {PAYLOAD_FIELD:?} field",
+                    entry.id
+                );
+                return self.dead_letter(conn, &entry, failures).await;
+            }
+            Disposition::TooManyFailures => {
+                return self.dead_letter(conn, &entry, failures).await;
+            }
+        };
+        // Failed entries are left pending, to be reclaimed and retried.
+        if self.handle_entry(&entry.id, payload).await.is_ok() {
+            let _: usize = conn.xack(stream, group, &[&entry.id]).await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "spin_trigger_redis.handle_stream_entry", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("{} receive", self.config.stream),
+        otel.kind = "consumer",
+        messaging.operation = "receive",
+        messaging.system = "redis",
+        messaging.message.id = id,
+    ))]
+    async fn handle_entry(&self, id: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+        let server_addr = &self.client.get_connection_info().addr;
+        let stream = &self.config.stream;
+        tracing::trace!(%server_addr, %stream, %id, "Received stream entry");
+
+        let component_id = &self.component_id;
+        tracing::trace!("Executing Redis component {component_id}");
+        dispatch_handler(&self.trigger_app, component_id, None, payload)
+            .await
+            .inspect_err(|err| {
+                tracing::info!("Component {component_id} handler failed: {err}");
+            })
+    }
+
+    /// Moves an entry to the dead-letter stream, recording where it came from
+    /// and how many times its handler failed.
+    async fn dead_letter(
+        &self,
+        conn: &mut MultiplexedConnection,
+        entry: &StreamId,
+        failures: usize,
+    ) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            dead_letter_stream,
+            ..
+        } = &self.config;
+        tracing::warn!(
+            "Moving entry {} of stream {stream:?} to {dead_letter_stream:?} after {failures} failures",
+            entry.id
+        );
+
+        let fields = dead_letter_fields(entry, stream, failures);
+        redis::pipe()
+            .atomic()
+            .xadd(dead_letter_stream, "*", &fields)
+            .ignore()
+            .xack(stream, group, &[&entry.id])
+            .ignore()
+            .query_async::<()>(conn)
+            .await
+            .with_context(|| {
+                format!(
+                    "Redis trigger failed to move entry {} to {dead_letter_stream:?}",
+                    entry.id
+                )
+            })
+    }
+}
+
+/// Creates the consumer group, and the stream if need be. The group starts
+/// with the entries added after it is created.
+async fn create_group(
+    conn: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+) -> RedisResult<()> {
+    let res: RedisResult<()> = conn.xgroup_create_mkstream(stream, group, "$").await;
+    match res {
+        Err(err) if err.code() == Some("BUSYGROUP") => Ok(()),
+        res => res,
+    }
+}
+
+/// What to do with an entry whose handler has already failed `failures`
+/// times.
+#[derive(Debug, PartialEq)]
+enum Disposition {
+    /// Pass the payload to the handler.
+    Handle(Vec<u8>),
+    /// Dead-letter the entry, since no handler could ever succeed.
+    MissingPayload,
+    /// Dead-letter the entry, since the handler has failed too many times.
+    TooManyFailures,
+}
+
+fn disposition(entry: &StreamId, failures: usize, max_failures: u32) -> Disposition {
+    if failures >= max_failures as usize {
+        return Disposition::TooManyFailures;
+    }
+    match entry.get::<Vec<u8>>(PAYLOAD_FIELD) {
+        Some(payload) => Disposition::Handle(payload),
+        None => Disposition::MissingPayload,
+    }
+}
+
+/// The number of times the handler has failed for an entry that was just
+/// claimed, from its `XPENDING` reply. Claiming counts as a delivery, so all
+/// but the latest delivery failed.
+fn claimed_failures(pending: &StreamPendingCountReply) -> usize {
+    pending
+        .ids
+        .first()
+        .map(|pending| pending.times_delivered)
+        .unwrap_or_default()
+        .saturating_sub(1)
+}
+
+/// Where the next `XAUTOCLAIM` of a scan starts, or `None` once the scan has
+/// wrapped around to the start of the stream.
+fn next_claim_start(next_stream_id: String) -> Option<String> {
+    (next_stream_id != "0-0").then_some(next_stream_id)
+}
+
+/// The fields of an entry's dead-letter copy: its own, plus where it came
+/// from and how many times its handler failed.
+fn dead_letter_fields(entry: &StreamId, stream: &str, failures: usize) -> Vec<(String, Vec<u8>)> {
+    let mut fields = entry
+        .map
+        .iter()
+        .filter_map(|(field, value)| {
+            let value = redis::from_redis_value::<Vec<u8>>(value).ok()?;
+            Some((field.clone(), value))
+        })
+        .collect::<Vec<_>>();
+    fields.push(("source_stream".into(), stream.as_bytes().to_vec()));
+    fields.push(("source_id".into(), entry.id.clone().into_bytes()));
+    fields.push(("failures".into(), failures.to_string().into_bytes()));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use redis::{Value, streams::StreamPendingId};
+
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> StreamId {
+        StreamId {
+            id: "1-0".into(),
+            map: fields
+                .iter()
+                .map(|(field, value)| {
+                    (
+                        field.to_string(),
+                        Value::BulkString(value.as_bytes().to_vec()),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn entries_are_handled_until_they_fail_too_many_times() {
+        let entry = entry(&[("payload", "order")]);
+        assert_eq!(
+            disposition(&entry, 0, 3),
+            Disposition::Handle(b"order".to_vec())
+        );
+        assert_eq!(
+            disposition(&entry, 2, 3),
+            Disposition::Handle(b"order".to_vec())
+        );
+        assert_eq!(disposition(&entry, 3, 3), Disposition::TooManyFailures);
+    }
+
+    #[test]
+    fn entries_without_a_payload_are_dead_lettered() {
+        let entry = entry(&[("body", "order")]);
+        assert_eq!(disposition(&entry, 0, 3), Disposition::MissingPayload);
+    }
+
+    #[test]
+    fn failures_exclude_the_claiming_delivery() {
+        let pending = |times_delivered| StreamPendingCountReply {
+            ids: vec![StreamPendingId {
+                id: "1-0".into(),
+                times_delivered,
+                ..Default::default()
+            }],
+        };
+        assert_eq!(claimed_failures(&pending(1)), 0);
+        assert_eq!(claimed_failures(&pending(4)), 3);
+        // The entry was acknowledged or deleted since it was claimed.
+        assert_eq!(claimed_failures(&StreamPendingCountReply::default()), 0);
+    }
+
+    #[test]
+    fn claim_scan_ends_when_it_wraps_around() {
+        assert_eq!(next_claim_start("5-2".into()).as_deref(), Some("5-2"));
+        assert_eq!(next_claim_start("0-0".into()), None);
+    }
+
+    #[test]
+    fn dead_letters_record_their_source() {
+        let entry = entry(&[("payload", "order"), ("region", "eu")]);
+        let fields = dead_letter_fields(&entry, "orders", 3)
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let expected = [
+            ("payload", "order"),
+            ("region", "eu"),
+            ("source_stream", "orders"),
+            ("source_id", "1-0"),
+            ("failures", "3"),
+        ]
+        .into_iter()
+        .map(|(field, value)| (field.to_string(), value.as_bytes().to_vec()))
+        .collect::<HashMap<_, _>>();
+        assert_eq!(fields, expected);
+    }
+}
@@ -0,0 +1,350 @@
+//! A trigger which runs `wasi:cli/run` components on cron schedules or at
+//! fixed intervals.
+
+mod schedule;
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, bail, ensure};
+use clap::Args;
+use jiff::{Timestamp, tz::TimeZone};
+use rand::RngExt;
+use serde::Deserialize;
+use spin_factor_wasi::WasiFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{App, GracefulShutdown, Trigger, TriggerApp};
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::task::TaskTracker;
+use tracing::{Level, instrument};
+use wasmtime_wasi::p2::bindings::CommandIndices;
+
+use crate::schedule::Schedule;
+
+/// The environment variable which tells a component the time its run was
+/// scheduled for.
+const SCHEDULED_TIME_ENV_VAR: &str = "SPIN_TIMER_SCHEDULED_TIME";
+/// The time zone of cron expressions, if not configured.
+const DEFAULT_TIME_ZONE: &str = "UTC";
+
+pub struct TimerTrigger {
+    run_now: bool,
+    shutdown: GracefulShutdown,
+}
+
+/// Timer trigger CLI arguments.
+#[derive(Args, Debug)]
+pub struct CliArgs {
+    /// Run each timer's component once as soon as the app starts, as well as
+    /// on its schedule.
+    ///
+    /// Use this to test scheduled components without waiting for them.
+    #[clap(long, env = "SPIN_TIMER_RUN_NOW")]
+    pub run_now: bool,
+}
+
+/// Timer trigger metadata.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerMetadata {
+    /// Default time zone of cron expressions, such as "Europe/London"
+    time_zone: Option<String>,
+}
+
+/// Timer trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+    /// Cron expression of when to run the component
+    cron: Option<String>,
+    /// Fixed interval at which to run the component, instead of a cron expression
+    interval: Option<String>,
+    /// Optionally override the time zone of the cron expression
+    time_zone: Option<String>,
+    /// What to do when a run is due while the previous one is still running
+    #[serde(default)]
+    overlap: Overlap,
+    /// Longest random delay to add before each run
+    jitter: Option<String>,
+}
+
+/// What to do when a run is due while the previous one is still running.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Overlap {
+    /// Skip the new run.
+    #[default]
+    Skip,
+    /// Start the new run once the previous one finishes. At most one run
+    /// waits; any more are skipped.
+    Queue,
+    /// Start the new run alongside the previous one.
+    Allow,
+}
+
+impl<F: RuntimeFactors> Trigger<F> for TimerTrigger {
+    const TYPE: &'static str = "timer";
+
+    type CliArgs = CliArgs;
+
+    type InstanceState = ();
+
+    fn new(cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            run_now: cli_args.run_now,
+            shutdown: GracefulShutdown::new(),
+        })
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let app = trigger_app.app();
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let metadata = app
+            .get_trigger_metadata::<TriggerMetadata>(trigger_type)?
+            .unwrap_or_default();
+        let default_time_zone = metadata.time_zone.as_deref().unwrap_or(DEFAULT_TIME_ZONE);
+
+        // Set up all timers before starting any, so that mistakes fail fast
+        let mut timers = Vec::new();
+        for (_, config) in app.trigger_configs::<TriggerConfig>(trigger_type)? {
+            let pre = trigger_app.get_instance_pre(&config.component)?;
+            let indices = CommandIndices::new(pre)
+                .map_err(anyhow::Error::from)
+                .with_context(|| {
+                    format!(
+                        "timer component {} must be a command exporting wasi:cli/run",
+                        config.component
+                    )
+                })?;
+            timers.push(Arc::new(Timer::new(config, default_time_zone, indices)?));
+        }
+
+        println!("Active Timers:");
+        for timer in &timers {
+            println!("\t{}: [{}]", timer.description, timer.component_id);
+        }
+
+        let trigger_app = Arc::new(trigger_app);
+        let runs = TaskTracker::new();
+        let schedules = timers.iter().map(|timer| {
+            timer
+                .clone()
+                .schedule_runs(trigger_app.clone(), runs.clone(), self.run_now)
+        });
+        tokio::select! {
+            _ = futures::future::join_all(schedules) => {}
+            _ = self.shutdown.requested() => {
+                tracing::info!("Shutting down timers, waiting for runs in progress");
+            }
+        }
+
+        // Let the runs which have started finish.
+        runs.close();
+        runs.wait().await;
+        Ok(())
+    }
+
+    fn graceful_shutdown(&self) -> Option<GracefulShutdown> {
+        Some(self.shutdown.clone())
+    }
+}
+
+/// Runs a component on a schedule.
+struct Timer {
+    component_id: String,
+    description: String,
+    schedule: Schedule,
+    jitter: Duration,
+    indices: CommandIndices,
+    /// Permits for the runs which are running or waiting to run, if
+    /// overlapping runs are not allowed.
+    pending: Option<Arc<Semaphore>>,
+    /// Held by the run in progress, if overlapping runs are not allowed.
+    running: Mutex<()>,
+}
+
+impl Timer {
+    fn new(
+        config: TriggerConfig,
+        default_time_zone: &str,
+        indices: CommandIndices,
+    ) -> anyhow::Result<Self> {
+        let component_id = config.component;
+
+        let (schedule, description) = match (&config.cron, &config.interval) {
+            (Some(cron), None) => {
+                let time_zone_name = config.time_zone.as_deref().unwrap_or(default_time_zone);
+                let time_zone = TimeZone::get(time_zone_name).with_context(|| {
+                    format!(
+                        "unknown time zone {time_zone_name:?} for timer component {component_id}"
+                    )
+                })?;
+                let description = format!("{cron} ({time_zone_name})");
+                let cron = cron
+                    .parse()
+                    .with_context(|| format!("invalid timer for component {component_id}"))?;
+                (Schedule::Cron(cron, time_zone), description)
+            }
+            (None, Some(interval)) => {
+                let description = format!("every {interval}");
+                let interval = schedule::parse_interval(interval)
+                    .with_context(|| format!("invalid timer for component {component_id}"))?;
+                (Schedule::Interval(interval), description)
+            }
+            _ => bail!(
+                "timer for component {component_id} must have exactly one of `cron` or `interval`"
+            ),
+        };
+        ensure!(
+            schedule.next_after(Timestamp::now()).is_some(),
+            "timer for component {component_id} never fires"
+        );
+
+        let jitter = match &config.jitter {
+            Some(jitter) => humantime::parse_duration(jitter).with_context(|| {
+                format!("invalid jitter {jitter:?} for timer component {component_id}")
+            })?,
+            None => Duration::ZERO,
+        };
+
+        let pending = match config.overlap {
+            Overlap::Skip => Some(1),
+            Overlap::Queue => Some(2),
+            Overlap::Allow => None,
+        }
+        .map(|permits| Arc::new(Semaphore::new(permits)));
+
+        Ok(Self {
+            component_id,
+            description,
+            schedule,
+            jitter,
+            indices,
+            pending,
+            running: Mutex::new(()),
+        })
+    }
+
+    /// Starts runs at the scheduled times, until the schedule ends.
+    async fn schedule_runs<F: RuntimeFactors>(
+        self: Arc<Self>,
+        trigger_app: Arc<TriggerApp<TimerTrigger, F>>,
+        runs: TaskTracker,
+        run_now: bool,
+    ) {
+        let mut last = Timestamp::now();
+        if run_now {
+            self.start_run(&trigger_app, &runs, last, Duration::ZERO);
+        }
+        loop {
+            let now = Timestamp::now();
+            // Times missed while the process was suspended are not caught up.
+            let Some(next) = self
+                .schedule
+                .next_after(last)
+                .filter(|next| *next >= now)
+                .or_else(|| self.schedule.next_after(now))
+            else {
+                tracing::warn!(
+                    "Timer for component {} will not fire again",
+                    self.component_id
+                );
+                return;
+            };
+            let wait = (next.as_millisecond() - now.as_millisecond()).max(0) as u64;
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+
+            let delay = if self.jitter.is_zero() {
+                Duration::ZERO
+            } else {
+                self.jitter.mul_f64(rand::rng().random_range(0.0..=1.0))
+            };
+            self.start_run(&trigger_app, &runs, next, delay);
+            last = next;
+        }
+    }
+
+    /// Starts a run after a delay, unless the overlap policy skips it.
+    fn start_run<F: RuntimeFactors>(
+        self: &Arc<Self>,
+        trigger_app: &Arc<TriggerApp<TimerTrigger, F>>,
+        runs: &TaskTracker,
+        scheduled: Timestamp,
+        delay: Duration,
+    ) {
+        let permit = match &self.pending {
+            Some(pending) => match pending.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    tracing::info!(
+                        "Skipping run of component {} scheduled for {scheduled}: the previous run has not finished",
+                        self.component_id
+                    );
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        let timer = self.clone();
+        let trigger_app = trigger_app.clone();
+        runs.spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _running = if permit.is_some() {
+                Some(timer.running.lock().await)
+            } else {
+                None
+            };
+            // Errors are recorded by the span.
+            _ = timer.execute(&trigger_app, scheduled).await;
+        });
+    }
+
+    #[instrument(name = "spin_trigger_timer.execute", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("timer {}", self.component_id),
+        scheduled_time = %scheduled,
+    ))]
+    async fn execute<F: RuntimeFactors>(
+        &self,
+        trigger_app: &TriggerApp<TimerTrigger, F>,
+        scheduled: Timestamp,
+    ) -> anyhow::Result<()> {
+        let component_id = self.component_id.as_str();
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = "timer",
+            app_id = trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        tracing::trace!("Executing timer component {component_id}");
+        let mut instance_builder = trigger_app.prepare(component_id)?;
+        if let Some(wasi_builder) = instance_builder.factor_builder::<WasiFactor>() {
+            wasi_builder.args([component_id]);
+            wasi_builder.env([(SCHEDULED_TIME_ENV_VAR, scheduled.to_string())]);
+        }
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+
+        let command = self.indices.load(&mut store, &instance)?;
+        command
+            .wasi_cli_run()
+            .call_run(&mut store)
+            .await
+            .or_else(ignore_successful_proc_exit_trap)?
+            .map_err(|()| anyhow::anyhow!("component {component_id} exited unsuccessfully"))
+    }
+}
+
+fn ignore_successful_proc_exit_trap(guest_err: wasmtime::Error) -> anyhow::Result<Result<(), ()>> {
+    match guest_err
+        .root_cause()
+        .downcast_ref::<wasmtime_wasi::I32Exit>()
+    {
+        Some(trap) => match trap.0 {
+            0 => Ok(Ok(())),
+            _ => Err(guest_err.into()),
+        },
+        None => Err(guest_err.into()),
+    }
+}
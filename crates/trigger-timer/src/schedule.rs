@@ -0,0 +1,319 @@
+//! When timers fire: on cron schedules, or at fixed intervals.
+
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{Context, bail, ensure};
+use jiff::{Timestamp, civil::Date, tz::TimeZone};
+
+/// The most days searched for the next time a cron expression matches. An
+/// expression for 29 February can go eight years without matching.
+const MAX_SEARCH_DAYS: usize = 366 * 9;
+
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// When a timer fires.
+#[derive(Clone, Debug)]
+pub(crate) enum Schedule {
+    /// At the times a cron expression matches, in a time zone.
+    Cron(Cron, TimeZone),
+    /// At a fixed interval from when the trigger starts.
+    Interval(Duration),
+}
+
+impl Schedule {
+    /// The first time the timer fires after the given time, if it ever does.
+    pub fn next_after(&self, after: Timestamp) -> Option<Timestamp> {
+        match self {
+            Self::Cron(cron, time_zone) => cron.next_after(after, time_zone),
+            Self::Interval(interval) => {
+                let interval = i64::try_from(interval.as_millis()).ok()?;
+                Timestamp::from_millisecond(after.as_millisecond().checked_add(interval)?).ok()
+            }
+        }
+    }
+}
+
+/// Parses a fixed interval, such as `30s`, `15m` or `1h 30m`.
+pub(crate) fn parse_interval(s: &str) -> anyhow::Result<Duration> {
+    let interval = humantime::parse_duration(s)
+        .with_context(|| format!("invalid interval {s:?}: expected a duration such as '15m'"))?;
+    ensure!(!interval.is_zero(), "interval must be longer than zero");
+    Ok(interval)
+}
+
+/// A standard five-field cron expression (minute, hour, day of month, month
+/// and day of week), or one of the `@hourly`, `@daily`, `@weekly`,
+/// `@monthly` and `@yearly` shorthands.
+///
+/// As in most cron implementations, if both the day of month and the day of
+/// week are restricted, a day matches if either does.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Cron {
+    // Each field is a bit set of the values it matches.
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            bail!(
+                "invalid cron expression {s:?}: expected five fields (minute, hour, day of month, month and day of week)"
+            );
+        };
+        let field = |name: &str, value: &str, min: u8, max: u8, names: &[&str]| {
+            parse_field(value, min, max, names)
+                .with_context(|| format!("invalid {name} in cron expression {s:?}"))
+        };
+        // Both 0 and 7 are Sunday.
+        let days_of_week = field("day of week", day_of_week, 0, 7, DAY_NAMES)?;
+        Ok(Self {
+            minutes: field("minute", minute, 0, 59, &[])?,
+            hours: field("hour", hour, 0, 23, &[])?,
+            days_of_month: field("day of month", day_of_month, 1, 31, &[])?,
+            months: field("month", month, 1, 12, MONTH_NAMES)?,
+            days_of_week: (days_of_week | (days_of_week >> 7)) & 0x7f,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_day_of_week: day_of_week.starts_with('*'),
+        })
+    }
+}
+
+impl Cron {
+    /// The first time the expression matches in the time zone after the given
+    /// time. Times skipped by daylight saving changes run at the equivalent
+    /// time after the change, and times repeated by them run only once.
+    fn next_after(&self, after: Timestamp, time_zone: &TimeZone) -> Option<Timestamp> {
+        let start = after.to_zoned(time_zone.clone()).datetime();
+        let mut date = start.date();
+        let (mut from_hour, mut from_minute) = (start.hour(), start.minute() + 1);
+        for _ in 0..MAX_SEARCH_DAYS {
+            if self.matches_day(date) {
+                for hour in from_hour..24 {
+                    if !has(self.hours, hour) {
+                        continue;
+                    }
+                    let from_minute = if hour == from_hour { from_minute } else { 0 };
+                    for minute in from_minute..60 {
+                        if !has(self.minutes, minute) {
+                            continue;
+                        }
+                        let Ok(zoned) = date.at(hour, minute, 0, 0).to_zoned(time_zone.clone())
+                        else {
+                            continue;
+                        };
+                        if zoned.timestamp() > after {
+                            return Some(zoned.timestamp());
+                        }
+                    }
+                }
+            }
+            date = date.tomorrow().ok()?;
+            (from_hour, from_minute) = (0, 0);
+        }
+        None
+    }
+
+    fn matches_day(&self, date: Date) -> bool {
+        if !has(self.months, date.month()) {
+            return false;
+        }
+        let day_of_month = has(self.days_of_month, date.day());
+        let day_of_week = has(self.days_of_week, date.weekday().to_sunday_zero_offset());
+        if self.any_day_of_month || self.any_day_of_week {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        }
+    }
+}
+
+fn has(bits: u64, value: i8) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parses a cron field, such as `*/15`, `1-5` or `jan,jul`, into a bit set.
+fn parse_field(field: &str, min: u8, max: u8, names: &[&str]) -> anyhow::Result<u64> {
+    let value = |s: &str| -> anyhow::Result<u8> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(index) => min + index as u8,
+            None => s
+                .parse()
+                .with_context(|| format!("expected a number or name; got {s:?}"))?,
+        };
+        ensure!(
+            (min..=max).contains(&value),
+            "{value} is not between {min} and {max}"
+        );
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u8 = step
+                    .parse()
+                    .with_context(|| format!("expected a number after '/'; got {step:?}"))?;
+                ensure!(step > 0, "step must be at least 1");
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `a/n` means every nth value from `a`.
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        ensure!(start <= end, "range {range:?} ends before it starts");
+        for value in (start..=end).step_by(step.unwrap_or(1).into()) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::tz::offset;
+
+    use super::*;
+
+    fn next(cron: &str, after: &str) -> String {
+        let cron: Cron = cron.parse().unwrap();
+        cron.next_after(after.parse().unwrap(), &TimeZone::UTC)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 0, 59, &[]).unwrap().count_ones(), 60);
+        assert_eq!(
+            parse_field("*/15", 0, 59, &[]).unwrap(),
+            1 | (1 << 15) | (1 << 30) | (1 << 45)
+        );
+        assert_eq!(
+            parse_field("1-3,10", 0, 59, &[]).unwrap(),
+            0b1110 | (1 << 10)
+        );
+        assert_eq!(
+            parse_field("50/5", 0, 59, &[]).unwrap(),
+            (1 << 50) | (1 << 55)
+        );
+        assert_eq!(
+            parse_field("JAN,jul", 1, 12, MONTH_NAMES).unwrap(),
+            (1 << 1) | (1 << 7)
+        );
+
+        assert!(parse_field("60", 0, 59, &[]).is_err());
+        assert!(parse_field("5-1", 0, 59, &[]).is_err());
+        assert!(parse_field("*/0", 0, 59, &[]).is_err());
+        assert!(parse_field("mon", 0, 59, &[]).is_err());
+    }
+
+    #[test]
+    fn parses_expressions() {
+        assert_eq!(
+            "@daily".parse::<Cron>().unwrap(),
+            "0 0 * * *".parse().unwrap()
+        );
+        // Sunday may be 0 or 7.
+        assert_eq!(
+            "0 0 * * 7".parse::<Cron>().unwrap(),
+            "0 0 * * sun".parse().unwrap()
+        );
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("* * * * * *".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    fn finds_next_times() {
+        assert_eq!(
+            next("*/15 * * * *", "2024-01-01T10:07:30Z"),
+            "2024-01-01T10:15:00Z"
+        );
+        // Strictly after the given time.
+        assert_eq!(
+            next("0 * * * *", "2024-01-01T10:00:00Z"),
+            "2024-01-01T11:00:00Z"
+        );
+        assert_eq!(
+            next("30 2 * * *", "2024-01-01T03:00:00Z"),
+            "2024-01-02T02:30:00Z"
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2024-03-01T00:00:00Z"),
+            "2028-02-29T00:00:00Z"
+        );
+        // 2024-01-01 was a Monday.
+        assert_eq!(
+            next("0 9 * * fri", "2024-01-01T00:00:00Z"),
+            "2024-01-05T09:00:00Z"
+        );
+    }
+
+    #[test]
+    fn either_restricted_day_matches() {
+        // The 15th, or any Monday.
+        assert_eq!(
+            next("0 0 15 * mon", "2024-01-01T00:00:00Z"),
+            "2024-01-08T00:00:00Z"
+        );
+        assert_eq!(
+            next("0 0 15 * mon", "2024-01-10T00:00:00Z"),
+            "2024-01-15T00:00:00Z"
+        );
+        // Only Mondays, as the day of month is unrestricted.
+        assert_eq!(
+            next("0 0 * * mon", "2024-01-01T00:00:00Z"),
+            "2024-01-08T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn uses_the_time_zone() {
+        let cron: Cron = "0 9 * * *".parse().unwrap();
+        let time_zone = TimeZone::fixed(offset(-5));
+        let next = cron
+            .next_after("2024-01-01T12:00:00Z".parse().unwrap(), &time_zone)
+            .unwrap();
+        assert_eq!(next.to_string(), "2024-01-01T14:00:00Z");
+    }
+
+    #[test]
+    fn intervals_follow_the_last_time() {
+        let schedule = Schedule::Interval(parse_interval("1h 30m").unwrap());
+        let next = schedule
+            .next_after("2024-01-01T00:00:00Z".parse().unwrap())
+            .unwrap();
+        assert_eq!(next.to_string(), "2024-01-01T01:30:00Z");
+
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("soon").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Delays between attempts to reconnect to a server, such as a message broker.
+
+use std::time::Duration;
+
+use rand::RngExt;
+
+/// The longest delay before the first attempt.
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+/// The longest delay before any attempt.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff with jitter, so that triggers which lost the same
+/// server don't all reconnect at once when it comes back.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    attempts: u32,
+}
+
+impl Backoff {
+    /// The delay before the next attempt: a random time up to a limit which
+    /// doubles with each attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        let limit = INITIAL_DELAY
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(MAX_DELAY);
+        self.attempts = self.attempts.saturating_add(1);
+        limit.mul_f64(rand::rng().random_range(0.5..=1.0))
+    }
+
+    /// Starts again from the shortest delay, after a successful attempt.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_up_to_the_limit() {
+        let mut backoff = Backoff::default();
+        let first = backoff.next_delay();
+        assert!(first >= INITIAL_DELAY / 2 && first <= INITIAL_DELAY);
+        let second = backoff.next_delay();
+        assert!(second >= INITIAL_DELAY && second <= INITIAL_DELAY * 2);
+
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= MAX_DELAY);
+        }
+        assert!(backoff.next_delay() >= MAX_DELAY / 2);
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= INITIAL_DELAY);
+    }
+}
@@ -254,6 +254,7 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
         }
 
         let trigger = T::new(self.trigger_args, &app)?;
+        let graceful_shutdown = trigger.graceful_shutdown();
 
         let mut builder: TriggerAppBuilder<T, B> = TriggerAppBuilder::new(trigger);
         let config = builder.engine_config();
@@ -316,7 +317,16 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
             .await?;
 
         let (abortable, abort_handle) = futures::future::abortable(run_fut);
-        ctrlc::set_handler(move || abort_handle.abort())?;
+        ctrlc::set_handler(move || match &graceful_shutdown {
+            Some(shutdown) if shutdown.is_requested() => {
+                tracing::info!("Shutdown already in progress");
+            }
+            Some(shutdown) => {
+                tracing::info!("User requested shutdown: finishing in-flight work");
+                shutdown.request();
+            }
+            None => abort_handle.abort(),
+        })?;
         match abortable.await {
             Ok(Ok(())) => {
                 tracing::info!("Trigger executor shut down: exiting");
@@ -405,6 +415,10 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> TriggerAppBuilder<T, B> {
         self.trigger.add_to_linker(core_engine_builder.linker())?;
 
         let (factors, runtime_config) = B::build(&common_options, &options)?;
+        self.trigger
+            .set_log_dir(B::log_dir(&runtime_config).as_deref());
+        self.trigger
+            .set_state_dir(B::state_dir(&runtime_config).as_deref());
 
         let mut executor = FactorsExecutor::new(core_engine_builder, factors)?;
         B::configure_app(&mut executor, &runtime_config, &common_options, &options)?;
@@ -464,6 +478,18 @@ pub trait RuntimeFactorsBuilder {
         let _ = (executor, runtime_config, config, args);
         Ok(())
     }
+
+    /// The directory app logs are written to, if logging to disk is enabled.
+    fn log_dir(runtime_config: &Self::RuntimeConfig) -> Option<PathBuf> {
+        let _ = runtime_config;
+        None
+    }
+
+    /// The directory app state is stored in, if any.
+    fn state_dir(runtime_config: &Self::RuntimeConfig) -> Option<PathBuf> {
+        let _ = runtime_config;
+        None
+    }
 }
 
 pub mod help {
@@ -1,5 +1,7 @@
+mod backoff;
 pub mod cli;
 pub mod loader;
+mod shutdown;
 
 use heck::ToTitleCase;
 use std::future::Future;
@@ -9,6 +11,8 @@ use spin_core::Linker;
 use spin_factors::RuntimeFactors;
 use spin_factors_executor::{FactorsExecutorApp, FactorsInstanceBuilder};
 
+pub use backoff::Backoff;
+pub use shutdown::GracefulShutdown;
 pub use spin_app::App;
 
 /// Type alias for a [`spin_factors_executor::FactorsExecutorApp`] specialized to a [`Trigger`].
@@ -57,6 +61,20 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
         // the do-nothing unit composer
     }
 
+    /// Tells the trigger where app logs are written, if anywhere.
+    ///
+    /// This is called before the trigger is run.
+    fn set_log_dir(&mut self, log_dir: Option<&std::path::Path>) {
+        let _ = log_dir;
+    }
+
+    /// Tells the trigger where app state is stored, if anywhere.
+    ///
+    /// This is called before the trigger is run.
+    fn set_state_dir(&mut self, state_dir: Option<&std::path::Path>) {
+        let _ = state_dir;
+    }
+
     /// Update the [`Linker`] for this trigger.
     fn add_to_linker(
         &mut self,
@@ -72,6 +90,12 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
         trigger_app: TriggerApp<Self, F>,
     ) -> impl Future<Output = anyhow::Result<()>> + Send;
 
+    /// Returns a signal used to ask this trigger to shut down gracefully,
+    /// if it supports doing so. Triggers which don't are stopped immediately.
+    fn graceful_shutdown(&self) -> Option<GracefulShutdown> {
+        None
+    }
+
     /// Returns a list of host requirements supported by this trigger specifically.
     ///
     /// See [`App::ensure_needs_only`].
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// A request for a trigger to stop accepting new work and to exit once its
+/// in-flight work has finished.
+#[derive(Clone)]
+pub struct GracefulShutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl GracefulShutdown {
+    /// Creates a signal which has not yet been requested.
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Requests shutdown.
+    pub fn request(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Returns whether shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Waits until shutdown is requested.
+    pub async fn requested(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The channel can't close while we hold the sender, so this only
+        // returns once shutdown is requested.
+        let _ = receiver.wait_for(|requested| *requested).await;
+    }
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requested_resolves_after_request() {
+        let shutdown = GracefulShutdown::new();
+        assert!(!shutdown.is_requested());
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.requested().await }
+        });
+        shutdown.request();
+        waiter.await.unwrap();
+        assert!(shutdown.is_requested());
+
+        // Waiting after the request returns immediately.
+        shutdown.requested().await;
+    }
+}
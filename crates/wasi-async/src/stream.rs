@@ -45,3 +45,54 @@ impl<D, T: Send + Sync + 'static> wasmtime::component::StreamProducer<D> for Str
         }
     }
 }
+
+pub fn consumer<T: Send + 'static>(tx: tokio::sync::mpsc::Sender<T>) -> StreamConsumer<T> {
+    StreamConsumer {
+        tx: tokio_util::sync::PollSender::new(tx),
+    }
+}
+
+pub struct StreamConsumer<T> {
+    tx: tokio_util::sync::PollSender<T>,
+}
+
+impl<D, T: wasmtime::component::Lift + Send + 'static> wasmtime::component::StreamConsumer<D>
+    for StreamConsumer<T>
+{
+    type Item = T;
+
+    fn poll_consume(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        store: wasmtime::StoreContextMut<D>,
+        mut source: wasmtime::component::Source<'_, Self::Item>,
+        finish: bool,
+    ) -> std::task::Poll<wasmtime::Result<wasmtime::component::StreamResult>> {
+        use std::task::Poll;
+        use wasmtime::component::StreamResult;
+        use wasmtime::error::Context as _;
+
+        let tx = &mut self.get_mut().tx;
+        match tx.poll_reserve(cx) {
+            Poll::Pending => {
+                if finish {
+                    Poll::Ready(Ok(StreamResult::Cancelled))
+                } else {
+                    Poll::Pending
+                }
+            }
+            Poll::Ready(Err(_)) => Poll::Ready(Ok(StreamResult::Dropped)),
+            Poll::Ready(Ok(())) => {
+                let mut item = None;
+                source
+                    .read(store, &mut item)
+                    .context("failed to read stream item")?;
+                let item = item.context("stream item missing")?;
+                match tx.send_item(item) {
+                    Ok(()) => Poll::Ready(Ok(StreamResult::Completed)),
+                    Err(_) => Poll::Ready(Ok(StreamResult::Dropped)),
+                }
+            }
+        }
+    }
+}
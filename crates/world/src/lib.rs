@@ -14,7 +14,9 @@ wasmtime::component::bindgen!({
         include spin:up/platform@3.4.0;
         include spin:up/platform@4.0.0;
         include wasi:keyvalue/imports@0.2.0-draft2;
+        export spin:mqtt-trigger/inbound-mqtt@3.0.0;
         export spin:redis/inbound-redis@3.0.0;
+        export spin:websocket/inbound-websocket@3.0.0;
     }
     "#,
     path: "../../wit",
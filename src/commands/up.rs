@@ -7,6 +7,7 @@ use std::{
     fmt::Debug,
     path::{Path, PathBuf},
     process::Stdio,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use anyhow::{Context, Result, anyhow, bail, ensure};
@@ -37,6 +38,9 @@ const MULTI_TRIGGER_START_OFFSET: tokio::time::Duration = tokio::time::Duration:
 // any exited" check.
 const MULTI_TRIGGER_LET_ALL_START: tokio::time::Duration = tokio::time::Duration::from_millis(500);
 
+/// Set once a shutdown signal has been forwarded to the trigger processes.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
 /// Start the Spin runtime.
 // NOTE: Most of the messy clap parsing details are in the child parsing module.
 pub struct UpCommand(UpCommandInner);
@@ -283,14 +287,20 @@ impl UpCommandInner {
             tokio::time::sleep(MULTI_TRIGGER_LET_ALL_START).await;
         }
 
-        let (first_to_finish, _index, _rest) = futures::future::select_all(trigger_tasks).await;
+        let (first_to_finish, _index, rest) = futures::future::select_all(trigger_tasks).await;
+
+        let shutting_down = SHUTDOWN_REQUESTED.load(Ordering::SeqCst);
+        if shutting_down {
+            // Let the other triggers finish draining before the working directory is removed.
+            futures::future::join_all(rest).await;
+        }
 
         if let Ok(process_result) = first_to_finish {
             let status = process_result?;
             if !status.success() {
-                if is_multi {
+                if is_multi && !shutting_down {
                     println!("A trigger exited unexpectedly. Terminating.");
-                    kill_child_processes(&pids);
+                    kill_child_processes(&pids, false);
                 }
                 return Err(crate::subprocess::ExitStatusError::new(status).into());
             }
@@ -617,7 +627,10 @@ fn set_kill_on_ctrl_c(_pids: &[usize]) -> Result<(), anyhow::Error> {
 fn set_kill_on_ctrl_c(pids: &[nix::unistd::Pid]) -> Result<(), anyhow::Error> {
     let pids = pids.to_owned();
     ctrlc::set_handler(move || {
-        kill_child_processes(&pids);
+        // Forward the first signal so that triggers can shut down gracefully,
+        // and kill them if the user asks again.
+        let already_requested = SHUTDOWN_REQUESTED.swap(true, Ordering::SeqCst);
+        kill_child_processes(&pids, already_requested);
     })?;
     Ok(())
 }
@@ -638,13 +651,20 @@ fn get_pids(trigger_processes: &[tokio::process::Child]) -> Vec<nix::unistd::Pid
 }
 
 #[cfg(windows)]
-fn kill_child_processes(_pids: &[usize]) {}
+fn kill_child_processes(_pids: &[usize], _force: bool) {}
 
+/// Asks the trigger processes to shut down (SIGTERM), or kills them
+/// immediately (SIGKILL) if `force` is set.
 #[cfg(not(windows))]
-fn kill_child_processes(pids: &[nix::unistd::Pid]) {
+fn kill_child_processes(pids: &[nix::unistd::Pid], force: bool) {
+    let signal = if force {
+        nix::sys::signal::SIGKILL
+    } else {
+        nix::sys::signal::SIGTERM
+    };
     // https://github.com/nix-rust/nix/issues/656
     for pid in pids {
-        if let Err(err) = nix::sys::signal::kill(*pid, nix::sys::signal::SIGTERM) {
+        if let Err(err) = nix::sys::signal::kill(*pid, signal) {
             tracing::warn!("Failed to kill trigger handler process: {:?}", err)
         }
     }
@@ -722,7 +742,9 @@ fn trigger_commands_for_trigger_types(trigger_types: Vec<&str>) -> Result<Vec<Ve
     trigger_types
         .iter()
         .map(|&t| match t {
-            "http" | "redis" => Ok(trigger_command(t)),
+            "http" | "redis" | "mqtt-subscription" | "timer" | "cli-command" => {
+                Ok(trigger_command(t))
+            }
             _ => {
                 let cmd = resolve_trigger_plugin(t)?;
                 Ok(vec![cmd])
@@ -1007,4 +1029,21 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn passes_args_after_escape_to_trigger() {
+        let cmd = UpCommand::try_parse_from(["up", "-f", "app.wasm", "--", "-e", "--since", "1"])
+            .unwrap();
+
+        assert_eq!(cmd.0.app_source.as_deref(), Some("app.wasm"));
+        assert!(cmd.0.env.is_empty());
+
+        let ta: Vec<&str> = cmd
+            .0
+            .trigger_args
+            .iter()
+            .map(|s| s.to_str().unwrap())
+            .collect();
+        assert_eq!(ta, ["--", "-e", "--since", "1"]);
+    }
 }
@@ -85,7 +85,13 @@ fn split_args(flags: &FlagIndex, raw_args: clap_lex::RawArgs) -> (Vec<OsString>,
     let mut cursor = raw_args.cursor();
 
     while let Some(parsed) = raw_args.next(&mut cursor) {
-        if let Some((Ok(long), eq_value)) = parsed.to_long() {
+        if parsed.is_escape() {
+            // Everything after `--` is for the trigger, even if `spin up`
+            // recognizes it.
+            trigger_args.push(parsed.to_value_os().into());
+            trigger_args.extend(raw_args.remaining(&mut cursor).map(Into::into));
+            break;
+        } else if let Some((Ok(long), eq_value)) = parsed.to_long() {
             classify_long_flag(
                 long,
                 eq_value,
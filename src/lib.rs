@@ -26,8 +26,11 @@ use commands::{
 use spin_runtime_factors::FactorsBuilder;
 use spin_trigger::cli::FactorsTriggerCommand;
 use spin_trigger::cli::help::HelpArgsOnlyTrigger;
+use spin_trigger_command::{CommandExit, CommandTrigger};
 use spin_trigger_http::HttpTrigger;
+use spin_trigger_mqtt::MqttTrigger;
 use spin_trigger_redis::RedisTrigger;
+use spin_trigger_timer::TimerTrigger;
 
 pub use opts::HELP_ARGS_ONLY_TRIGGER_TYPE;
 
@@ -127,6 +130,11 @@ enum SpinApp {
 enum TriggerCommands {
     Http(FactorsTriggerCommand<HttpTrigger, FactorsBuilder>),
     Redis(FactorsTriggerCommand<RedisTrigger, FactorsBuilder>),
+    #[clap(name = "mqtt-subscription")]
+    Mqtt(FactorsTriggerCommand<MqttTrigger, FactorsBuilder>),
+    Timer(FactorsTriggerCommand<TimerTrigger, FactorsBuilder>),
+    #[clap(name = "cli-command")]
+    Command(FactorsTriggerCommand<CommandTrigger, FactorsBuilder>),
     #[clap(name = crate::HELP_ARGS_ONLY_TRIGGER_TYPE, hide = true)]
     HelpArgsOnly(FactorsTriggerCommand<HelpArgsOnlyTrigger, FactorsBuilder>),
 }
@@ -145,6 +153,17 @@ impl SpinApp {
             Self::Build(cmd) => cmd.run().await,
             Self::Trigger(TriggerCommands::Http(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::Redis(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Mqtt(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Timer(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Command(cmd)) => {
+                cmd.run()
+                    .await
+                    .map_err(|err| match err.downcast::<CommandExit>() {
+                        // Exit with the command's exit code, without reporting an error.
+                        Ok(exit) => subprocess::ExitStatusError::ExitCode(exit.code).into(),
+                        Err(err) => err,
+                    })
+            }
             Self::Trigger(TriggerCommands::HelpArgsOnly(cmd)) => cmd.run().await,
             Self::Plugins(cmd) => cmd.run().await,
             Self::External(args) => execute_external_subcommand(args, SpinApp::command()).await,